@@ -0,0 +1,97 @@
+// Method-position allocation: `value.allocate_in(&reap)` and `iter.allocated_in(&reap)`.
+
+use std::iter::FusedIterator;
+
+use super::{Reap, Rp};
+
+/// Allocation with the value in method position.
+///
+/// Implemented for every type, so that `value.allocate_in(&reap)` can be chained onto the end of
+/// a builder expression instead of wrapping it in `reap.allocate(..)`.
+///
+/// # Examples
+///
+/// ```
+/// use reap::{AllocateIn, Reap};
+///
+/// let reap = Reap::new();
+/// let name = "arena".to_uppercase().allocate_in(&reap);
+/// assert_eq!(*name, "ARENA");
+/// ```
+pub trait AllocateIn: Sized {
+    /// Allocates `self` in `reap`.
+    fn allocate_in(self, reap: &Reap<Self>) -> Rp<Self>;
+}
+
+impl<T> AllocateIn for T {
+    #[inline]
+    #[cfg_attr(any(feature = "backtrace", feature = "callsites"), track_caller)]
+    fn allocate_in(self, reap: &Reap<T>) -> Rp<T> {
+        reap.allocate(self)
+    }
+}
+
+/// An iterator adaptor allocating every item in a `Reap`.
+///
+/// # Examples
+///
+/// ```
+/// use reap::{AllocatedInExt, Reap};
+///
+/// let reap = Reap::new();
+/// let rps: Vec<_> = (0..4).map(|i| i * i).allocated_in(&reap).collect();
+/// assert_eq!(*rps[3], 9);
+/// ```
+pub trait AllocatedInExt: Iterator + Sized {
+    /// Returns an iterator allocating each item of `self` in `reap` as it is yielded.
+    #[inline]
+    fn allocated_in(self, reap: &Reap<Self::Item>) -> AllocatedIn<'_, Self> {
+        AllocatedIn {
+            iter: self,
+            reap,
+        }
+    }
+}
+
+impl<I> AllocatedInExt for I where I: Iterator {}
+
+/// An iterator yielding the items of another as `Rp`s, returned by `allocated_in`.
+///
+/// Items are only allocated as they are asked for.
+#[derive(Debug)]
+pub struct AllocatedIn<'a, I>
+    where I: Iterator + 'a,
+          I::Item: 'a
+{
+    iter: I,
+    reap: &'a Reap<I::Item>,
+}
+
+impl<'a, I> Iterator for AllocatedIn<'a, I>
+    where I: Iterator
+{
+    type Item = Rp<I::Item>;
+
+    #[inline]
+    fn next(&mut self) -> Option<Rp<I::Item>> {
+        self.iter.next().map(|item| self.reap.allocate(item))
+    }
+
+    #[inline]
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.iter.size_hint()
+    }
+}
+
+impl<'a, I> DoubleEndedIterator for AllocatedIn<'a, I>
+    where I: DoubleEndedIterator
+{
+    #[inline]
+    fn next_back(&mut self) -> Option<Rp<I::Item>> {
+        self.iter.next_back().map(|item| self.reap.allocate(item))
+    }
+}
+
+impl<'a, I> ExactSizeIterator for AllocatedIn<'a, I> where I: ExactSizeIterator {}
+
+impl<'a, I> FusedIterator for AllocatedIn<'a, I> where I: FusedIterator {}
@@ -0,0 +1,208 @@
+//! A fixed-capacity arena living entirely within its own value.
+//!
+//! A `Reap` gets its slots from the heap, one chunk at a time. A `ReapArray<T, N>` has room for
+//! exactly `N` objects, fixed at compile time, stored inline: put it on the stack or inside
+//! another value, and allocating from it never touches the heap at all. It has the same bump and
+//! freelist behaviour as a `Reap`, and handles that borrow the array instead of keeping it alive.
+//!
+//! # Examples
+//!
+//! ```
+//! use reap::array::{ArrayRp, ReapArray};
+//!
+//! let scratch: ReapArray<String, 4> = ReapArray::new();
+//! let a = scratch.allocate(String::from("a"));
+//! let mut b = scratch.allocate(String::from("b"));
+//! b.push('!');
+//! assert_eq!(scratch.len(), 2);
+//!
+//! drop(a);
+//! assert_eq!(ArrayRp::into_inner(b), "b!");
+//! assert!(scratch.is_empty());
+//! ```
+
+use std::array;
+use std::cell::{Cell, UnsafeCell};
+use std::fmt;
+use std::mem::{self, MaybeUninit};
+use std::ops::{Deref, DerefMut};
+use std::ptr;
+
+use super::Release;
+
+/// An arena with room for `N` objects of type `T`, allocated inline.
+pub struct ReapArray<T, const N: usize> {
+    slots: [UnsafeCell<MaybeUninit<T>>; N],
+    // For each slot on the freelist, the index of the next one.
+    next: [Cell<usize>; N],
+    // Head of the freelist, `N` if it is empty.
+    free: Cell<usize>,
+    // Slots from this index on have never been handed out.
+    bumped: Cell<usize>,
+    live: Cell<usize>,
+}
+
+impl<T, const N: usize> ReapArray<T, N> {
+    /// Creates a new, empty `ReapArray<T, N>`.
+    #[inline]
+    pub fn new() -> ReapArray<T, N> {
+        ReapArray {
+            slots: array::from_fn(|_| UnsafeCell::new(MaybeUninit::uninit())),
+            next: array::from_fn(|_| Cell::new(N)),
+            free: Cell::new(N),
+            bumped: Cell::new(0),
+            live: Cell::new(0),
+        }
+    }
+
+    /// Allocates `object` in the array.
+    ///
+    /// # Panics
+    ///
+    /// Panics if all `N` slots are in use.
+    #[inline]
+    pub fn allocate(&self, object: T) -> ArrayRp<'_, T, N> {
+        match self.try_allocate(object) {
+            Ok(rp) => rp,
+            Err(_) => panic!("ReapArray: all {} slots are in use", N),
+        }
+    }
+
+    /// Allocates `object` in the array, or gives it back if all `N` slots are in use.
+    pub fn try_allocate(&self, object: T) -> Result<ArrayRp<'_, T, N>, T> {
+        let index = match self.next_slot() {
+            Some(index) => index,
+            None => return Err(object),
+        };
+        self.live.set(self.live.get() + 1);
+        unsafe {
+            (*self.slots[index].get()).write(object);
+        }
+        Ok(ArrayRp {
+            array: self,
+            index,
+        })
+    }
+
+    /// Returns the number of objects the array has room for, `N`.
+    #[inline]
+    pub fn capacity(&self) -> usize {
+        N
+    }
+
+    /// Returns the number of live objects in the array.
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.live.get()
+    }
+
+    /// Returns `true` if the array holds no live objects.
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Returns `true` if every slot is in use, so that allocating would fail.
+    #[inline]
+    pub fn is_full(&self) -> bool {
+        self.len() == N
+    }
+
+    // Pops a slot off the freelist, or takes the next never used one.
+    #[inline]
+    fn next_slot(&self) -> Option<usize> {
+        let head = self.free.get();
+        if head < N {
+            self.free.set(self.next[head].get());
+            return Some(head);
+        }
+        let bumped = self.bumped.get();
+        if bumped < N {
+            self.bumped.set(bumped + 1);
+            Some(bumped)
+        } else {
+            None
+        }
+    }
+
+    // Puts the slot at `index`, whose object is gone, back on the freelist.
+    #[inline]
+    fn release(&self, index: usize) {
+        self.live.set(self.live.get() - 1);
+        self.next[index].set(self.free.get());
+        self.free.set(index);
+    }
+}
+
+impl<T, const N: usize> Default for ReapArray<T, N> {
+    #[inline]
+    fn default() -> ReapArray<T, N> {
+        ReapArray::new()
+    }
+}
+
+impl<T, const N: usize> fmt::Debug for ReapArray<T, N> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("ReapArray")
+            .field("len", &self.len())
+            .field("capacity", &N)
+            .finish()
+    }
+}
+
+/// A handle to an object allocated in a `ReapArray`.
+///
+/// The object is dropped, and its slot freed, when the handle is.
+pub struct ArrayRp<'a, T: 'a, const N: usize> {
+    array: &'a ReapArray<T, N>,
+    index: usize,
+}
+
+impl<'a, T, const N: usize> ArrayRp<'a, T, N> {
+    /// Moves the object out of the array, freeing its slot.
+    #[inline]
+    pub fn into_inner(this: ArrayRp<'a, T, N>) -> T {
+        let (array, index) = (this.array, this.index);
+        // The slot is released here instead of by the destructor, which would drop the object.
+        mem::forget(this);
+        let object = unsafe { ptr::read((*array.slots[index].get()).as_ptr()) };
+        array.release(index);
+        object
+    }
+}
+
+impl<'a, T, const N: usize> Deref for ArrayRp<'a, T, N> {
+    type Target = T;
+
+    #[inline]
+    fn deref(&self) -> &T {
+        unsafe { (*self.array.slots[self.index].get()).assume_init_ref() }
+    }
+}
+
+impl<'a, T, const N: usize> DerefMut for ArrayRp<'a, T, N> {
+    #[inline]
+    fn deref_mut(&mut self) -> &mut T {
+        unsafe { (*self.array.slots[self.index].get()).assume_init_mut() }
+    }
+}
+
+impl<'a, T, const N: usize> fmt::Debug for ArrayRp<'a, T, N>
+    where T: fmt::Debug
+{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Debug::fmt(&**self, f)
+    }
+}
+
+impl<'a, T, const N: usize> Drop for ArrayRp<'a, T, N> {
+    fn drop(&mut self) {
+        // Released even if the object's destructor panics, but only once it has finished, so a
+        // destructor allocating from the array can't be handed its own slot.
+        let (array, index) = (self.array, self.index);
+        let _release = Release::new(|| array.release(index));
+        unsafe {
+            (*self.array.slots[self.index].get()).assume_init_drop();
+        }
+    }
+}
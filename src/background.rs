@@ -0,0 +1,203 @@
+// Allocating the next chunk on a background thread, for `Reap::set_background_growth`.
+//
+// All arenas share a single worker thread, spawned with the first request and fed through a
+// channel, so growing never spawns a thread of its own.
+
+use std::alloc::{self, Layout};
+use std::cmp;
+use std::mem;
+use std::ptr;
+use std::sync::Mutex;
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::thread;
+
+use super::{alloc_failed, Chunk, Reap, Slot};
+
+// A layout to allocate, and where to send the address of the allocation, zero on failure.
+type Request = (Layout, Sender<usize>);
+
+// The worker allocating chunks for every arena, `None` until the first request.
+static WORKER: Mutex<Option<Sender<Request>>> = Mutex::new(None);
+
+// Hands `layout` to the worker, spawning it if needed, and returns where the address will arrive.
+fn submit(layout: Layout) -> Receiver<usize> {
+    let (reply, address) = mpsc::channel();
+    let mut worker = WORKER.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+    let requests = worker.get_or_insert_with(|| {
+        let (requests, incoming) = mpsc::channel::<Request>();
+        thread::spawn(move || {
+            for (layout, reply) in incoming {
+                let ptr = unsafe { alloc::alloc(layout) };
+                // Its arena is gone without waiting for it, e.g. while unwinding.
+                if reply.send(ptr as usize).is_err() && !ptr.is_null() {
+                    unsafe { alloc::dealloc(ptr, layout) };
+                }
+            }
+        });
+        requests
+    });
+    // The worker only calls the allocator, which doesn't unwind, so it never hangs up.
+    requests.send((layout, reply)).unwrap();
+    address
+}
+
+// The background growth settings of a `Reap`, and the chunk being allocated, if any.
+pub(super) struct Background {
+    // Fraction of the current chunk handed out at which the next one is requested, if enabled.
+    threshold: Option<f64>,
+    // The capacity and layout of the chunk being allocated, and where the worker sends its address.
+    pub(super) pending: Option<(usize, Layout, Receiver<usize>)>,
+}
+
+impl Background {
+    #[inline]
+    pub(super) fn new() -> Background {
+        Background {
+            threshold: None,
+            pending: None,
+        }
+    }
+
+    // Starts allocating a chunk of `capacity` elements with the given `layout`, unless one already
+    // is being allocated.
+    fn request(&mut self, capacity: usize, layout: Layout) {
+        if self.pending.is_none() {
+            self.pending = Some((capacity, layout, submit(layout)));
+        }
+    }
+
+    // Returns the chunk being allocated if it has `capacity` elements, waiting for it if needed.
+    pub(super) fn take(&mut self, capacity: usize) -> Option<Chunk> {
+        match self.pending {
+            Some((cap, _, _)) if cap == capacity => {}
+            _ => return None,
+        }
+        let (cap, layout, address) = self.pending.take().unwrap();
+        #[allow(unused_mut)]
+        let mut ptr = address.recv().unwrap_or(0) as *mut u8;
+        // Failpoints are per thread, so the chunk is only made to fail once it is taken over.
+        #[cfg(feature = "failpoints")]
+        {
+            if super::failpoint::should_fail() {
+                if !ptr.is_null() {
+                    unsafe { alloc::dealloc(ptr, layout) };
+                }
+                ptr = ptr::null_mut();
+            }
+        }
+        if ptr.is_null() {
+            alloc_failed(layout);
+        }
+        Some(Chunk {
+            ptr,
+            cap,
+            layout,
+            #[cfg(feature = "mlock")]
+            locked: false,
+        })
+    }
+}
+
+impl Drop for Background {
+    fn drop(&mut self) {
+        // A chunk requested but never needed.
+        if let Some((_, layout, address)) = self.pending.take() {
+            if let Ok(ptr) = address.recv() {
+                if ptr != 0 {
+                    unsafe { alloc::dealloc(ptr as *mut u8, layout) };
+                }
+            }
+        }
+    }
+}
+
+impl<T> Reap<T> {
+    /// Sets whether new chunks are allocated ahead of time on a background thread.
+    ///
+    /// Once `threshold`, a fraction between 0 and 1, of the current chunk's slots have been handed
+    /// out, the next chunk is requested from the system allocator on a background thread. By the
+    /// time the current chunk is used up the next one is usually ready, so allocating rarely
+    /// blocks on the allocator, only on waiting for the thread if it is still busy. With `None`,
+    /// the default, chunks are allocated when needed.
+    ///
+    /// All arenas share one background thread, spawned by the first request, which serves their
+    /// requests in turn. A chunk requested but never needed is freed along with the `Reap`.
+    /// Chunks that are `mlock`ed are always allocated when needed.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `threshold` is not between 0 and 1.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use reap::Reap;
+    ///
+    /// let reap = Reap::with_capacity(1024);
+    /// reap.set_background_growth(Some(0.75));
+    /// // The second chunk is requested at the 768th allocation, and taken over at the 1025th.
+    /// let objects: Vec<_> = (0..2000u64).map(|i| reap.allocate(i)).collect();
+    /// assert_eq!(reap.stats().chunks, 2);
+    /// # drop(objects);
+    /// ```
+    pub fn set_background_growth(&self, threshold: Option<f64>) {
+        if let Some(threshold) = threshold {
+            assert!((0.0..=1.0).contains(&threshold),
+                    "reap: background growth threshold {} is not between 0 and 1",
+                    threshold);
+        }
+        self.0.background.borrow_mut().threshold = threshold;
+        self.arm_background_growth();
+    }
+
+    /// Returns the threshold set with `set_background_growth`, if any.
+    #[inline]
+    pub fn background_growth(&self) -> Option<f64> {
+        self.0.background.borrow().threshold
+    }
+
+    // Sets the slot of the current chunk whose allocation requests the next chunk, if any.
+    pub(super) fn arm_background_growth(&self) {
+        self.0.grow_at.set(ptr::null_mut());
+        let threshold = match self.0.background.borrow().threshold {
+            Some(threshold) if mem::size_of::<T>() != 0 => threshold,
+            _ => return,
+        };
+        #[cfg(feature = "mlock")]
+        {
+            if self.0.mlock.get() {
+                return;
+            }
+        }
+        let chunks = self.0.chunks.borrow();
+        let chunk = match chunks.last() {
+            Some(chunk) => chunk,
+            None => return,
+        };
+        let index = cmp::min((chunk.capacity() as f64 * threshold).ceil() as usize,
+                             chunk.capacity() - 1);
+        let at = unsafe { chunk.start::<Slot<T>>().add(index) };
+        // Already past the threshold, e.g. when enabled late, request it with the next slot.
+        let at = cmp::max(at as *mut u8, self.0.ptr.get());
+        if at < self.0.end.get() {
+            self.0.grow_at.set(at);
+        }
+    }
+
+    // Requests the chunk to follow the current one from a background thread.
+    #[cold]
+    pub(super) fn grow_in_background(&self) {
+        let capacity = match self.0.chunks.borrow().last() {
+            Some(chunk) => chunk.capacity().checked_mul(2),
+            None => None,
+        };
+        let capacity = match capacity {
+            Some(capacity) => capacity,
+            None => return,
+        };
+        // Growing fails loudly in `grow` as usual.
+        if let Some(layout) = Chunk::layout::<Slot<T>>(capacity) {
+            self.0.background.borrow_mut().request(capacity, layout);
+        }
+    }
+}
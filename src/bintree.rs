@@ -0,0 +1,505 @@
+//! Building blocks for binary search trees whose nodes live in a `Reap`.
+//!
+//! A `BinaryTree` is a plain binary tree: each node owns its children through `Rp`s and keeps a
+//! private back link to its parent, as in `graph`. It doesn't order or balance anything by itself.
+//! Instead, its `CursorMut` offers the primitives balanced trees are made of, inserting leaves,
+//! splicing nodes out, and rotations that keep every parent link right, so that an AVL or
+//! red-black tree comes down to the bookkeeping in its values.
+
+use std::fmt;
+use std::marker;
+use std::mem;
+use std::ptr;
+
+use super::{Reap, Rp};
+
+// A single node in a `BinaryTree`.
+struct Node<T> {
+    value: T,
+    // Owning links to the children.
+    left: Option<Rp<Node<T>>>,
+    right: Option<Rp<Node<T>>>,
+    // Back link to the parent, null for the root.
+    parent: *mut Node<T>,
+}
+
+// Returns a raw pointer to the node owned by `link`, or null.
+//
+// Taken straight from the `Rp`'s own pointer, for the same reason as in `dlist`: the back links
+// must stay valid across mutable borrows of the node through its `Rp`.
+#[inline]
+fn as_ptr<T>(link: &Option<Rp<Node<T>>>) -> *mut Node<T> {
+    match *link {
+        Some(ref node) => node.ptr.as_ptr(),
+        None => ptr::null_mut(),
+    }
+}
+
+// Returns the link to the left child of `node`, or to its right one.
+#[inline]
+unsafe fn child_link<T>(node: *mut Node<T>, left: bool) -> *mut Option<Rp<Node<T>>> {
+    if left {
+        ptr::addr_of_mut!((*node).left)
+    } else {
+        ptr::addr_of_mut!((*node).right)
+    }
+}
+
+// Returns the leftmost node of the subtree at `node`, which must not be null.
+#[inline]
+unsafe fn leftmost<T>(mut node: *mut Node<T>) -> *mut Node<T> {
+    loop {
+        match as_ptr(&(*node).left) {
+            left if left.is_null() => return node,
+            left => node = left,
+        }
+    }
+}
+
+/// A binary tree with arena-allocated nodes.
+///
+/// Iterating goes in order, left subtree first, so a tree kept in search order by its user
+/// iterates in the order of its keys.
+///
+/// # Examples
+///
+/// ```
+/// use reap::bintree::BinaryTree;
+///
+/// let mut tree = BinaryTree::new();
+/// tree.insert_root(1).unwrap();
+/// {
+///     let mut cursor = tree.cursor_mut();
+///     cursor.insert_right(2).unwrap();
+///     cursor.move_to_right();
+///     cursor.insert_right(3).unwrap();
+///
+///     // 1 ( _ 2 ( _ 3 ) ) becomes 2 ( 1 3 ).
+///     cursor.move_to_root();
+///     assert!(cursor.rotate_left());
+///     assert_eq!(cursor.peek_parent(), Some(&2));
+/// }
+/// assert_eq!(tree.root(), Some(&2));
+/// assert_eq!(tree.iter().cloned().collect::<Vec<_>>(), vec![1, 2, 3]);
+/// ```
+pub struct BinaryTree<T> {
+    reap: Reap<Node<T>>,
+    root: Option<Rp<Node<T>>>,
+    len: usize,
+}
+
+impl<T> BinaryTree<T> {
+    /// Creates an empty `BinaryTree<T>`.
+    #[inline]
+    pub fn new() -> BinaryTree<T> {
+        BinaryTree {
+            reap: Reap::new(),
+            root: None,
+            len: 0,
+        }
+    }
+
+    /// Returns the number of nodes in the tree.
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Returns `true` if the tree contains no nodes.
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Returns a reference to the value of the root, if any.
+    #[inline]
+    pub fn root(&self) -> Option<&T> {
+        self.root.as_ref().map(|node| &node.value)
+    }
+
+    /// Makes a node holding `value` the root of an empty tree, or returns `value` back if the tree
+    /// isn't empty.
+    #[inline]
+    pub fn insert_root(&mut self, value: T) -> Result<(), T> {
+        if self.root.is_some() {
+            return Err(value);
+        }
+        self.root = Some(self.leaf(ptr::null_mut(), value));
+        Ok(())
+    }
+
+    /// Returns an in-order iterator over references to the values.
+    #[inline]
+    pub fn iter(&self) -> Iter<'_, T> {
+        let root = as_ptr(&self.root);
+        Iter {
+            node: if root.is_null() {
+                root
+            } else {
+                unsafe { leftmost(root) }
+            },
+            len: self.len,
+            _marker: marker::PhantomData,
+        }
+    }
+
+    /// Returns a cursor positioned at the root.
+    ///
+    /// If the tree is empty the cursor starts at the "ghost" position above the root.
+    #[inline]
+    pub fn cursor_mut(&mut self) -> CursorMut<'_, T> {
+        let current = as_ptr(&self.root);
+        CursorMut {
+            tree: self,
+            current,
+        }
+    }
+
+    // Allocates a leaf holding `value`, to be linked in as a child of `parent`.
+    fn leaf(&mut self, parent: *mut Node<T>, value: T) -> Rp<Node<T>> {
+        self.len += 1;
+        self.reap.allocate(Node {
+            value,
+            left: None,
+            right: None,
+            parent,
+        })
+    }
+
+    // Returns the link owning `node`: the root link, or one of its parent's child links.
+    //
+    // `node` must be a non-null node of this tree.
+    unsafe fn owner(&mut self, node: *mut Node<T>) -> *mut Option<Rp<Node<T>>> {
+        let parent = (*node).parent;
+        if parent.is_null() {
+            &mut self.root as *mut _
+        } else if as_ptr(&(*parent).left) == node {
+            ptr::addr_of_mut!((*parent).left)
+        } else {
+            ptr::addr_of_mut!((*parent).right)
+        }
+    }
+
+    // Rotates `node` left, lifting its right child into its place, or right, lifting its left
+    // child, returning whether there was such a child.
+    //
+    // `node` must be a non-null node of this tree.
+    unsafe fn rotate(&mut self, node: *mut Node<T>, left: bool) -> bool {
+        // The lifted child's inner subtree moves across to `node`.
+        let lifted = child_link(node, !left);
+        let child = match (*lifted).take() {
+            Some(child) => child,
+            None => return false,
+        };
+        let child_ptr = child.ptr.as_ptr();
+        *lifted = (*child_link(child_ptr, left)).take();
+        let moved = as_ptr(&*lifted);
+        if !moved.is_null() {
+            (*moved).parent = node;
+        }
+        let owner = self.owner(node);
+        let this = (*owner).take();
+        (*child_ptr).parent = (*node).parent;
+        (*node).parent = child_ptr;
+        *child_link(child_ptr, left) = this;
+        *owner = Some(child);
+        true
+    }
+
+    // Removes `node`, which must have at most one child, splicing that child into its place and
+    // returning its value.
+    //
+    // `node` must be a non-null node of this tree.
+    unsafe fn splice(&mut self, node: *mut Node<T>) -> T {
+        let child = match (*node).left.take() {
+            Some(left) => Some(left),
+            None => (*node).right.take(),
+        };
+        let child_ptr = as_ptr(&child);
+        if !child_ptr.is_null() {
+            (*child_ptr).parent = (*node).parent;
+        }
+        let owner = self.owner(node);
+        let this = (*owner).take().expect("linked node without an owner");
+        *owner = child;
+        self.len -= 1;
+        Rp::take(this).value
+    }
+}
+
+impl<T> Drop for BinaryTree<T> {
+    fn drop(&mut self) {
+        // Rotate left children up until the root has none, then drop it and carry on with its
+        // right subtree: letting the drop glue recurse would blow the stack on degenerate trees.
+        let mut root = self.root.take();
+        while let Some(mut node) = root {
+            root = match node.left.take() {
+                Some(mut left) => {
+                    node.left = left.right.take();
+                    left.right = Some(node);
+                    Some(left)
+                }
+                None => node.right.take(),
+            };
+        }
+    }
+}
+
+impl<T> Default for BinaryTree<T> {
+    #[inline]
+    fn default() -> BinaryTree<T> {
+        BinaryTree::new()
+    }
+}
+
+impl<T> fmt::Debug for BinaryTree<T>
+    where T: fmt::Debug
+{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_list().entries(self.iter()).finish()
+    }
+}
+
+impl<'a, T> IntoIterator for &'a BinaryTree<T> {
+    type Item = &'a T;
+    type IntoIter = Iter<'a, T>;
+
+    #[inline]
+    fn into_iter(self) -> Iter<'a, T> {
+        self.iter()
+    }
+}
+
+/// An in-order iterator over references to the values of a `BinaryTree`.
+pub struct Iter<'a, T: 'a> {
+    node: *mut Node<T>,
+    len: usize,
+    _marker: marker::PhantomData<&'a T>,
+}
+
+impl<'a, T> Iterator for Iter<'a, T> {
+    type Item = &'a T;
+
+    #[inline]
+    fn next(&mut self) -> Option<&'a T> {
+        if self.node.is_null() {
+            return None;
+        }
+        unsafe {
+            let node = self.node;
+            // The leftmost node right of this one, or else the first ancestor this one is left of.
+            self.node = match as_ptr(&(*node).right) {
+                right if !right.is_null() => leftmost(right),
+                _ => {
+                    let mut child = node;
+                    let mut parent = (*node).parent;
+                    while !parent.is_null() && as_ptr(&(*parent).right) == child {
+                        child = parent;
+                        parent = (*parent).parent;
+                    }
+                    parent
+                }
+            };
+            self.len -= 1;
+            Some(&(*node).value)
+        }
+    }
+
+    #[inline]
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.len, Some(self.len))
+    }
+}
+
+impl<'a, T> ExactSizeIterator for Iter<'a, T> {}
+
+/// A cursor over a `BinaryTree` that can insert, remove and rotate nodes.
+///
+/// A cursor always rests either on a node or on the "ghost" position above the root.
+pub struct CursorMut<'a, T: 'a> {
+    tree: &'a mut BinaryTree<T>,
+    // Null for the ghost position.
+    current: *mut Node<T>,
+}
+
+impl<'a, T> CursorMut<'a, T> {
+    /// Returns a mutable reference to the value under the cursor, or `None` at the ghost
+    /// position.
+    #[inline]
+    pub fn current(&mut self) -> Option<&mut T> {
+        if self.current.is_null() {
+            None
+        } else {
+            unsafe { Some(&mut (*self.current).value) }
+        }
+    }
+
+    // Returns a reference to the value of `node`, or `None` if it is null.
+    #[inline]
+    fn peek(&self, node: *mut Node<T>) -> Option<&T> {
+        if node.is_null() {
+            None
+        } else {
+            unsafe { Some(&(*node).value) }
+        }
+    }
+
+    // Returns the child of the node under the cursor on the given side, null at the ghost.
+    #[inline]
+    fn child(&self, left: bool) -> *mut Node<T> {
+        if self.current.is_null() {
+            ptr::null_mut()
+        } else {
+            unsafe { as_ptr(&*child_link(self.current, left)) }
+        }
+    }
+
+    // Returns the parent of the node under the cursor, null at the root and at the ghost.
+    #[inline]
+    fn parent(&self) -> *mut Node<T> {
+        if self.current.is_null() {
+            ptr::null_mut()
+        } else {
+            unsafe { (*self.current).parent }
+        }
+    }
+
+    /// Returns a reference to the value of the left child of the node under the cursor, if any.
+    #[inline]
+    pub fn peek_left(&self) -> Option<&T> {
+        self.peek(self.child(true))
+    }
+
+    /// Returns a reference to the value of the right child of the node under the cursor, if any.
+    #[inline]
+    pub fn peek_right(&self) -> Option<&T> {
+        self.peek(self.child(false))
+    }
+
+    /// Returns a reference to the value of the parent of the node under the cursor, if any.
+    #[inline]
+    pub fn peek_parent(&self) -> Option<&T> {
+        self.peek(self.parent())
+    }
+
+    /// Returns `true` if the node under the cursor is the left child of its parent.
+    #[inline]
+    pub fn is_left_child(&self) -> bool {
+        let parent = self.parent();
+        !parent.is_null() && unsafe { as_ptr(&(*parent).left) } == self.current
+    }
+
+    // Moves the cursor to `node` unless it is null, returning whether it moved.
+    #[inline]
+    fn move_to(&mut self, node: *mut Node<T>) -> bool {
+        if node.is_null() {
+            false
+        } else {
+            self.current = node;
+            true
+        }
+    }
+
+    /// Moves the cursor to the root, or to the ghost position if the tree is empty.
+    #[inline]
+    pub fn move_to_root(&mut self) {
+        self.current = as_ptr(&self.tree.root);
+    }
+
+    /// Moves the cursor to the left child of the node under it, returning `false` and staying put
+    /// if there is none.
+    #[inline]
+    pub fn move_to_left(&mut self) -> bool {
+        let left = self.child(true);
+        self.move_to(left)
+    }
+
+    /// Moves the cursor to the right child of the node under it, returning `false` and staying put
+    /// if there is none.
+    #[inline]
+    pub fn move_to_right(&mut self) -> bool {
+        let right = self.child(false);
+        self.move_to(right)
+    }
+
+    /// Moves the cursor to the parent of the node under it, returning `false` and staying put at
+    /// the root or the ghost position.
+    #[inline]
+    pub fn move_to_parent(&mut self) -> bool {
+        let parent = self.parent();
+        self.move_to(parent)
+    }
+
+    // Links a new leaf holding `value` as the child on the given side of the node under the
+    // cursor, or returns `value` back if there already is one.
+    fn insert(&mut self, left: bool, value: T) -> Result<(), T> {
+        if self.current.is_null() || !self.child(left).is_null() {
+            return Err(value);
+        }
+        let leaf = self.tree.leaf(self.current, value);
+        unsafe { *child_link(self.current, left) = Some(leaf) };
+        Ok(())
+    }
+
+    /// Adds a leaf holding `value` as the left child of the node under the cursor, which stays
+    /// where it is.
+    ///
+    /// Returns `value` back if there already is a left child, or at the ghost position.
+    #[inline]
+    pub fn insert_left(&mut self, value: T) -> Result<(), T> {
+        self.insert(true, value)
+    }
+
+    /// Adds a leaf holding `value` as the right child of the node under the cursor, which stays
+    /// where it is.
+    ///
+    /// Returns `value` back if there already is a right child, or at the ghost position.
+    #[inline]
+    pub fn insert_right(&mut self, value: T) -> Result<(), T> {
+        self.insert(false, value)
+    }
+
+    /// Rotates the right child of the node under the cursor up into its place, returning `false`
+    /// and doing nothing if there is none.
+    ///
+    /// The order of the nodes is unchanged. The cursor stays on the same node, which becomes the
+    /// left child of its former right child.
+    #[inline]
+    pub fn rotate_left(&mut self) -> bool {
+        !self.current.is_null() && unsafe { self.tree.rotate(self.current, true) }
+    }
+
+    /// Rotates the left child of the node under the cursor up into its place, returning `false`
+    /// and doing nothing if there is none.
+    ///
+    /// The order of the nodes is unchanged. The cursor stays on the same node, which becomes the
+    /// right child of its former left child.
+    #[inline]
+    pub fn rotate_right(&mut self) -> bool {
+        !self.current.is_null() && unsafe { self.tree.rotate(self.current, false) }
+    }
+
+    /// Removes the value under the cursor and returns it, keeping the order of the others.
+    ///
+    /// A node with at most one child is replaced by that child. A node with two takes on the value
+    /// of its in-order successor instead, whose node is removed in its place. Either way the
+    /// cursor moves to the parent of the node actually removed, where rebalancing would start.
+    ///
+    /// Returns `None` and does nothing at the ghost position.
+    pub fn remove_current(&mut self) -> Option<T> {
+        if self.current.is_null() {
+            return None;
+        }
+        unsafe {
+            let mut node = self.current;
+            let right = as_ptr(&(*node).right);
+            if (*node).left.is_some() && !right.is_null() {
+                let successor = leftmost(right);
+                mem::swap(&mut (*node).value, &mut (*successor).value);
+                node = successor;
+            }
+            self.current = (*node).parent;
+            Some(self.tree.splice(node))
+        }
+    }
+}
@@ -0,0 +1,259 @@
+//! An arena branded with a lifetime, handing out plain references.
+//!
+//! Every `Rp` carries a pointer to its arena and gives its slot back when dropped. Code that can
+//! do all of its work inside one closure can skip that: `Reap::with` creates a `BrandedReap` whose
+//! objects all live until the closure returns, and hands them out as plain `&'brand mut T`. The
+//! `'brand` lifetime is fresh for every call and can't escape the closure, so the compiler checks
+//! that no reference outlives the arena, with no state per handle at all.
+//!
+//! Along with the arena the closure gets the `Token` of its brand, the single key to every
+//! `BrandCell` of that brand: objects can be shared freely as `&'brand BrandCell<'brand, T>`, and
+//! whoever holds the token mutably may mutate any of them, GhostCell-style, without a borrow flag
+//! per object.
+//!
+//! # Examples
+//!
+//! ```
+//! use reap::Reap;
+//!
+//! let total = Reap::with(|reap, mut token| {
+//!     let a = reap.allocate(1);
+//!     let b = reap.allocate(2);
+//!     *a += *b;
+//!
+//!     // Shared, but still mutable through the token.
+//!     let counter = reap.allocate_cell(0);
+//!     let (left, right) = (counter, counter);
+//!     *left.borrow_mut(&mut token) += *a;
+//!     *right.borrow_mut(&mut token) += *b;
+//!     *counter.borrow(&token)
+//! });
+//! assert_eq!(total, 5);
+//! ```
+//!
+//! References don't outlive the closure:
+//!
+//! ```compile_fail
+//! use reap::Reap;
+//!
+//! let escaped = Reap::with(|reap, _| reap.allocate(String::from("gone")));
+//! ```
+//!
+//! And a token only opens the cells of its own brand:
+//!
+//! ```compile_fail
+//! use reap::Reap;
+//!
+//! Reap::with(|outer, _| {
+//!     let cell = outer.allocate_cell(0);
+//!     Reap::<i32>::with(|_, mut token| {
+//!         *cell.borrow_mut(&mut token) = 1;
+//!     });
+//! });
+//! ```
+
+use std::cell::{Cell, RefCell, UnsafeCell};
+use std::fmt;
+use std::marker::PhantomData;
+use std::mem;
+use std::ptr;
+
+use super::Reap;
+use super::extend::Extents;
+
+// Invariant in `'brand`, so that one brand can never be coerced into another.
+type Brand<'brand> = PhantomData<fn(&'brand ()) -> &'brand ()>;
+
+/// An arena whose objects live as long as the `'brand` of the `Reap::with` call that created it.
+///
+/// Unlike a `Reap`, this arena drops its objects itself, so it is invariant in `T`: it can't be
+/// coerced into an arena of shorter-lived objects, which it would drop after they expired.
+///
+/// ```compile_fail
+/// use reap::brand::BrandedReap;
+///
+/// fn shorten<'b, 'a: 'b>(reap: &'b BrandedReap<'b, &'static str>)
+///                        -> &'b BrandedReap<'b, &'a str> {
+///     reap
+/// }
+/// ```
+pub struct BrandedReap<'brand, T> {
+    arena: Arena<T>,
+    _brand: Brand<'brand>,
+}
+
+// The objects of a `BrandedReap`, apart from the brand, which their destructor can't outlive.
+struct Arena<T> {
+    reap: Reap<T>,
+    // The slot of every object from `allocate`, dropped in order along with the arena.
+    objects: RefCell<Vec<*mut T>>,
+    // Objects from `alloc_extend`.
+    extents: RefCell<Extents>,
+    _invariant: PhantomData<Cell<T>>,
+}
+
+/// The unique key to the `BrandCell`s of a brand, passed to the closure of `Reap::with`.
+///
+/// There is exactly one token per brand, and it can't be cloned, so borrowing it mutably proves
+/// that no other borrow of any cell of the brand exists.
+pub struct Token<'brand> {
+    _brand: Brand<'brand>,
+}
+
+impl<T> Reap<T> {
+    /// Calls `f` with a fresh branded arena and the `Token` of its brand, returning its result.
+    ///
+    /// The arena is dropped, along with every object allocated in it, when `f` returns.
+    ///
+    /// The type of the objects is chosen outside of `f`, so it can't mention `'brand` itself:
+    /// objects can't hold branded references to each other, only the code in `f` can.
+    pub fn with<F, R>(f: F) -> R
+        where F: for<'brand> FnOnce(&'brand BrandedReap<'brand, T>, Token<'brand>) -> R
+    {
+        let reap = BrandedReap {
+            arena: Arena {
+                reap: Reap::new(),
+                objects: RefCell::new(Vec::new()),
+                extents: RefCell::new(Extents::new()),
+                _invariant: PhantomData,
+            },
+            _brand: PhantomData,
+        };
+        f(&reap, Token { _brand: PhantomData })
+    }
+}
+
+impl<'brand, T> BrandedReap<'brand, T> {
+    /// Allocates `object`, returning a reference valid until the closure of `Reap::with` returns.
+    #[inline]
+    #[allow(clippy::mut_from_ref)]
+    #[cfg_attr(any(feature = "backtrace", feature = "callsites"), track_caller)]
+    pub fn allocate(&'brand self, object: T) -> &'brand mut T {
+        // A regular slot, whose `Rp` is the arena itself.
+        let ptr = self.arena.reap.reserve();
+        unsafe {
+            ptr::write(ptr, object);
+        }
+        #[cfg(any(feature = "backtrace", feature = "callsites"))]
+        self.arena.reap.track(ptr);
+        self.arena.objects.borrow_mut().push(ptr);
+        // The slot is only given back when the arena is dropped.
+        unsafe { &mut *ptr }
+    }
+
+    /// Allocates `object` in a `BrandCell`, to be shared and mutated through the `Token`.
+    #[inline]
+    #[cfg_attr(any(feature = "backtrace", feature = "callsites"), track_caller)]
+    pub fn allocate_cell(&'brand self, object: T) -> &'brand BrandCell<'brand, T> {
+        BrandCell::from_mut(self.allocate(object))
+    }
+
+    /// Allocates every item of `iter` contiguously, returning them as a slice.
+    ///
+    /// `iter` may itself allocate from this arena.
+    #[inline]
+    #[allow(clippy::mut_from_ref)]
+    pub fn alloc_extend<I>(&'brand self, iter: I) -> &'brand mut [T]
+        where I: IntoIterator<Item = T>
+    {
+        // The extents are only dropped along with the arena.
+        unsafe { &mut *Extents::extend(&self.arena.extents, iter) }
+    }
+}
+
+impl<T> Drop for Arena<T> {
+    fn drop(&mut self) {
+        // Taken out first, so that a panicking destructor leaks the rest rather than dropping
+        // any twice.
+        for ptr in mem::take(self.objects.get_mut()) {
+            self.reap.deallocate(ptr);
+        }
+        unsafe {
+            self.extents.get_mut().drop_objects::<T>();
+        }
+    }
+}
+
+impl<'brand, T> fmt::Debug for BrandedReap<'brand, T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("BrandedReap").finish_non_exhaustive()
+    }
+}
+
+impl<'brand> fmt::Debug for Token<'brand> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("Token")
+    }
+}
+
+/// A value that can only be accessed through the `Token` of its brand.
+///
+/// Reading takes a shared borrow of the token, and writing a mutable one, so the borrow checker
+/// enforces the rules of `RefCell` on all the cells of a brand at once, at compile time.
+#[repr(transparent)]
+pub struct BrandCell<'brand, T: ?Sized> {
+    _brand: Brand<'brand>,
+    value: UnsafeCell<T>,
+}
+
+impl<'brand, T> BrandCell<'brand, T> {
+    /// Creates a new cell of brand `'brand` holding `value`.
+    #[inline]
+    pub fn new(value: T) -> BrandCell<'brand, T> {
+        BrandCell {
+            _brand: PhantomData,
+            value: UnsafeCell::new(value),
+        }
+    }
+
+    /// Consumes the cell, returning its value.
+    #[inline]
+    pub fn into_inner(self) -> T {
+        self.value.into_inner()
+    }
+}
+
+impl<'brand, T: ?Sized> BrandCell<'brand, T> {
+    /// Returns a shared reference to the value, for as long as the token is borrowed.
+    #[inline]
+    pub fn borrow<'a>(&'a self, _token: &'a Token<'brand>) -> &'a T {
+        unsafe { &*self.value.get() }
+    }
+
+    /// Returns a mutable reference to the value, for as long as the token is mutably borrowed.
+    #[inline]
+    #[allow(clippy::mut_from_ref)]
+    pub fn borrow_mut<'a>(&'a self, _token: &'a mut Token<'brand>) -> &'a mut T {
+        unsafe { &mut *self.value.get() }
+    }
+
+    /// Returns a mutable reference to the value, which the mutable borrow of the cell makes
+    /// unique without the token.
+    #[inline]
+    pub fn get_mut(&mut self) -> &mut T {
+        self.value.get_mut()
+    }
+
+    /// Turns a mutable reference into a shared reference to a cell.
+    #[inline]
+    pub fn from_mut(value: &mut T) -> &BrandCell<'brand, T> {
+        // `BrandCell<T>` is `repr(transparent)` over `UnsafeCell<T>`, which has the layout of `T`.
+        unsafe { &*(value as *mut T as *const BrandCell<'brand, T>) }
+    }
+}
+
+impl<'brand, T> Default for BrandCell<'brand, T>
+    where T: Default
+{
+    #[inline]
+    fn default() -> BrandCell<'brand, T> {
+        BrandCell::new(T::default())
+    }
+}
+
+impl<'brand, T: ?Sized> fmt::Debug for BrandCell<'brand, T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        // The value can't be read without the token.
+        f.debug_struct("BrandCell").finish_non_exhaustive()
+    }
+}
@@ -0,0 +1,226 @@
+//! Memory budgets for categories of allocations.
+//!
+//! A program made of subsystems often wants each to stay within its share of memory: the UI gets
+//! 64 MB, gameplay gets 256 MB. A `Budget` is a category that allocations are charged to, from any
+//! number of `Reap`s of any types, with optional caps on the objects and bytes charged to it at
+//! once. Allocating through a budget that is full fails, rather than relying on convention, and
+//! usage can be queried at any time.
+//!
+//! Bytes are counted in slots, as in `ReapStats::slot_size`, so zero-sized types only count
+//! towards the object cap.
+//!
+//! # Examples
+//!
+//! ```
+//! use reap::Reap;
+//! use reap::budget::Budget;
+//!
+//! let ui = Budget::new();
+//! ui.set_max_objects(Some(2));
+//!
+//! let widgets = Reap::new();
+//! let button = ui.try_allocate(&widgets, "button").unwrap();
+//! let label = ui.try_allocate(&widgets, "label").unwrap();
+//! assert_eq!(ui.try_allocate(&widgets, "slider").unwrap_err(), "slider");
+//!
+//! drop(button);
+//! assert_eq!(ui.objects(), 1);
+//! assert!(ui.try_allocate(&widgets, "slider").is_ok());
+//! # drop(label);
+//! ```
+
+use std::cell::Cell;
+use std::fmt;
+use std::mem;
+use std::ops::{Deref, DerefMut};
+use std::rc::Rc;
+
+use super::{Reap, Rp, Slot};
+
+/// A category of allocations, with optional caps on its usage.
+///
+/// Cloning a `Budget` produces another handle to the same category.
+#[derive(Clone)]
+pub struct Budget(Rc<InnerBudget>);
+
+struct InnerBudget {
+    max_objects: Cell<Option<usize>>,
+    max_bytes: Cell<Option<usize>>,
+    // Usage by the live objects charged to the budget.
+    objects: Cell<usize>,
+    bytes: Cell<usize>,
+}
+
+impl Budget {
+    /// Creates a new budget, without any caps.
+    #[inline]
+    pub fn new() -> Budget {
+        Budget(Rc::new(InnerBudget {
+            max_objects: Cell::new(None),
+            max_bytes: Cell::new(None),
+            objects: Cell::new(0),
+            bytes: Cell::new(0),
+        }))
+    }
+
+    /// Sets the most objects that can be charged to this budget at once, or `None` for no cap.
+    ///
+    /// Lowering the cap below the current usage doesn't free anything, it only makes allocations
+    /// fail until enough objects are dropped.
+    #[inline]
+    pub fn set_max_objects(&self, max: Option<usize>) {
+        self.0.max_objects.set(max);
+    }
+
+    /// Sets the most bytes that can be charged to this budget at once, or `None` for no cap.
+    ///
+    /// Like `set_max_objects`, this only affects future allocations.
+    #[inline]
+    pub fn set_max_bytes(&self, max: Option<usize>) {
+        self.0.max_bytes.set(max);
+    }
+
+    /// Returns the cap on the number of objects, if any.
+    #[inline]
+    pub fn max_objects(&self) -> Option<usize> {
+        self.0.max_objects.get()
+    }
+
+    /// Returns the cap on the number of bytes, if any.
+    #[inline]
+    pub fn max_bytes(&self) -> Option<usize> {
+        self.0.max_bytes.get()
+    }
+
+    /// Returns the number of live objects charged to this budget.
+    #[inline]
+    pub fn objects(&self) -> usize {
+        self.0.objects.get()
+    }
+
+    /// Returns the number of bytes taken by the live objects charged to this budget.
+    #[inline]
+    pub fn bytes(&self) -> usize {
+        self.0.bytes.get()
+    }
+
+    /// Allocates `object` from `reap`, charged to this budget.
+    ///
+    /// Returns the object back if charging it would exceed either cap.
+    #[cfg_attr(any(feature = "backtrace", feature = "callsites"), track_caller)]
+    pub fn try_allocate<T>(&self, reap: &Reap<T>, object: T) -> Result<Budgeted<T>, T> {
+        let size = slot_size::<T>();
+        let objects = self.0.objects.get() + 1;
+        let bytes = self.0.bytes.get() + size;
+        if self.0.max_objects.get().is_some_and(|max| objects > max) ||
+           self.0.max_bytes.get().is_some_and(|max| bytes > max) {
+            return Err(object);
+        }
+        // Charged only once the allocation went through, so one that panics leaves the budget as
+        // it was.
+        let rp = reap.allocate(object);
+        self.0.objects.set(objects);
+        self.0.bytes.set(bytes);
+        Ok(Budgeted {
+            rp: Some(rp),
+            budget: self.clone(),
+        })
+    }
+
+    // Takes an object of type `T` off the books.
+    #[inline]
+    fn uncharge<T>(&self) {
+        self.0.objects.set(self.0.objects.get() - 1);
+        self.0.bytes.set(self.0.bytes.get() - slot_size::<T>());
+    }
+}
+
+impl Default for Budget {
+    #[inline]
+    fn default() -> Budget {
+        Budget::new()
+    }
+}
+
+impl fmt::Debug for Budget {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Budget")
+            .field("objects", &self.objects())
+            .field("max_objects", &self.max_objects())
+            .field("bytes", &self.bytes())
+            .field("max_bytes", &self.max_bytes())
+            .finish()
+    }
+}
+
+// Returns the number of bytes an object of type `T` is charged for.
+#[inline]
+fn slot_size<T>() -> usize {
+    if mem::size_of::<T>() == 0 {
+        0
+    } else {
+        mem::size_of::<Slot<T>>()
+    }
+}
+
+/// An owning handle to an object charged to a `Budget`, returned by `Budget::try_allocate`.
+///
+/// The charge is lifted when the handle is dropped, or converted with `into_inner` or `into_rp`.
+pub struct Budgeted<T> {
+    // Only `None` once converted.
+    rp: Option<Rp<T>>,
+    budget: Budget,
+}
+
+impl<T> Budgeted<T> {
+    /// Returns a reference to the budget the object is charged to.
+    #[inline]
+    pub fn budget(this: &Budgeted<T>) -> &Budget {
+        &this.budget
+    }
+
+    /// Moves the object out of its slot, lifting the charge.
+    #[inline]
+    pub fn into_inner(this: Budgeted<T>) -> T {
+        Rp::take(Budgeted::into_rp(this))
+    }
+
+    /// Converts this handle into a plain `Rp<T>`, no longer charged to the budget.
+    #[inline]
+    pub fn into_rp(mut this: Budgeted<T>) -> Rp<T> {
+        this.budget.uncharge::<T>();
+        this.rp.take().unwrap()
+    }
+}
+
+impl<T> Drop for Budgeted<T> {
+    fn drop(&mut self) {
+        if self.rp.is_some() {
+            self.budget.uncharge::<T>();
+        }
+    }
+}
+
+impl<T> Deref for Budgeted<T> {
+    type Target = T;
+
+    #[inline]
+    fn deref(&self) -> &T {
+        self.rp.as_ref().unwrap()
+    }
+}
+
+impl<T> DerefMut for Budgeted<T> {
+    #[inline]
+    fn deref_mut(&mut self) -> &mut T {
+        self.rp.as_mut().unwrap()
+    }
+}
+
+impl<T> fmt::Debug for Budgeted<T>
+    where T: fmt::Debug
+{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Debug::fmt(&**self, f)
+    }
+}
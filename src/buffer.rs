@@ -0,0 +1,223 @@
+//! A recycling pool of byte buffers.
+//!
+//! `BufferReap` hands out `Vec<u8>`-like buffers that return to the pool when dropped, so
+//! services that churn through short-lived buffers keep reusing the same warm allocations. Idle
+//! buffers are bucketed by power-of-two size class, and the total capacity held idle by the pool
+//! is capped.
+
+use std::cell::{Cell, RefCell};
+use std::cmp;
+use std::fmt;
+use std::mem;
+use std::ops::{Deref, DerefMut};
+use std::rc::Rc;
+
+// Smallest buffer capacity the pool hands out.
+const MIN_CLASS: u32 = 6;
+
+// Default cap on the total capacity of idle buffers, in bytes.
+const DEFAULT_MAX_RETAINED: usize = 16 * 1024 * 1024;
+
+// Returns the size class whose buffers are all at least `capacity` bytes.
+#[inline]
+fn class_for_request(capacity: usize) -> u32 {
+    let rounded = capacity.checked_next_power_of_two().expect("capacity overflow");
+    cmp::max(rounded.trailing_zeros(), MIN_CLASS)
+}
+
+// Returns the size class a buffer with the given `capacity` can serve.
+#[inline]
+fn class_for_buffer(capacity: usize) -> u32 {
+    // `capacity` is non-zero for any buffer handed out by the pool.
+    (usize::BITS - 1) - capacity.leading_zeros()
+}
+
+/// A pool of reusable byte buffers.
+///
+/// Cloning a `BufferReap` produces another handle to the same pool.
+///
+/// # Examples
+///
+/// ```
+/// use reap::buffer::BufferReap;
+///
+/// let pool = BufferReap::new();
+///
+/// let mut buf = pool.get(1000);
+/// buf.extend_from_slice(b"hello");
+/// let addr = buf.as_ptr();
+/// drop(buf);
+///
+/// // The same allocation comes back, cleared.
+/// let buf = pool.get(500);
+/// assert!(buf.is_empty());
+/// assert_eq!(buf.as_ptr(), addr);
+/// ```
+#[derive(Clone)]
+pub struct BufferReap(Rc<InnerBufferReap>);
+
+struct InnerBufferReap {
+    // Idle buffers, `buckets[c]` holds buffers with a capacity in `[2^c, 2^(c+1))`.
+    buckets: RefCell<Vec<Vec<Vec<u8>>>>,
+    // Total capacity of all idle buffers.
+    retained: Cell<usize>,
+    // Cap on `retained`, buffers that would exceed it are freed instead of pooled.
+    max_retained: usize,
+}
+
+impl BufferReap {
+    /// Creates a new pool retaining at most 16 MiB of idle buffers.
+    #[inline]
+    pub fn new() -> BufferReap {
+        BufferReap::with_max_retained(DEFAULT_MAX_RETAINED)
+    }
+
+    /// Creates a new pool retaining at most `max_retained` bytes of idle buffer capacity.
+    #[inline]
+    pub fn with_max_retained(max_retained: usize) -> BufferReap {
+        BufferReap(Rc::new(InnerBufferReap {
+            buckets: RefCell::new(Vec::new()),
+            retained: Cell::new(0),
+            max_retained,
+        }))
+    }
+
+    /// Returns an empty buffer with a capacity of at least `min_capacity` bytes.
+    ///
+    /// An idle buffer is reused if one is large enough, otherwise a new one is allocated with the
+    /// capacity rounded up to its size class.
+    pub fn get(&self, min_capacity: usize) -> Buffer {
+        let class = class_for_request(min_capacity);
+        let reused = {
+            let mut buckets = self.0.buckets.borrow_mut();
+            // Any buffer in this class or above is large enough.
+            buckets.iter_mut().skip(class as usize).filter_map(|bucket| bucket.pop()).next()
+        };
+        let buf = match reused {
+            Some(buf) => {
+                self.0.retained.set(self.0.retained.get() - buf.capacity());
+                buf
+            }
+            None => Vec::with_capacity(1 << class),
+        };
+        Buffer {
+            buf,
+            pool: self.clone(),
+        }
+    }
+
+    /// Returns the number of idle buffers held by the pool.
+    #[inline]
+    pub fn idle(&self) -> usize {
+        self.0.buckets.borrow().iter().map(Vec::len).sum()
+    }
+
+    /// Returns the total capacity of the idle buffers held by the pool, in bytes.
+    #[inline]
+    pub fn retained_bytes(&self) -> usize {
+        self.0.retained.get()
+    }
+
+    /// Frees all idle buffers.
+    pub fn clear(&self) {
+        let buckets = mem::take(&mut *self.0.buckets.borrow_mut());
+        self.0.retained.set(0);
+        drop(buckets);
+    }
+
+    // Takes `buf` back into the pool, or frees it if the pool is full.
+    fn recycle(&self, mut buf: Vec<u8>) {
+        let capacity = buf.capacity();
+        if capacity == 0 || self.0.retained.get() + capacity > self.0.max_retained {
+            return;
+        }
+        buf.clear();
+        let class = class_for_buffer(capacity) as usize;
+        let mut buckets = self.0.buckets.borrow_mut();
+        if buckets.len() <= class {
+            buckets.resize_with(class + 1, Vec::new);
+        }
+        buckets[class].push(buf);
+        self.0.retained.set(self.0.retained.get() + capacity);
+    }
+}
+
+impl Default for BufferReap {
+    #[inline]
+    fn default() -> BufferReap {
+        BufferReap::new()
+    }
+}
+
+impl fmt::Debug for BufferReap {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("BufferReap")
+            .field("idle", &self.idle())
+            .field("retained_bytes", &self.retained_bytes())
+            .field("max_retained", &self.0.max_retained)
+            .finish()
+    }
+}
+
+/// A byte buffer on loan from a `BufferReap`, returned to the pool when dropped.
+///
+/// Dereferences to `Vec<u8>`, so it can be written to and grown like any vector.
+pub struct Buffer {
+    buf: Vec<u8>,
+    pool: BufferReap,
+}
+
+impl Buffer {
+    /// Takes the underlying `Vec<u8>` out of the pool's management.
+    #[inline]
+    pub fn detach(mut this: Buffer) -> Vec<u8> {
+        mem::take(&mut this.buf)
+    }
+
+    /// Returns a reference to the pool this buffer will be returned to.
+    #[inline]
+    pub fn pool(&self) -> &BufferReap {
+        &self.pool
+    }
+}
+
+impl Deref for Buffer {
+    type Target = Vec<u8>;
+
+    #[inline]
+    fn deref(&self) -> &Vec<u8> {
+        &self.buf
+    }
+}
+
+impl DerefMut for Buffer {
+    #[inline]
+    fn deref_mut(&mut self) -> &mut Vec<u8> {
+        &mut self.buf
+    }
+}
+
+impl AsRef<[u8]> for Buffer {
+    fn as_ref(&self) -> &[u8] {
+        &self.buf
+    }
+}
+
+impl AsMut<[u8]> for Buffer {
+    fn as_mut(&mut self) -> &mut [u8] {
+        &mut self.buf
+    }
+}
+
+impl fmt::Debug for Buffer {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Debug::fmt(&self.buf, f)
+    }
+}
+
+impl Drop for Buffer {
+    fn drop(&mut self) {
+        let buf = mem::take(&mut self.buf);
+        self.pool.recycle(buf);
+    }
+}
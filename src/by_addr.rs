@@ -0,0 +1,99 @@
+// Identity comparison of handles, for `ByAddr`.
+
+use std::cmp::Ordering;
+use std::hash::{Hash, Hasher};
+use std::ops::{Deref, DerefMut};
+
+/// A wrapper comparing and hashing a handle by the address of its object, not its value.
+///
+/// Works with any pointer type, `Rp`s included. Sets and maps of `ByAddr<Rp<T>>` are keyed by
+/// object identity, as wanted for the visited set of a graph traversal, or memo tables keyed by
+/// node, and don't require `T: Hash` or `T: Eq`.
+///
+/// Wrap either the handle itself or a reference to its object, as in `ByAddr(&*rp)`:
+/// `ByAddr(&rp)` would compare the addresses of the handles instead.
+///
+/// Zero-sized objects all share one address, so wrapped handles to them always compare equal.
+///
+/// # Examples
+///
+/// ```
+/// use std::collections::HashSet;
+///
+/// use reap::{ByAddr, Reap};
+///
+/// let reap = Reap::new();
+/// let (a, b) = (reap.allocate(1), reap.allocate(1));
+/// assert_eq!(a, b);
+///
+/// let visited: HashSet<_> = vec![ByAddr(&*a), ByAddr(&*b), ByAddr(&*a)].into_iter().collect();
+/// assert_eq!(visited.len(), 2);
+/// ```
+#[derive(Clone, Copy, Debug, Default)]
+pub struct ByAddr<P>(pub P);
+
+impl<P> ByAddr<P>
+    where P: Deref
+{
+    // Returns the address of the pointed to object, without any metadata.
+    #[inline]
+    fn addr(&self) -> *const () {
+        &*self.0 as *const P::Target as *const ()
+    }
+}
+
+impl<P> PartialEq for ByAddr<P>
+    where P: Deref
+{
+    #[inline]
+    fn eq(&self, other: &ByAddr<P>) -> bool {
+        self.addr() == other.addr()
+    }
+}
+
+impl<P> Eq for ByAddr<P> where P: Deref {}
+
+impl<P> PartialOrd for ByAddr<P>
+    where P: Deref
+{
+    #[inline]
+    fn partial_cmp(&self, other: &ByAddr<P>) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl<P> Ord for ByAddr<P>
+    where P: Deref
+{
+    #[inline]
+    fn cmp(&self, other: &ByAddr<P>) -> Ordering {
+        self.addr().cmp(&other.addr())
+    }
+}
+
+impl<P> Hash for ByAddr<P>
+    where P: Deref
+{
+    #[inline]
+    fn hash<H>(&self, state: &mut H)
+        where H: Hasher
+    {
+        self.addr().hash(state);
+    }
+}
+
+impl<P> Deref for ByAddr<P> {
+    type Target = P;
+
+    #[inline]
+    fn deref(&self) -> &P {
+        &self.0
+    }
+}
+
+impl<P> DerefMut for ByAddr<P> {
+    #[inline]
+    fn deref_mut(&mut self) -> &mut P {
+        &mut self.0
+    }
+}
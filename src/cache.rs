@@ -0,0 +1,221 @@
+//! An arena of cached values expiring in bulk.
+//!
+//! Cache entries usually have a lifetime, and are all allocated and freed at the same rate, which
+//! suits an arena well. A `TtlCache` stores its values in a `Reap`, each with the epoch it expires
+//! at, and `sweep` frees everything expired in one go. Epochs are plain numbers whose meaning is up
+//! to the caller: seconds since startup, frame numbers, generations of a build system.
+//!
+//! The cache owns its values, and hands out `CacheKey`s rather than handles, so that a value can
+//! always be freed once it expires: looking a key up afterwards finds nothing.
+//!
+//! # Examples
+//!
+//! ```
+//! use reap::cache::TtlCache;
+//!
+//! let mut cache = TtlCache::new();
+//! let short = cache.insert("short", 10);
+//! let long = cache.insert("long", 60);
+//!
+//! assert_eq!(cache.sweep(30), 1);
+//! assert_eq!(cache.get(short), None);
+//! assert_eq!(cache.get(long), Some(&"long"));
+//! ```
+
+use std::cmp::Reverse;
+use std::collections::BinaryHeap;
+use std::fmt;
+
+use super::generation::Generation;
+use super::{Reap, Rp};
+
+/// Identifies a value in a `TtlCache`.
+///
+/// Once the value is removed or expires, no other value will answer to its key.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct CacheKey {
+    index: usize,
+    generation: Generation,
+}
+
+// An entry of the cache, alive or vacant.
+struct Entry<T> {
+    // Moves on when the entry's value is removed or swept, so that neither an old key nor an
+    // expiry still queued for the old value applies to the value inserted after it.
+    generation: Generation,
+    // The value with the epoch it expires at, or `None` while vacant.
+    value: Option<(u64, Rp<T>)>,
+}
+
+/// A cache of values of type `T`, each expiring at a given epoch.
+pub struct TtlCache<T> {
+    reap: Reap<T>,
+    entries: Vec<Entry<T>>,
+    // Indices of vacant entries.
+    vacant: Vec<usize>,
+    // Pending expiries, earliest first, as `(expires, index, generation)`. Entries that were
+    // removed or had their expiry changed since are skipped when their turn comes.
+    expiries: BinaryHeap<Reverse<(u64, usize, Generation)>>,
+}
+
+impl<T> TtlCache<T> {
+    /// Creates a new, empty cache.
+    #[inline]
+    pub fn new() -> TtlCache<T> {
+        TtlCache {
+            reap: Reap::new(),
+            entries: Vec::new(),
+            vacant: Vec::new(),
+            expiries: BinaryHeap::new(),
+        }
+    }
+
+    /// Returns the number of values in the cache, including expired ones not yet swept.
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.entries.len() - self.vacant.len()
+    }
+
+    /// Returns `true` if the cache holds no values.
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Returns the arena the values are stored in.
+    #[inline]
+    pub fn reap(&self) -> &Reap<T> {
+        &self.reap
+    }
+
+    /// Inserts `value`, to be freed by the first `sweep` at or after epoch `expires`.
+    pub fn insert(&mut self, value: T, expires: u64) -> CacheKey {
+        let rp = self.reap.allocate(value);
+        let index = match self.vacant.pop() {
+            Some(index) => index,
+            None => {
+                self.entries.push(Entry {
+                    generation: Generation::new(),
+                    value: None,
+                });
+                self.entries.len() - 1
+            }
+        };
+        let entry = &mut self.entries[index];
+        entry.value = Some((expires, rp));
+        self.expiries.push(Reverse((expires, index, entry.generation)));
+        CacheKey {
+            index,
+            generation: entry.generation,
+        }
+    }
+
+    /// Returns a reference to the value for `key`, if it is still in the cache.
+    ///
+    /// Values past their expiry are still found until swept.
+    #[inline]
+    pub fn get(&self, key: CacheKey) -> Option<&T> {
+        self.entry(key).map(|(_, rp)| &**rp)
+    }
+
+    /// Returns a mutable reference to the value for `key`, if it is still in the cache.
+    #[inline]
+    pub fn get_mut(&mut self, key: CacheKey) -> Option<&mut T> {
+        match self.entries.get_mut(key.index) {
+            Some(&mut Entry { generation, value: Some((_, ref mut rp)) })
+                if generation == key.generation => Some(&mut **rp),
+            _ => None,
+        }
+    }
+
+    /// Returns the epoch the value for `key` expires at, if it is still in the cache.
+    #[inline]
+    pub fn expires(&self, key: CacheKey) -> Option<u64> {
+        self.entry(key).map(|&(expires, _)| expires)
+    }
+
+    /// Changes the epoch the value for `key` expires at, e.g. to keep a value that was just used
+    /// around for longer. Returns `false` if the value is no longer in the cache.
+    pub fn set_expires(&mut self, key: CacheKey, expires: u64) -> bool {
+        match self.entries.get_mut(key.index) {
+            Some(&mut Entry { generation, value: Some((ref mut at, _)) })
+                if generation == key.generation => {
+                *at = expires;
+                self.expiries.push(Reverse((expires, key.index, generation)));
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// Removes the value for `key`, returning it if it was still in the cache.
+    pub fn remove(&mut self, key: CacheKey) -> Option<T> {
+        self.entry(key)?;
+        self.vacate(key.index).map(Rp::take)
+    }
+
+    /// Frees every value whose expiry is at or before `now`, returning how many were freed.
+    ///
+    /// Only the expired values are looked at, so a sweep finding little to do is cheap.
+    pub fn sweep(&mut self, now: u64) -> usize {
+        let mut swept = 0;
+        while let Some(&Reverse((expires, index, generation))) = self.expiries.peek() {
+            if expires > now {
+                break;
+            }
+            self.expiries.pop();
+            // Skip the leftovers of removed values, and of expiries changed since.
+            let current = match self.entries[index] {
+                Entry { generation: g, value: Some((at, _)) } => g == generation && at == expires,
+                _ => false,
+            };
+            if current {
+                drop(self.vacate(index));
+                swept += 1;
+            }
+        }
+        swept
+    }
+
+    /// Removes every value from the cache, invalidating every key.
+    pub fn clear(&mut self) {
+        for index in 0..self.entries.len() {
+            drop(self.vacate(index));
+        }
+        self.expiries.clear();
+    }
+
+    // Returns the live entry for `key`, if any.
+    #[inline]
+    fn entry(&self, key: CacheKey) -> Option<&(u64, Rp<T>)> {
+        match self.entries.get(key.index) {
+            Some(entry) if entry.generation == key.generation => entry.value.as_ref(),
+            _ => None,
+        }
+    }
+
+    // Vacates the entry at `index`, returning its value if it had one.
+    fn vacate(&mut self, index: usize) -> Option<Rp<T>> {
+        let entry = &mut self.entries[index];
+        let (_, rp) = entry.value.take()?;
+        entry.generation.bump();
+        self.vacant.push(index);
+        Some(rp)
+    }
+}
+
+impl<T> Default for TtlCache<T> {
+    #[inline]
+    fn default() -> TtlCache<T> {
+        TtlCache::new()
+    }
+}
+
+impl<T> fmt::Debug for TtlCache<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("TtlCache")
+            .field("len", &self.len())
+            .field("reap", &self.reap)
+            .finish()
+    }
+}
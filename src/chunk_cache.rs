@@ -0,0 +1,148 @@
+//! A process-wide cache of retired chunks.
+//!
+//! Programs creating an arena per request, or per frame, allocate and free the same few chunk
+//! sizes over and over. With the cache enabled, every chunk an arena of this crate lets go of is
+//! kept, bucketed by size, and new or growing arenas take a chunk from there before asking the
+//! system allocator. That covers the chunks of a dropped `Reap` and of the other arenas alike, as
+//! well as buffers taken over by `Reap::adopt_vec`, which go back with the layout their `Vec`
+//! allocated them with. Arenas of the same type always ask for the same sizes, so they reuse each
+//! other's chunks; arenas of other types do whenever their chunk sizes happen to match.
+//!
+//! The cache is off until given a limit with `set_limit`, and never holds more bytes than that.
+//! Chunks that were `mlock`ed are always freed.
+//!
+//! # Examples
+//!
+//! ```
+//! use reap::{chunk_cache, Reap};
+//!
+//! chunk_cache::set_limit(16 * 1024 * 1024);
+//! for request in 0..100 {
+//!     let reap = Reap::new();
+//!     let objects: Vec<_> = (0..1000u64).map(|i| reap.allocate(i * request)).collect();
+//!     # drop(objects);
+//!     // Dropping the arena retires its chunks rather than freeing them, for the next request.
+//! }
+//! chunk_cache::set_limit(0);
+//! ```
+
+use std::alloc::{self, Layout};
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Mutex, MutexGuard};
+
+// The limit in bytes, zero while disabled. Read without the lock, to keep the disabled cache out of
+// the way of every chunk allocation.
+static LIMIT: AtomicUsize = AtomicUsize::new(0);
+
+static CACHE: Mutex<Option<Cache>> = Mutex::new(None);
+
+struct Cache {
+    // Retired chunks by size in bytes, as the address and alignment of each.
+    buckets: HashMap<usize, Vec<(usize, usize)>>,
+    // Total size of the retired chunks.
+    retained: usize,
+}
+
+impl Cache {
+    // Removes the retired chunk of `size` bytes at `index` in its bucket.
+    fn remove(&mut self, size: usize, index: usize) -> (usize, usize) {
+        let chunks = self.buckets.get_mut(&size).unwrap();
+        let chunk = chunks.swap_remove(index);
+        if chunks.is_empty() {
+            self.buckets.remove(&size);
+        }
+        self.retained -= size;
+        chunk
+    }
+
+    // Removes any retired chunk of `size` bytes.
+    fn pop(&mut self, size: usize) -> Option<(usize, usize)> {
+        let len = self.buckets.get(&size)?.len();
+        Some(self.remove(size, len - 1))
+    }
+}
+
+// Locks the cache, which the thread panicking while holding the lock left consistent.
+fn lock() -> MutexGuard<'static, Option<Cache>> {
+    CACHE.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+}
+
+/// Enables the cache, retaining up to `bytes` of retired chunks, or disables it with 0.
+///
+/// Lowering the limit frees retired chunks until the cache is within it.
+pub fn set_limit(bytes: usize) {
+    LIMIT.store(bytes, Ordering::Relaxed);
+    trim(bytes);
+}
+
+// Frees retired chunks until at most `bytes` are left.
+fn trim(bytes: usize) {
+    let mut cache = lock();
+    if let Some(ref mut cache) = *cache {
+        while cache.retained > bytes {
+            // Buckets are never left empty, so there is one as long as anything is retained.
+            let size = *cache.buckets.keys().next().unwrap();
+            let (ptr, align) = cache.pop(size).unwrap();
+            unsafe {
+                alloc::dealloc(ptr as *mut u8, Layout::from_size_align_unchecked(size, align));
+            }
+        }
+    }
+}
+
+/// Returns the limit set with `set_limit`, 0 while the cache is disabled.
+#[inline]
+pub fn limit() -> usize {
+    LIMIT.load(Ordering::Relaxed)
+}
+
+/// Returns the number of bytes of retired chunks currently in the cache.
+pub fn retained() -> usize {
+    lock().as_ref().map_or(0, |cache| cache.retained)
+}
+
+/// Frees every retired chunk in the cache, leaving the limit as it is.
+pub fn clear() {
+    trim(0);
+}
+
+// Takes a retired chunk of `layout.size()` bytes aligned at least to `layout.align()`, returning
+// its address and the layout it must eventually be freed with.
+pub(super) fn take(layout: Layout) -> Option<(*mut u8, Layout)> {
+    if limit() == 0 {
+        return None;
+    }
+    let mut cache = lock();
+    let cache = cache.as_mut()?;
+    let index = cache.buckets
+        .get(&layout.size())?
+        .iter()
+        .position(|&(_, align)| align >= layout.align())?;
+    let (ptr, align) = cache.remove(layout.size(), index);
+    let layout = unsafe { Layout::from_size_align_unchecked(layout.size(), align) };
+    Some((ptr as *mut u8, layout))
+}
+
+// Retires the chunk at `ptr` allocated with `layout`, returning `false` if the cache won't take
+// it, in which case the caller must free it.
+pub(super) fn put(ptr: *mut u8, layout: Layout) -> bool {
+    if limit() == 0 {
+        return false;
+    }
+    let mut cache = lock();
+    // Again under the lock, so that nothing gets in after `set_limit` trimmed the cache.
+    let limit = limit();
+    let cache = cache.get_or_insert_with(|| {
+        Cache {
+            buckets: HashMap::new(),
+            retained: 0,
+        }
+    });
+    if cache.retained + layout.size() > limit {
+        return false;
+    }
+    cache.buckets.entry(layout.size()).or_default().push((ptr as usize, layout.align()));
+    cache.retained += layout.size();
+    true
+}
@@ -0,0 +1,64 @@
+// Collecting iterators into a new `Reap`.
+
+use std::fmt;
+use std::iter::FromIterator;
+
+use super::{Reap, Rp};
+
+/// A new `Reap<T>` along with a handle to each of its objects, collected from an iterator.
+///
+/// This is `Reap::from_iter` for iterator pipelines: collecting into a `ReapCollect` loads every
+/// item into a fresh arena, sized from the iterator's lower size hint.
+///
+/// # Examples
+///
+/// ```
+/// use reap::ReapCollect;
+///
+/// let ReapCollect { reap, rps } = (0..10).map(|i| i * i).collect();
+/// assert_eq!(*rps[9], 81);
+/// assert_eq!(reap.stats().live, 10);
+/// ```
+pub struct ReapCollect<T> {
+    /// The arena holding the collected objects.
+    pub reap: Reap<T>,
+    /// A handle to each collected object, in iteration order.
+    pub rps: Vec<Rp<T>>,
+}
+
+impl<T> ReapCollect<T> {
+    /// Returns the arena and the handles, as returned by `Reap::from_iter`.
+    #[inline]
+    pub fn into_parts(self) -> (Reap<T>, Vec<Rp<T>>) {
+        (self.reap, self.rps)
+    }
+}
+
+impl<T> FromIterator<T> for ReapCollect<T> {
+    #[inline]
+    #[cfg_attr(any(feature = "backtrace", feature = "callsites"), track_caller)]
+    fn from_iter<I>(iter: I) -> ReapCollect<T>
+        where I: IntoIterator<Item = T>
+    {
+        let (reap, rps) = Reap::from_iter(iter);
+        ReapCollect { reap, rps }
+    }
+}
+
+impl<T> From<ReapCollect<T>> for (Reap<T>, Vec<Rp<T>>) {
+    #[inline]
+    fn from(collect: ReapCollect<T>) -> (Reap<T>, Vec<Rp<T>>) {
+        collect.into_parts()
+    }
+}
+
+impl<T> fmt::Debug for ReapCollect<T>
+    where T: fmt::Debug
+{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("ReapCollect")
+            .field("reap", &self.reap)
+            .field("rps", &self.rps)
+            .finish()
+    }
+}
@@ -0,0 +1,346 @@
+//! A densely packed pool with stable generational handles.
+//!
+//! A `Reap` keeps every object where it was allocated, which is what makes its handles stable,
+//! but leaves holes behind as objects are freed. Systems in an entity-component design instead
+//! want to sweep over all components of a kind in one tight loop. A `DensePool` keeps its values
+//! packed in a single slice, filling the hole left by a removal with the last value, and hands out
+//! handles that go through an indirection table so that they survive the shuffling.
+//!
+//! The values don't live in a `Reap`'s slots. Keeping them dense means moving them on every
+//! removal, and a `Reap` never moves an object: its handles are pointers, which is what makes them
+//! cheap, and what a swap-remove would invalidate. Chunks that double in size also couldn't hand
+//! out one contiguous slice over every value. So the values live in a single arena chunk of bare
+//! `T`s instead, allocated like every other chunk of the crate, through the chunk cache and the
+//! injected failures of the `failpoints` feature. A pool that outgrows its chunk moves its values
+//! to a new one twice the size and retires the old one, and it is the handles, not the addresses,
+//! that stay stable. The two are meant to be used together: data referenced by address, like the
+//! nodes of a scene graph, goes in a `Reap`, while the components swept every frame go in a
+//! `DensePool`, and the objects in the arena refer to them by `DenseHandle`, which is `Copy` and
+//! borrows nothing.
+//!
+//! # Examples
+//!
+//! ```
+//! use reap::dense::DensePool;
+//!
+//! let mut positions = DensePool::new();
+//! let a = positions.insert(1.0f32);
+//! let b = positions.insert(2.0);
+//! let c = positions.insert(3.0);
+//!
+//! positions.remove(a);
+//! for x in positions.iter_mut() {
+//!     *x *= 10.0;
+//! }
+//!
+//! assert_eq!(positions.get(b), Some(&20.0));
+//! assert_eq!(positions.get(c), Some(&30.0));
+//! assert_eq!(positions.get(a), None);
+//! assert_eq!(positions.as_slice().len(), 2);
+//! ```
+
+use std::cmp;
+use std::fmt;
+use std::marker::PhantomData;
+use std::mem;
+use std::ptr::{self, NonNull};
+use std::slice;
+
+use super::generation::Generation;
+use super::{Chunk, PAGE};
+
+/// Identifies a value in a `DensePool`.
+///
+/// A handle stays valid for as long as its value is in the pool, and once the value is removed
+/// no other value will answer to it.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct DenseHandle {
+    index: usize,
+    generation: Generation,
+}
+
+// An entry of the indirection table.
+struct Slot {
+    // Moves on when `remove` or `clear` takes the slot's value, so that a handle to a removed
+    // value doesn't find the value inserted into the slot later.
+    generation: Generation,
+    // Position of the value in the chunk, or `None` while vacant.
+    dense: Option<usize>,
+}
+
+/// A pool of values stored contiguously, addressed by generational handles.
+pub struct DensePool<T> {
+    // The values, packed at the start of the chunk. Zero-sized values need none.
+    chunk: Option<Chunk>,
+    // Number of values in the chunk.
+    len: usize,
+    // Index into `slots` of each value, in storage order.
+    owners: Vec<usize>,
+    slots: Vec<Slot>,
+    // Indices of vacant slots.
+    vacant: Vec<usize>,
+    _marker: PhantomData<T>,
+}
+
+// The pool owns its values like a `Vec` does, and its chunk is plain memory any thread may free.
+unsafe impl<T: Send> Send for DensePool<T> {}
+unsafe impl<T: Sync> Sync for DensePool<T> {}
+
+impl<T> DensePool<T> {
+    /// Creates a new, empty `DensePool`.
+    #[inline]
+    pub fn new() -> DensePool<T> {
+        DensePool::with_capacity(0)
+    }
+
+    /// Creates a new, empty `DensePool` with room for `capacity` values.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the required capacity overflows, and aborts if the allocation fails, like
+    /// `Vec::with_capacity`.
+    #[inline]
+    pub fn with_capacity(capacity: usize) -> DensePool<T> {
+        DensePool {
+            chunk: if capacity == 0 || mem::size_of::<T>() == 0 {
+                None
+            } else {
+                Some(Chunk::new::<T>(capacity))
+            },
+            len: 0,
+            owners: Vec::with_capacity(capacity),
+            slots: Vec::with_capacity(capacity),
+            vacant: Vec::new(),
+            _marker: PhantomData,
+        }
+    }
+
+    /// Returns the number of values in the pool.
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Returns `true` if the pool holds no values.
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Returns the number of values the pool can hold before moving them to a larger chunk.
+    #[inline]
+    pub fn capacity(&self) -> usize {
+        if mem::size_of::<T>() == 0 {
+            return usize::MAX;
+        }
+        self.chunk.as_ref().map_or(0, Chunk::capacity)
+    }
+
+    /// Inserts `value` at the end of the dense storage, returning a handle to it.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the pool is full and the capacity of a larger chunk overflows, and aborts if
+    /// allocating it fails.
+    pub fn insert(&mut self, value: T) -> DenseHandle {
+        if self.len == self.capacity() {
+            self.grow();
+        }
+        let dense = self.len;
+        unsafe {
+            ptr::write(self.start().add(dense), value);
+        }
+        self.len += 1;
+        let index = match self.vacant.pop() {
+            Some(index) => index,
+            None => {
+                self.slots.push(Slot {
+                    generation: Generation::new(),
+                    dense: None,
+                });
+                self.slots.len() - 1
+            }
+        };
+        let slot = &mut self.slots[index];
+        slot.dense = Some(dense);
+        self.owners.push(index);
+        DenseHandle {
+            index,
+            generation: slot.generation,
+        }
+    }
+
+    /// Removes the value for `handle`, returning it if it was still in the pool.
+    ///
+    /// The last value in the dense storage takes the removed value's place.
+    pub fn remove(&mut self, handle: DenseHandle) -> Option<T> {
+        let dense = self.dense_index(handle)?;
+        let slot = &mut self.slots[handle.index];
+        slot.dense = None;
+        slot.generation.bump();
+        self.vacant.push(handle.index);
+
+        self.len -= 1;
+        let value = unsafe {
+            let start = self.start();
+            let value = ptr::read(start.add(dense));
+            ptr::copy(start.add(self.len), start.add(dense), 1);
+            value
+        };
+        self.owners.swap_remove(dense);
+        // Point the moved value's slot at its new position.
+        if let Some(&moved) = self.owners.get(dense) {
+            self.slots[moved].dense = Some(dense);
+        }
+        Some(value)
+    }
+
+    /// Returns `true` if the value for `handle` is still in the pool.
+    #[inline]
+    pub fn contains(&self, handle: DenseHandle) -> bool {
+        self.dense_index(handle).is_some()
+    }
+
+    /// Returns a reference to the value for `handle`, if it is still in the pool.
+    #[inline]
+    pub fn get(&self, handle: DenseHandle) -> Option<&T> {
+        self.dense_index(handle).map(|dense| &self.as_slice()[dense])
+    }
+
+    /// Returns a mutable reference to the value for `handle`, if it is still in the pool.
+    #[inline]
+    pub fn get_mut(&mut self, handle: DenseHandle) -> Option<&mut T> {
+        match self.dense_index(handle) {
+            Some(dense) => Some(&mut self.as_mut_slice()[dense]),
+            None => None,
+        }
+    }
+
+    /// Returns the values as a contiguous slice, in no particular order.
+    #[inline]
+    pub fn as_slice(&self) -> &[T] {
+        unsafe { slice::from_raw_parts(self.start(), self.len) }
+    }
+
+    /// Returns the values as a contiguous mutable slice, in no particular order.
+    #[inline]
+    pub fn as_mut_slice(&mut self) -> &mut [T] {
+        unsafe { slice::from_raw_parts_mut(self.start(), self.len) }
+    }
+
+    /// Returns an iterator over the values, in storage order.
+    #[inline]
+    pub fn iter(&self) -> slice::Iter<'_, T> {
+        self.as_slice().iter()
+    }
+
+    /// Returns an iterator over mutable references to the values, in storage order.
+    #[inline]
+    pub fn iter_mut(&mut self) -> slice::IterMut<'_, T> {
+        self.as_mut_slice().iter_mut()
+    }
+
+    /// Returns an iterator over the handles of the values, in storage order, matching `iter`.
+    pub fn handles(&self) -> impl Iterator<Item = DenseHandle> + '_ {
+        self.owners.iter().map(move |&index| {
+            DenseHandle {
+                index,
+                generation: self.slots[index].generation,
+            }
+        })
+    }
+
+    /// Removes all values from the pool, invalidating every handle.
+    pub fn clear(&mut self) {
+        for &index in &self.owners {
+            let slot = &mut self.slots[index];
+            slot.dense = None;
+            slot.generation.bump();
+            self.vacant.push(index);
+        }
+        self.owners.clear();
+        unsafe { self.drop_values() }
+    }
+
+    // Returns a pointer to the first value.
+    #[inline]
+    fn start(&self) -> *mut T {
+        match self.chunk {
+            Some(ref chunk) => chunk.start::<T>(),
+            None => NonNull::dangling().as_ptr(),
+        }
+    }
+
+    // Moves the values to a chunk twice the size, or to a first chunk of a page.
+    #[cold]
+    fn grow(&mut self) {
+        let cap = match self.capacity() {
+            0 => cmp::max(PAGE / mem::size_of::<T>(), 1),
+            cap => cap.checked_mul(2).expect("capacity overflow"),
+        };
+        let chunk = Chunk::new::<T>(cap);
+        unsafe {
+            ptr::copy_nonoverlapping(self.start(), chunk.start::<T>(), self.len);
+        }
+        // The old chunk is retired here, its values already moved out.
+        self.chunk = Some(chunk);
+    }
+
+    // Drops every value, leaving the pool without any.
+    unsafe fn drop_values(&mut self) {
+        let values = ptr::slice_from_raw_parts_mut(self.start(), self.len);
+        // Forgotten first, so that a panicking destructor can't lead to a double drop.
+        self.len = 0;
+        ptr::drop_in_place(values);
+    }
+
+    // Returns the position in the chunk of the value for `handle`, if it is still in the pool.
+    #[inline]
+    fn dense_index(&self, handle: DenseHandle) -> Option<usize> {
+        match self.slots.get(handle.index) {
+            Some(slot) if slot.generation == handle.generation => slot.dense,
+            _ => None,
+        }
+    }
+}
+
+impl<T> Drop for DensePool<T> {
+    fn drop(&mut self) {
+        unsafe { self.drop_values() }
+    }
+}
+
+impl<T> Default for DensePool<T> {
+    #[inline]
+    fn default() -> DensePool<T> {
+        DensePool::new()
+    }
+}
+
+impl<T> fmt::Debug for DensePool<T>
+    where T: fmt::Debug
+{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_list().entries(self.iter()).finish()
+    }
+}
+
+impl<'a, T> IntoIterator for &'a DensePool<T> {
+    type Item = &'a T;
+    type IntoIter = slice::Iter<'a, T>;
+
+    #[inline]
+    fn into_iter(self) -> slice::Iter<'a, T> {
+        self.iter()
+    }
+}
+
+impl<'a, T> IntoIterator for &'a mut DensePool<T> {
+    type Item = &'a mut T;
+    type IntoIter = slice::IterMut<'a, T>;
+
+    #[inline]
+    fn into_iter(self) -> slice::IterMut<'a, T> {
+        self.iter_mut()
+    }
+}
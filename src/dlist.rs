@@ -0,0 +1,574 @@
+//! A doubly-linked list whose nodes live in a `Reap`.
+//!
+//! Each node owns its successor through an `Rp`, while the link back to its predecessor is a
+//! plain pointer that the list keeps valid. Since a node never moves once allocated, splicing two
+//! lists together is a matter of relinking the ends, and a removed node's slot goes straight back
+//! to the freelist for the next insertion.
+
+use std::fmt;
+use std::iter::FromIterator;
+use std::marker;
+use std::ptr;
+
+use super::{Reap, Rp};
+
+// A single link in a `DList`.
+struct Node<T> {
+    value: T,
+    // Owning link to the next node, `None` for the tail.
+    next: Option<Rp<Node<T>>>,
+    // Back link to the previous node, null for the head.
+    prev: *mut Node<T>,
+}
+
+// Returns a raw pointer to the node owned by `link`, or null.
+//
+// Taken straight from the `Rp`'s own pointer, never through a reference to the node: a pointer
+// derived from a `&mut Node` would be invalidated by the next mutable borrow of the node through
+// its `Rp`, and the back links must stay valid across those.
+#[inline]
+fn as_ptr<T>(link: &Option<Rp<Node<T>>>) -> *mut Node<T> {
+    match *link {
+        Some(ref node) => node.ptr.as_ptr(),
+        None => ptr::null_mut(),
+    }
+}
+
+/// A doubly-linked list with arena-allocated nodes.
+///
+/// # Examples
+///
+/// ```
+/// use reap::dlist::DList;
+///
+/// let mut list = DList::new();
+/// list.push_back(2);
+/// list.push_back(3);
+/// list.push_front(1);
+///
+/// assert_eq!(list.iter().cloned().collect::<Vec<_>>(), vec![1, 2, 3]);
+/// assert_eq!(list.pop_back(), Some(3));
+/// ```
+pub struct DList<T> {
+    reap: Reap<Node<T>>,
+    head: Option<Rp<Node<T>>>,
+    tail: *mut Node<T>,
+    len: usize,
+}
+
+impl<T> DList<T> {
+    /// Creates an empty `DList<T>`.
+    #[inline]
+    pub fn new() -> DList<T> {
+        DList {
+            reap: Reap::new(),
+            head: None,
+            tail: ptr::null_mut(),
+            len: 0,
+        }
+    }
+
+    /// Returns the number of elements in the list.
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Returns `true` if the list contains no elements.
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Returns a reference to the first element, if any.
+    #[inline]
+    pub fn front(&self) -> Option<&T> {
+        self.head.as_ref().map(|node| &node.value)
+    }
+
+    /// Returns a mutable reference to the first element, if any.
+    #[inline]
+    pub fn front_mut(&mut self) -> Option<&mut T> {
+        self.head.as_mut().map(|node| &mut node.value)
+    }
+
+    /// Returns a reference to the last element, if any.
+    #[inline]
+    pub fn back(&self) -> Option<&T> {
+        if self.tail.is_null() {
+            None
+        } else {
+            unsafe { Some(&(*self.tail).value) }
+        }
+    }
+
+    /// Returns a mutable reference to the last element, if any.
+    #[inline]
+    pub fn back_mut(&mut self) -> Option<&mut T> {
+        if self.tail.is_null() {
+            None
+        } else {
+            unsafe { Some(&mut (*self.tail).value) }
+        }
+    }
+
+    /// Prepends an element to the list.
+    pub fn push_front(&mut self, value: T) {
+        let node = Some(self.reap.allocate(Node {
+            value,
+            next: self.head.take(),
+            prev: ptr::null_mut(),
+        }));
+        let node_ptr = as_ptr(&node);
+        unsafe {
+            match as_ptr(&(*node_ptr).next) {
+                next if next.is_null() => self.tail = node_ptr,
+                next => (*next).prev = node_ptr,
+            }
+        }
+        self.head = node;
+        self.len += 1;
+    }
+
+    /// Appends an element to the list.
+    pub fn push_back(&mut self, value: T) {
+        let tail = self.tail;
+        if tail.is_null() {
+            self.push_front(value);
+        } else {
+            unsafe { self.insert_after(tail, value) }
+        }
+    }
+
+    /// Removes the first element and returns it, or `None` if the list is empty.
+    pub fn pop_front(&mut self) -> Option<T> {
+        let head = as_ptr(&self.head);
+        if head.is_null() {
+            None
+        } else {
+            unsafe { Some(self.unlink(head)) }
+        }
+    }
+
+    /// Removes the last element and returns it, or `None` if the list is empty.
+    pub fn pop_back(&mut self) -> Option<T> {
+        let tail = self.tail;
+        if tail.is_null() {
+            None
+        } else {
+            unsafe { Some(self.unlink(tail)) }
+        }
+    }
+
+    /// Moves all elements of `other` to the end of `self`, leaving `other` empty.
+    ///
+    /// This is `O(1)`: no nodes are moved or reallocated, the two lists are simply relinked.
+    /// `other`'s nodes remain in `other`'s `Reap`, which is kept alive by their handles.
+    pub fn append(&mut self, other: &mut DList<T>) {
+        if other.is_empty() {
+            return;
+        }
+        if self.is_empty() {
+            // Swapping the reaps as well lets `self` keep allocating alongside the spliced nodes.
+            ::std::mem::swap(self, other);
+            return;
+        }
+        let head = other.head.take();
+        unsafe {
+            (*as_ptr(&head)).prev = self.tail;
+            (*self.tail).next = head;
+        }
+        self.tail = other.tail;
+        self.len += other.len;
+        other.tail = ptr::null_mut();
+        other.len = 0;
+    }
+
+    /// Returns a front-to-back iterator over references to the elements.
+    #[inline]
+    pub fn iter(&self) -> Iter<'_, T> {
+        Iter {
+            head: as_ptr(&self.head),
+            tail: self.tail,
+            len: self.len,
+            _marker: marker::PhantomData,
+        }
+    }
+
+    /// Returns a front-to-back iterator over mutable references to the elements.
+    #[inline]
+    pub fn iter_mut(&mut self) -> IterMut<'_, T> {
+        IterMut {
+            head: as_ptr(&self.head),
+            tail: self.tail,
+            len: self.len,
+            _marker: marker::PhantomData,
+        }
+    }
+
+    /// Returns a cursor positioned at the first element.
+    ///
+    /// If the list is empty the cursor starts at the "ghost" position between the tail and the
+    /// head.
+    #[inline]
+    pub fn cursor_front_mut(&mut self) -> CursorMut<'_, T> {
+        let current = as_ptr(&self.head);
+        CursorMut {
+            list: self,
+            current,
+        }
+    }
+
+    /// Returns a cursor positioned at the last element.
+    ///
+    /// If the list is empty the cursor starts at the "ghost" position between the tail and the
+    /// head.
+    #[inline]
+    pub fn cursor_back_mut(&mut self) -> CursorMut<'_, T> {
+        let current = self.tail;
+        CursorMut {
+            list: self,
+            current,
+        }
+    }
+
+    // Links a new node holding `value` directly after `node`.
+    //
+    // `node` must be a non-null node of this list.
+    unsafe fn insert_after(&mut self, node: *mut Node<T>, value: T) {
+        let new = Some(self.reap.allocate(Node {
+            value,
+            next: (*node).next.take(),
+            prev: node,
+        }));
+        let new_ptr = as_ptr(&new);
+        match as_ptr(&(*new_ptr).next) {
+            next if next.is_null() => self.tail = new_ptr,
+            next => (*next).prev = new_ptr,
+        }
+        (*node).next = new;
+        self.len += 1;
+    }
+
+    // Unlinks `node` from the list and returns its value, freeing its slot.
+    //
+    // `node` must be a non-null node of this list.
+    unsafe fn unlink(&mut self, node: *mut Node<T>) -> T {
+        let prev = (*node).prev;
+        let owner: *mut Option<Rp<Node<T>>> = if prev.is_null() {
+            &mut self.head
+        } else {
+            &mut (*prev).next
+        };
+        let this = (*owner).take().expect("linked node without an owner");
+        let next = (*node).next.take();
+        match as_ptr(&next) {
+            next if next.is_null() => self.tail = prev,
+            next => (*next).prev = prev,
+        }
+        *owner = next;
+        self.len -= 1;
+        Rp::take(this).value
+    }
+}
+
+impl<T> Drop for DList<T> {
+    fn drop(&mut self) {
+        // Unlink iteratively, letting the default drop glue recurse down the `next` chain would
+        // blow the stack on long lists.
+        let mut link = self.head.take();
+        while let Some(mut node) = link {
+            link = node.next.take();
+        }
+    }
+}
+
+impl<T> Default for DList<T> {
+    #[inline]
+    fn default() -> DList<T> {
+        DList::new()
+    }
+}
+
+impl<T> fmt::Debug for DList<T>
+    where T: fmt::Debug
+{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_list().entries(self.iter()).finish()
+    }
+}
+
+impl<T> Extend<T> for DList<T> {
+    fn extend<I>(&mut self, iter: I)
+        where I: IntoIterator<Item = T>
+    {
+        for value in iter {
+            self.push_back(value);
+        }
+    }
+}
+
+impl<T> FromIterator<T> for DList<T> {
+    fn from_iter<I>(iter: I) -> DList<T>
+        where I: IntoIterator<Item = T>
+    {
+        let mut list = DList::new();
+        list.extend(iter);
+        list
+    }
+}
+
+impl<T> IntoIterator for DList<T> {
+    type Item = T;
+    type IntoIter = IntoIter<T>;
+
+    #[inline]
+    fn into_iter(self) -> IntoIter<T> {
+        IntoIter(self)
+    }
+}
+
+impl<'a, T> IntoIterator for &'a DList<T> {
+    type Item = &'a T;
+    type IntoIter = Iter<'a, T>;
+
+    #[inline]
+    fn into_iter(self) -> Iter<'a, T> {
+        self.iter()
+    }
+}
+
+impl<'a, T> IntoIterator for &'a mut DList<T> {
+    type Item = &'a mut T;
+    type IntoIter = IterMut<'a, T>;
+
+    #[inline]
+    fn into_iter(self) -> IterMut<'a, T> {
+        self.iter_mut()
+    }
+}
+
+/// An iterator over references to the elements of a `DList`.
+pub struct Iter<'a, T: 'a> {
+    head: *const Node<T>,
+    tail: *const Node<T>,
+    len: usize,
+    _marker: marker::PhantomData<&'a T>,
+}
+
+impl<'a, T> Iterator for Iter<'a, T> {
+    type Item = &'a T;
+
+    #[inline]
+    fn next(&mut self) -> Option<&'a T> {
+        if self.len == 0 {
+            None
+        } else {
+            unsafe {
+                let node = self.head;
+                self.head = as_ptr(&(*node).next);
+                self.len -= 1;
+                Some(&(*node).value)
+            }
+        }
+    }
+
+    #[inline]
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.len, Some(self.len))
+    }
+}
+
+impl<'a, T> DoubleEndedIterator for Iter<'a, T> {
+    #[inline]
+    fn next_back(&mut self) -> Option<&'a T> {
+        if self.len == 0 {
+            None
+        } else {
+            unsafe {
+                let node = self.tail;
+                self.tail = (*node).prev;
+                self.len -= 1;
+                Some(&(*node).value)
+            }
+        }
+    }
+}
+
+impl<'a, T> ExactSizeIterator for Iter<'a, T> {}
+
+/// An iterator over mutable references to the elements of a `DList`.
+pub struct IterMut<'a, T: 'a> {
+    head: *mut Node<T>,
+    tail: *mut Node<T>,
+    len: usize,
+    _marker: marker::PhantomData<&'a mut T>,
+}
+
+impl<'a, T> Iterator for IterMut<'a, T> {
+    type Item = &'a mut T;
+
+    #[inline]
+    fn next(&mut self) -> Option<&'a mut T> {
+        if self.len == 0 {
+            None
+        } else {
+            unsafe {
+                let node = self.head;
+                self.head = as_ptr(&(*node).next);
+                self.len -= 1;
+                Some(&mut (*node).value)
+            }
+        }
+    }
+
+    #[inline]
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.len, Some(self.len))
+    }
+}
+
+impl<'a, T> DoubleEndedIterator for IterMut<'a, T> {
+    #[inline]
+    fn next_back(&mut self) -> Option<&'a mut T> {
+        if self.len == 0 {
+            None
+        } else {
+            unsafe {
+                let node = self.tail;
+                self.tail = (*node).prev;
+                self.len -= 1;
+                Some(&mut (*node).value)
+            }
+        }
+    }
+}
+
+impl<'a, T> ExactSizeIterator for IterMut<'a, T> {}
+
+/// An owning iterator over the elements of a `DList`.
+pub struct IntoIter<T>(DList<T>);
+
+impl<T> Iterator for IntoIter<T> {
+    type Item = T;
+
+    #[inline]
+    fn next(&mut self) -> Option<T> {
+        self.0.pop_front()
+    }
+
+    #[inline]
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.0.len, Some(self.0.len))
+    }
+}
+
+impl<T> DoubleEndedIterator for IntoIter<T> {
+    #[inline]
+    fn next_back(&mut self) -> Option<T> {
+        self.0.pop_back()
+    }
+}
+
+impl<T> ExactSizeIterator for IntoIter<T> {}
+
+/// A cursor over a `DList` that can insert and remove elements in `O(1)`.
+///
+/// A cursor always rests either on an element or on the "ghost" position, which sits after the
+/// tail and before the head.
+///
+/// # Examples
+///
+/// ```
+/// use reap::dlist::DList;
+///
+/// let mut list: DList<i32> = (1..7).collect();
+///
+/// let mut cursor = list.cursor_front_mut();
+/// while let Some(&mut n) = cursor.current() {
+///     if n % 2 == 0 {
+///         cursor.remove_current();
+///     } else {
+///         cursor.move_next();
+///     }
+/// }
+///
+/// assert_eq!(list.into_iter().collect::<Vec<_>>(), vec![1, 3, 5]);
+/// ```
+pub struct CursorMut<'a, T: 'a> {
+    list: &'a mut DList<T>,
+    // Null for the ghost position.
+    current: *mut Node<T>,
+}
+
+impl<'a, T> CursorMut<'a, T> {
+    /// Returns a mutable reference to the element under the cursor, or `None` at the ghost
+    /// position.
+    #[inline]
+    pub fn current(&mut self) -> Option<&mut T> {
+        if self.current.is_null() {
+            None
+        } else {
+            unsafe { Some(&mut (*self.current).value) }
+        }
+    }
+
+    /// Moves the cursor to the next element, or from the ghost position to the head.
+    #[inline]
+    pub fn move_next(&mut self) {
+        self.current = if self.current.is_null() {
+            as_ptr(&self.list.head)
+        } else {
+            unsafe { as_ptr(&(*self.current).next) }
+        };
+    }
+
+    /// Moves the cursor to the previous element, or from the ghost position to the tail.
+    #[inline]
+    pub fn move_prev(&mut self) {
+        self.current = if self.current.is_null() {
+            self.list.tail
+        } else {
+            unsafe { (*self.current).prev }
+        };
+    }
+
+    /// Removes the element under the cursor and returns it, moving the cursor to the next
+    /// element.
+    ///
+    /// Returns `None` and does nothing at the ghost position.
+    pub fn remove_current(&mut self) -> Option<T> {
+        if self.current.is_null() {
+            None
+        } else {
+            unsafe {
+                let node = self.current;
+                self.current = as_ptr(&(*node).next);
+                Some(self.list.unlink(node))
+            }
+        }
+    }
+
+    /// Inserts an element after the cursor, or at the front of the list from the ghost position.
+    pub fn insert_after(&mut self, value: T) {
+        if self.current.is_null() {
+            self.list.push_front(value);
+        } else {
+            unsafe { self.list.insert_after(self.current, value) }
+        }
+    }
+
+    /// Inserts an element before the cursor, or at the back of the list from the ghost position.
+    pub fn insert_before(&mut self, value: T) {
+        let prev = if self.current.is_null() {
+            self.list.tail
+        } else {
+            unsafe { (*self.current).prev }
+        };
+        if prev.is_null() {
+            self.list.push_front(value);
+        } else {
+            unsafe { self.list.insert_after(prev, value) }
+        }
+    }
+}
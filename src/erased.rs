@@ -0,0 +1,195 @@
+//! An arena for values of any type.
+//!
+//! `ErasedReap` keeps one `Reap<T>` per type it has been asked to allocate, created on first use
+//! and looked up by `TypeId`. Applications with many small node types can share one arena object
+//! instead of managing a `Reap` per type, like a compiler allocating every kind of syntax tree and
+//! type-checker node from a single `TypedReapSet`.
+
+use std::any::{Any, TypeId};
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::rc::Rc;
+
+use super::{Reap, ReapStats, Rp};
+
+// Type-erased operations on a sub-arena.
+trait Pool {
+    fn as_any(&self) -> &dyn Any;
+
+    // Returns `true` if nothing but the `ErasedReap` refers to this sub-arena.
+    fn is_unused(&self) -> bool;
+
+    // Returns this sub-arena's statistics.
+    fn stats(&self) -> ReapStats;
+}
+
+impl<T> Pool for Reap<T>
+    where T: 'static
+{
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn is_unused(&self) -> bool {
+        // Every live `Rp` holds a handle to its `Reap`.
+        Rc::strong_count(&self.0) == 1
+    }
+
+    fn stats(&self) -> ReapStats {
+        Reap::stats(self)
+    }
+}
+
+/// An arena that can allocate values of any `'static` type.
+///
+/// # Examples
+///
+/// ```
+/// use reap::erased::ErasedReap;
+///
+/// let reap = ErasedReap::new();
+///
+/// let a = reap.allocate(1u32);
+/// let b = reap.allocate("two");
+/// let c = reap.allocate(vec![3.0f64]);
+///
+/// assert_eq!(*a, 1);
+/// assert_eq!(*b, "two");
+/// assert_eq!(c[0], 3.0);
+/// assert_eq!(reap.len(), 3);
+///
+/// // Sub-arenas are only purged once all of their handles are gone.
+/// drop(b);
+/// assert_eq!(reap.purge(), 1);
+/// assert!(!reap.contains::<&str>());
+/// ```
+pub struct ErasedReap {
+    // Each value is a `Reap<T>` for the `TypeId` of `T` it is keyed by.
+    reaps: RefCell<HashMap<TypeId, Box<dyn Pool>>>,
+}
+
+/// A set of arenas, one per type of value, as an `ErasedReap` is.
+///
+/// The name says what the arena holds rather than how: each type gets a `Reap<T>` of its own,
+/// created the first time a value of that type is allocated, so that objects of one type stay
+/// packed together, and `stats` and `purge` act on all of them at once.
+pub type TypedReapSet = ErasedReap;
+
+impl ErasedReap {
+    /// Creates a new, empty `ErasedReap`.
+    #[inline]
+    pub fn new() -> ErasedReap {
+        ErasedReap { reaps: RefCell::new(HashMap::new()) }
+    }
+
+    /// Allocates `object` in the sub-arena for its type, creating that sub-arena if needed.
+    #[inline]
+    pub fn allocate<T>(&self, object: T) -> Rp<T>
+        where T: 'static
+    {
+        // Clone the sub-arena out so that no borrow is held while `T` is moved into it.
+        self.reap::<T>().allocate(object)
+    }
+
+    /// Returns the sub-arena for values of type `T`, creating it if needed.
+    pub fn reap<T>(&self) -> Reap<T>
+        where T: 'static
+    {
+        self.reaps
+            .borrow_mut()
+            .entry(TypeId::of::<T>())
+            .or_insert_with(|| Box::new(Reap::<T>::new()))
+            .as_any()
+            .downcast_ref::<Reap<T>>()
+            .expect("sub-arena keyed by the wrong type")
+            .clone()
+    }
+
+    /// Returns `true` if a sub-arena for type `T` currently exists.
+    #[inline]
+    pub fn contains<T>(&self) -> bool
+        where T: 'static
+    {
+        self.reaps.borrow().contains_key(&TypeId::of::<T>())
+    }
+
+    /// Returns the number of sub-arenas, i.e. of distinct types allocated so far.
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.reaps.borrow().len()
+    }
+
+    /// Returns `true` if no sub-arenas exist.
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.reaps.borrow().is_empty()
+    }
+
+    /// Returns the total number of bytes allocated for chunks across all sub-arenas.
+    pub fn reserved_bytes(&self) -> usize {
+        self.reaps.borrow().values().map(|pool| pool.stats().reserved_bytes()).sum()
+    }
+
+    /// Returns the statistics of every sub-arena, summed.
+    ///
+    /// Each count is the total over all sub-arenas, so `hit_rate` and `occupancy` apply to the
+    /// set as a whole. Slot sizes differ from one sub-arena to the next, so `slot_size` is zero,
+    /// and with it `reserved_bytes` and `live_bytes` of the result: `ErasedReap::reserved_bytes`
+    /// gives the memory reserved across all sub-arenas instead.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use reap::erased::TypedReapSet;
+    ///
+    /// let set = TypedReapSet::new();
+    /// let numbers: Vec<_> = (0..3u64).map(|i| set.allocate(i)).collect();
+    /// let name = set.allocate("name");
+    /// drop(numbers);
+    /// let number = set.allocate(3u64);
+    ///
+    /// let stats = set.stats();
+    /// assert_eq!((stats.live, stats.free), (2, 2));
+    /// assert_eq!((stats.freelist_hits, stats.bump_allocations), (1, 4));
+    /// # drop((name, number));
+    /// ```
+    pub fn stats(&self) -> ReapStats {
+        let mut total = ReapStats::default();
+        for pool in self.reaps.borrow().values() {
+            let stats = pool.stats();
+            total.live += stats.live;
+            total.chunks += stats.chunks;
+            total.capacity += stats.capacity;
+            total.free += stats.free;
+            total.untouched += stats.untouched;
+            total.freelist_hits += stats.freelist_hits;
+            total.bump_allocations += stats.bump_allocations;
+        }
+        total
+    }
+
+    /// Drops every sub-arena that has no live handles, returning their memory to the system.
+    ///
+    /// A sub-arena is kept as long as any `Rp` allocated in it, or any `Reap` obtained from
+    /// `reap()`, is alive. Returns the number of sub-arenas dropped.
+    pub fn purge(&self) -> usize {
+        let unused: Vec<Box<dyn Pool>> = {
+            let mut reaps = self.reaps.borrow_mut();
+            let ids: Vec<TypeId> = reaps.iter()
+                .filter(|&(_, pool)| pool.is_unused())
+                .map(|(&id, _)| id)
+                .collect();
+            ids.iter().filter_map(|id| reaps.remove(id)).collect()
+        };
+        // Dropped only once the borrow is released: a sub-arena's growth observer goes with it,
+        // and may well use this arena in its destructor.
+        unused.len()
+    }
+}
+
+impl Default for ErasedReap {
+    #[inline]
+    fn default() -> ErasedReap {
+        ErasedReap::new()
+    }
+}
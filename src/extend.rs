@@ -0,0 +1,166 @@
+// Contiguous allocation of many objects at once, for `Reap::alloc_extend`.
+
+use std::cell::RefCell;
+use std::cmp;
+use std::mem;
+use std::ptr::{self, NonNull};
+
+use super::{Chunk, PAGE, Reap};
+
+// Storage for the objects allocated by `alloc_extend`.
+//
+// These are kept apart from the slots, which may be padded with guard words, in chunks of bare
+// `T`s. The objects of a `Reap` are never dropped, and so may not need dropping, only those of a
+// `BrandedReap`, which drops its extents along with itself.
+//
+// Regular chunks double in size, and the latest one is always last, as later extents go there.
+// An extent too large for the next regular chunk gets a dedicated chunk of its own instead, kept
+// before the current regular one, so that one outsized extent doesn't make all the chunks after it
+// outsized too.
+pub(super) struct Extents {
+    // Each chunk, with the number of objects initialized at its start.
+    chunks: Vec<(Chunk, usize)>,
+    // Capacity of the latest regular chunk, zero before the first.
+    regular: usize,
+    // Number of zero-sized objects allocated, which take no space in any chunk.
+    zsts: usize,
+}
+
+impl Extents {
+    #[inline]
+    pub(super) fn new() -> Extents {
+        Extents {
+            chunks: Vec::new(),
+            regular: 0,
+            zsts: 0,
+        }
+    }
+
+    // Moves the objects out of `items` into contiguous storage, returning a pointer to the first.
+    fn push<T>(&mut self, items: &mut Vec<T>) -> *mut T {
+        let len = items.len();
+        unsafe {
+            let dst = self.reserve::<T>(len);
+            ptr::copy_nonoverlapping(items.as_ptr(), dst, len);
+            items.set_len(0);
+            dst
+        }
+    }
+
+    // Moves every item of `iter` into contiguous storage in `extents`, returning them.
+    //
+    // The storage is never handed out twice, and lives as long as `extents`.
+    pub(super) fn extend<T, I>(extents: &RefCell<Extents>, iter: I) -> *mut [T]
+        where I: IntoIterator<Item = T>
+    {
+        // Collected up front, so that nothing is borrowed while the iterator runs.
+        let mut items: Vec<T> = iter.into_iter().collect();
+        if items.is_empty() {
+            return ptr::slice_from_raw_parts_mut(NonNull::dangling().as_ptr(), 0);
+        }
+        let len = items.len();
+        let ptr = extents.borrow_mut().push(&mut items);
+        ptr::slice_from_raw_parts_mut(ptr, len)
+    }
+
+    // Reserves room for `len` contiguous objects, returning a pointer to the first.
+    //
+    // The objects count as initialized from here on, the caller must write them right away.
+    unsafe fn reserve<T>(&mut self, len: usize) -> *mut T {
+        if mem::size_of::<T>() == 0 {
+            self.zsts += len;
+            return NonNull::dangling().as_ptr();
+        }
+        let fits = match self.chunks.last() {
+            Some((chunk, filled)) if self.regular != 0 => chunk.capacity() - filled >= len,
+            _ => false,
+        };
+        if !fits {
+            // Whatever room is left in the last chunk goes unused, slices can't straddle chunks.
+            let cap = match self.regular {
+                0 => cmp::max(PAGE / mem::size_of::<T>(), 1),
+                regular => regular.checked_mul(2).expect("capacity overflow"),
+            };
+            if len > cap {
+                let chunk = Chunk::new::<T>(len);
+                let dst = chunk.start::<T>();
+                let at = if self.regular == 0 {
+                    self.chunks.len()
+                } else {
+                    self.chunks.len() - 1
+                };
+                self.chunks.insert(at, (chunk, len));
+                return dst;
+            }
+            self.chunks.push((Chunk::new::<T>(cap), 0));
+            self.regular = cap;
+        }
+        let &mut (ref chunk, ref mut filled) = self.chunks.last_mut().unwrap();
+        let dst = chunk.start::<T>().add(*filled);
+        *filled += len;
+        dst
+    }
+
+    // Drops every object allocated so far, which must all be `T`s.
+    pub(super) unsafe fn drop_objects<T>(&mut self) {
+        for &mut (ref chunk, ref mut filled) in &mut self.chunks {
+            let objects = ptr::slice_from_raw_parts_mut(chunk.start::<T>(), *filled);
+            // Forget them first, so that a panicking destructor can't lead to a double drop.
+            *filled = 0;
+            ptr::drop_in_place(objects);
+        }
+        let zsts = ptr::slice_from_raw_parts_mut(NonNull::<T>::dangling().as_ptr(), self.zsts);
+        self.zsts = 0;
+        ptr::drop_in_place(zsts);
+    }
+}
+
+impl<T> Reap<T> {
+    /// Allocates every item of `iter` contiguously, returning them as a slice.
+    ///
+    /// Unlike `allocate`, this gives the objects no `Rp` each: they stay put until the arena is
+    /// dropped along with its last handle. This suits data that lives as long as the arena, like
+    /// the child lists of syntax tree nodes, where a handle per element would be pure overhead.
+    /// These objects aren't counted by `stats`.
+    ///
+    /// The objects are never dropped, their memory is just freed along with the arena, so only
+    /// types without any drop glue are accepted: allocating a `String` this way fails to compile
+    /// rather than leaking the string. A `Reap<T>` is covariant in `T`, and so may have been
+    /// handed objects that borrow from shorter-lived data than its own type says, which it
+    /// couldn't safely drop. Objects that need dropping belong in an `Rp` each, or in the branded
+    /// arena of `Reap::with`, whose `alloc_extend` does drop them.
+    ///
+    /// Extents are packed into chunks that double in size. One too large for the next chunk is
+    /// given a chunk of exactly its size instead, leaving the growth of the others unaffected.
+    ///
+    /// `iter` may itself allocate from this `Reap`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use reap::Reap;
+    ///
+    /// let reap = Reap::new();
+    /// let squares = reap.alloc_extend((1..5).map(|i| i * i));
+    /// squares[0] = 0;
+    /// assert_eq!(squares, [0, 4, 9, 16]);
+    /// ```
+    ///
+    /// ```compile_fail
+    /// use reap::Reap;
+    ///
+    /// let reap = Reap::new();
+    /// reap.alloc_extend(vec![String::from("leaked")]);
+    /// ```
+    #[allow(clippy::mut_from_ref)]
+    pub fn alloc_extend<I>(&self, iter: I) -> &mut [T]
+        where I: IntoIterator<Item = T>
+    {
+        const {
+            assert!(!mem::needs_drop::<T>(),
+                    "`Reap::alloc_extend` never drops its objects, use `BrandedReap::alloc_extend`")
+        };
+        // The extents live as long as the arena, which `self` keeps alive.
+        unsafe { &mut *Extents::extend(&self.0.extents, iter) }
+    }
+}
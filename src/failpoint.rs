@@ -0,0 +1,133 @@
+//! Injected allocation failures, for testing out-of-memory handling.
+//!
+//! Only available with the `failpoints` feature. Real allocation failures are hard to provoke on
+//! purpose, so the code paths handling them usually go untested. The functions in this module
+//! make the chunk allocations of every arena type in this crate fail on demand instead: the `n`th
+//! one from now, a random fraction of them, or all of them until `clear` is called.
+//!
+//! Failpoints are set per thread, and only affect chunk allocations made on the thread that set
+//! them, so tests running in parallel don't interfere with each other.
+//!
+//! A failed allocation behaves like the allocator returning null. Fallible APIs such as
+//! `Reap::try_with_capacity` return `AllocError::OutOfMemory`. Everything else would report the
+//! failure with `handle_alloc_error`, which aborts the process; for an injected failure they panic
+//! instead, so that tests can catch it and check the arena was left consistent.
+//!
+//! # Examples
+//!
+//! ```
+//! use reap::{AllocError, Reap};
+//! use reap::failpoint;
+//!
+//! failpoint::fail_nth(2);
+//! assert!(Reap::<u64>::try_with_capacity(16).is_ok());
+//! assert_eq!(Reap::<u64>::try_with_capacity(16).err(), Some(AllocError::OutOfMemory));
+//! assert!(Reap::<u64>::try_with_capacity(16).is_ok());
+//! assert_eq!(failpoint::injected(), 1);
+//! failpoint::clear();
+//! ```
+
+use std::alloc::Layout;
+use std::cell::Cell;
+
+// What the failpoint of a thread is set to.
+#[derive(Clone, Copy)]
+enum Mode {
+    Off,
+    // Fail the allocation this many allocations from now, counting the next one as 1.
+    Nth(usize),
+    // Fail each allocation with this probability, drawing from a xorshift generator.
+    Random(f64, u64),
+    Always,
+}
+
+thread_local! {
+    static MODE: Cell<Mode> = const { Cell::new(Mode::Off) };
+    static INJECTED: Cell<usize> = const { Cell::new(0) };
+    // Whether the latest allocation failure on this thread was injected.
+    static LAST_INJECTED: Cell<bool> = const { Cell::new(false) };
+}
+
+/// Makes the `n`th chunk allocation on this thread from now on fail, counting the next one as 1,
+/// and only that one.
+///
+/// # Panics
+///
+/// Panics if `n` is 0.
+pub fn fail_nth(n: usize) {
+    assert!(n != 0, "reap: failpoint allocations are counted from 1");
+    MODE.with(|mode| mode.set(Mode::Nth(n)));
+}
+
+/// Makes every chunk allocation on this thread fail with the given `probability`, a number
+/// between 0 and 1.
+///
+/// Failures are drawn from a pseudorandom sequence determined by `seed`, so a failing test can be
+/// replayed with the same seed.
+///
+/// # Panics
+///
+/// Panics if `probability` is not between 0 and 1.
+pub fn fail_randomly(probability: f64, seed: u64) {
+    assert!((0.0..=1.0).contains(&probability),
+            "reap: failpoint probability {} is not between 0 and 1",
+            probability);
+    // Xorshift never leaves the all-zero state.
+    let state = if seed == 0 { 0x9E37_79B9_7F4A_7C15 } else { seed };
+    MODE.with(|mode| mode.set(Mode::Random(probability, state)));
+}
+
+/// Makes every chunk allocation on this thread fail, until `clear` is called.
+pub fn fail_always() {
+    MODE.with(|mode| mode.set(Mode::Always));
+}
+
+/// Lets chunk allocations on this thread succeed again, and resets the count of `injected`.
+pub fn clear() {
+    MODE.with(|mode| mode.set(Mode::Off));
+    INJECTED.with(|injected| injected.set(0));
+}
+
+/// Returns the number of allocation failures injected on this thread since the last `clear`.
+pub fn injected() -> usize {
+    INJECTED.with(|injected| injected.get())
+}
+
+// Decides whether the chunk allocation about to be made fails, and counts it if so.
+pub(super) fn should_fail() -> bool {
+    let fail = MODE.with(|mode| {
+        match mode.get() {
+            Mode::Off => false,
+            Mode::Nth(1) => {
+                mode.set(Mode::Off);
+                true
+            }
+            Mode::Nth(n) => {
+                mode.set(Mode::Nth(n - 1));
+                false
+            }
+            Mode::Random(probability, mut state) => {
+                state ^= state << 13;
+                state ^= state >> 7;
+                state ^= state << 17;
+                mode.set(Mode::Random(probability, state));
+                // The top 53 bits, as a fraction in [0, 1).
+                ((state >> 11) as f64 / (1u64 << 53) as f64) < probability
+            }
+            Mode::Always => true,
+        }
+    });
+    if fail {
+        INJECTED.with(|injected| injected.set(injected.get() + 1));
+    }
+    LAST_INJECTED.with(|last| last.set(fail));
+    fail
+}
+
+// Panics if the allocation of `layout` that just failed was made to fail by a failpoint, rather
+// than by the allocator.
+pub(super) fn check_injected(layout: Layout) {
+    if LAST_INJECTED.with(|last| last.replace(false)) {
+        panic!("reap: injected failure allocating a chunk of {} bytes", layout.size());
+    }
+}
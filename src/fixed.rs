@@ -0,0 +1,231 @@
+//! A preallocated arena with constant worst-case allocate and free.
+//!
+//! A `Reap` is usually fast, but every so often an allocation has to grow it, which calls into
+//! the system allocator, and its bookkeeping goes through `RefCell`s. Audio callbacks and control
+//! loops can't afford the occasional slow path. A `FixedReap` allocates all of its storage up
+//! front and never again: allocating and freeing are each a handful of pointer operations on an
+//! intrusive freelist, with no borrow checks, no growth, and no way to reach the system allocator
+//! after construction. When it is full, allocation fails and hands the object back instead.
+//!
+//! # Examples
+//!
+//! ```
+//! use reap::fixed::{FixedReap, FixedRp};
+//!
+//! let voices = FixedReap::with_capacity(2);
+//! let a = voices.allocate(440.0f32);
+//! let b = voices.allocate(880.0);
+//! assert_eq!(voices.try_allocate(220.0).unwrap_err(), 220.0);
+//!
+//! drop(a);
+//! let c = voices.allocate(220.0);
+//! assert_eq!(*c + FixedRp::into_inner(b), 1100.0);
+//! ```
+
+use std::cell::Cell;
+use std::fmt;
+use std::mem::{self, ManuallyDrop};
+use std::ops::{Deref, DerefMut};
+use std::ptr::{self, NonNull};
+use std::rc::Rc;
+
+use super::{Chunk, Release};
+
+/// A fixed-capacity arena with constant-time allocation and release.
+///
+/// Cloning a `FixedReap` produces another handle to the same arena.
+pub struct FixedReap<T>(Rc<InnerFixedReap<T>>);
+
+struct InnerFixedReap<T> {
+    // All of the arena's storage, or `None` if its capacity is 0.
+    chunk: Option<Chunk>,
+    // Head of the freelist, threaded through the free slots themselves; null when full.
+    free: Cell<*mut FreeSlot<T>>,
+    live: Cell<usize>,
+}
+
+// A slot holds either a live object, or a link to the next free slot.
+union FreeSlot<T> {
+    value: ManuallyDrop<T>,
+    next: *mut FreeSlot<T>,
+}
+
+impl<T> FixedReap<T> {
+    /// Creates a new `FixedReap<T>` with room for exactly `capacity` objects.
+    ///
+    /// This is the only time the arena allocates. Every slot is written to here, so that the
+    /// pages backing them are already mapped when allocating from it later.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the required capacity overflows, and aborts if the allocation fails, like
+    /// `Vec::with_capacity`.
+    pub fn with_capacity(capacity: usize) -> FixedReap<T> {
+        let chunk = if capacity == 0 {
+            None
+        } else {
+            Some(Chunk::new::<FreeSlot<T>>(capacity))
+        };
+        let mut free = ptr::null_mut();
+        if let Some(ref chunk) = chunk {
+            // Linked back to front, so that slots are handed out in address order.
+            for i in (0..capacity).rev() {
+                unsafe {
+                    let slot = chunk.start::<FreeSlot<T>>().add(i);
+                    ptr::write(slot, FreeSlot { next: free });
+                    free = slot;
+                }
+            }
+        }
+        FixedReap(Rc::new(InnerFixedReap {
+            chunk,
+            free: Cell::new(free),
+            live: Cell::new(0),
+        }))
+    }
+
+    /// Allocates `object` in the arena.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the arena is full. Use `try_allocate` where that can't be ruled out.
+    #[inline]
+    pub fn allocate(&self, object: T) -> FixedRp<T> {
+        match self.try_allocate(object) {
+            Ok(rp) => rp,
+            Err(_) => panic!("FixedReap: all {} slots are in use", self.capacity()),
+        }
+    }
+
+    /// Allocates `object` in the arena, or gives it back if the arena is full.
+    #[inline]
+    pub fn try_allocate(&self, object: T) -> Result<FixedRp<T>, T> {
+        let slot = self.0.free.get();
+        if slot.is_null() {
+            return Err(object);
+        }
+        unsafe {
+            self.0.free.set((*slot).next);
+            ptr::write(slot, FreeSlot { value: ManuallyDrop::new(object) });
+            self.0.live.set(self.0.live.get() + 1);
+            Ok(FixedRp {
+                slot: NonNull::new_unchecked(slot),
+                reap: self.clone(),
+            })
+        }
+    }
+
+    /// Returns the number of objects the arena has room for.
+    #[inline]
+    pub fn capacity(&self) -> usize {
+        self.0.chunk.as_ref().map_or(0, Chunk::capacity)
+    }
+
+    /// Returns the number of live objects in the arena.
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.0.live.get()
+    }
+
+    /// Returns `true` if the arena holds no live objects.
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Returns `true` if every slot is in use, so that allocating would fail.
+    #[inline]
+    pub fn is_full(&self) -> bool {
+        self.0.free.get().is_null()
+    }
+
+    // Puts `slot`, whose object is gone, back on the freelist.
+    #[inline]
+    unsafe fn release(&self, slot: *mut FreeSlot<T>) {
+        self.0.live.set(self.0.live.get() - 1);
+        ptr::write(slot, FreeSlot { next: self.0.free.get() });
+        self.0.free.set(slot);
+    }
+}
+
+impl<T> Clone for FixedReap<T> {
+    #[inline]
+    fn clone(&self) -> FixedReap<T> {
+        FixedReap(self.0.clone())
+    }
+}
+
+impl<T> fmt::Debug for FixedReap<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("FixedReap")
+            .field("len", &self.len())
+            .field("capacity", &self.capacity())
+            .finish()
+    }
+}
+
+/// A handle to an object allocated in a `FixedReap`.
+///
+/// Like an `Rp`, it keeps its arena alive, and dropping it drops the object and frees its slot.
+pub struct FixedRp<T> {
+    slot: NonNull<FreeSlot<T>>,
+    reap: FixedReap<T>,
+}
+
+impl<T> FixedRp<T> {
+    /// Moves the object out of the arena, freeing its slot.
+    #[inline]
+    pub fn into_inner(this: FixedRp<T>) -> T {
+        let slot = this.slot.as_ptr();
+        // Move the arena out, and skip the destructor, which would drop the object.
+        let reap = unsafe { ptr::read(&this.reap) };
+        mem::forget(this);
+        unsafe {
+            let value = ManuallyDrop::into_inner(ptr::read(&(*slot).value));
+            reap.release(slot);
+            value
+        }
+    }
+
+    /// Returns a reference to this `FixedRp<T>`'s associated `FixedReap<T>`.
+    #[inline]
+    pub fn reap(&self) -> &FixedReap<T> {
+        &self.reap
+    }
+}
+
+impl<T> Deref for FixedRp<T> {
+    type Target = T;
+
+    #[inline]
+    fn deref(&self) -> &T {
+        unsafe { &(*self.slot.as_ptr()).value }
+    }
+}
+
+impl<T> DerefMut for FixedRp<T> {
+    #[inline]
+    fn deref_mut(&mut self) -> &mut T {
+        unsafe { &mut (*self.slot.as_ptr()).value }
+    }
+}
+
+impl<T> fmt::Debug for FixedRp<T>
+    where T: fmt::Debug
+{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Debug::fmt(&**self, f)
+    }
+}
+
+impl<T> Drop for FixedRp<T> {
+    fn drop(&mut self) {
+        // Released even if the object's destructor panics, but only once it has finished, so a
+        // destructor allocating from the arena can't be handed its own slot.
+        let (reap, slot) = (&self.reap, self.slot.as_ptr());
+        let _release = Release::new(move || unsafe { reap.release(slot) });
+        unsafe {
+            ManuallyDrop::drop(&mut (*slot).value);
+        }
+    }
+}
@@ -0,0 +1,158 @@
+//! A per-frame arena that catches handles outliving their frame.
+//!
+//! Game loops often allocate data that is only meant to be valid for the frame that produced it,
+//! and a pointer to last frame's data kept by mistake is a classic source of bugs. A `FrameReap`
+//! keeps a frame counter, advanced by `next_frame`, and tags every `FrameRp` with the frame it was
+//! allocated in. In debug builds, dereferencing a handle from an earlier frame panics, pointing at
+//! the bug where it happens rather than wherever the stale data ends up.
+//!
+//! # Examples
+//!
+//! ```
+//! use reap::frame::FrameReap;
+//!
+//! let frames = FrameReap::new();
+//! let a = frames.allocate(1);
+//! assert_eq!(*a, 1);
+//! drop(a);
+//!
+//! frames.next_frame();
+//! let b = frames.allocate(2);
+//! assert_eq!(b.frame(), 1);
+//! assert!(b.is_current());
+//! ```
+
+use std::cell::Cell;
+use std::fmt;
+use std::ops::{Deref, DerefMut};
+use std::rc::Rc;
+
+use super::{Reap, Rp};
+
+/// An arena whose handles are tagged with the frame they were allocated in.
+pub struct FrameReap<T> {
+    reap: Reap<T>,
+    // Index of the current frame, shared with every handle.
+    frame: Rc<Cell<u64>>,
+}
+
+impl<T> FrameReap<T> {
+    /// Creates a new `FrameReap<T>`, starting at frame 0.
+    #[inline]
+    pub fn new() -> FrameReap<T> {
+        FrameReap {
+            reap: Reap::new(),
+            frame: Rc::new(Cell::new(0)),
+        }
+    }
+
+    /// Allocates `object` in the current frame.
+    #[inline]
+    pub fn allocate(&self, object: T) -> FrameRp<T> {
+        FrameRp {
+            rp: self.reap.allocate(object),
+            frame: self.frame.get(),
+            current: self.frame.clone(),
+        }
+    }
+
+    /// Returns the index of the current frame.
+    #[inline]
+    pub fn frame(&self) -> u64 {
+        self.frame.get()
+    }
+
+    /// Ends the current frame and starts the next one, returning its index.
+    ///
+    /// Handles from earlier frames may still be dropped, but no longer dereferenced.
+    #[inline]
+    pub fn next_frame(&self) -> u64 {
+        let next = self.frame.get() + 1;
+        self.frame.set(next);
+        next
+    }
+
+    /// Returns a reference to the underlying `Reap`.
+    #[inline]
+    pub fn reap(&self) -> &Reap<T> {
+        &self.reap
+    }
+}
+
+impl<T> Default for FrameReap<T> {
+    #[inline]
+    fn default() -> FrameReap<T> {
+        FrameReap::new()
+    }
+}
+
+/// A handle to an object allocated in a given frame of a `FrameReap`.
+///
+/// # Panics
+///
+/// In debug builds, dereferencing a `FrameRp` after its frame has ended panics. Use `into_rp` to
+/// keep an object beyond its frame on purpose.
+pub struct FrameRp<T> {
+    rp: Rp<T>,
+    frame: u64,
+    current: Rc<Cell<u64>>,
+}
+
+impl<T> FrameRp<T> {
+    /// Returns the index of the frame this handle was allocated in.
+    #[inline]
+    pub fn frame(&self) -> u64 {
+        self.frame
+    }
+
+    /// Returns `true` if this handle's frame is still the current one.
+    #[inline]
+    pub fn is_current(&self) -> bool {
+        self.frame == self.current.get()
+    }
+
+    /// Converts the handle into a plain `Rp`, no longer tied to its frame.
+    #[inline]
+    pub fn into_rp(this: FrameRp<T>) -> Rp<T> {
+        this.rp
+    }
+
+    // Panics if this handle's frame has ended.
+    #[inline]
+    fn check(&self) {
+        #[cfg(debug_assertions)]
+        {
+            if !self.is_current() {
+                panic!("FrameRp: handle from frame {} used in frame {}",
+                       self.frame,
+                       self.current.get());
+            }
+        }
+    }
+}
+
+impl<T> Deref for FrameRp<T> {
+    type Target = T;
+
+    #[inline]
+    fn deref(&self) -> &T {
+        self.check();
+        &self.rp
+    }
+}
+
+impl<T> DerefMut for FrameRp<T> {
+    #[inline]
+    fn deref_mut(&mut self) -> &mut T {
+        self.check();
+        &mut self.rp
+    }
+}
+
+impl<T> fmt::Debug for FrameRp<T>
+    where T: fmt::Debug
+{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Debug::fmt(&**self, f)
+    }
+}
@@ -0,0 +1,82 @@
+// The stack of free slots of a `Reap`, kept inline until it grows past a few entries.
+
+use std::iter::Chain;
+use std::ptr;
+use std::slice;
+
+// Number of free pointers stored inline, before any are spilled to the heap.
+const INLINE: usize = 16;
+
+// A stack of pointers to free slots.
+//
+// A `Reap` that frees and reallocates a handful of objects at a time, the common case, never has
+// more than a few slots free at once. The bottom `INLINE` entries live in an array inside the
+// `Reap` itself, so that case never allocates a side vector; only the entries above them go to
+// `spill`.
+pub(super) struct FreeList {
+    inline: [*mut u8; INLINE],
+    // Number of entries in `inline`, only below `INLINE` while `spill` is empty.
+    len: usize,
+    // Entries above the inline ones, bottom first.
+    pub(super) spill: Vec<*mut u8>,
+}
+
+impl FreeList {
+    #[inline]
+    pub(super) fn new() -> FreeList {
+        FreeList {
+            inline: [ptr::null_mut(); INLINE],
+            len: 0,
+            spill: Vec::new(),
+        }
+    }
+
+    #[inline]
+    pub(super) fn push(&mut self, ptr: *mut u8) {
+        if self.len < INLINE {
+            self.inline[self.len] = ptr;
+            self.len += 1;
+        } else {
+            self.spill.push(ptr);
+        }
+    }
+
+    #[inline]
+    pub(super) fn pop(&mut self) -> Option<*mut u8> {
+        if let Some(ptr) = self.spill.pop() {
+            Some(ptr)
+        } else if self.len > 0 {
+            self.len -= 1;
+            Some(self.inline[self.len])
+        } else {
+            None
+        }
+    }
+
+    // Removes the entry `index` places from the bottom, keeping the others in order.
+    pub(super) fn remove(&mut self, index: usize) -> *mut u8 {
+        if index >= self.len {
+            return self.spill.remove(index - self.len);
+        }
+        let ptr = self.inline[index];
+        self.inline.copy_within(index + 1..self.len, index);
+        // The bottom entry of the spill moves down to keep the inline entries full.
+        if self.spill.is_empty() {
+            self.len -= 1;
+        } else {
+            self.inline[INLINE - 1] = self.spill.remove(0);
+        }
+        ptr
+    }
+
+    #[inline]
+    pub(super) fn len(&self) -> usize {
+        self.len + self.spill.len()
+    }
+
+    // Iterates over the entries from the bottom of the stack up, so that the last is reused first.
+    #[inline]
+    pub(super) fn iter<'a>(&'a self) -> Chain<slice::Iter<'a, *mut u8>, slice::Iter<'a, *mut u8>> {
+        self.inline[..self.len].iter().chain(self.spill.iter())
+    }
+}
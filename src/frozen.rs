@@ -0,0 +1,136 @@
+// Handles giving shared access only, for `Rp::freeze`.
+
+use std::borrow::Borrow;
+use std::cmp::Ordering;
+use std::fmt;
+use std::hash::{Hash, Hasher};
+use std::ops::Deref;
+
+use super::{Reap, Rp};
+
+/// An owning handle to an object that can no longer be mutated through it.
+///
+/// Created with `Rp::freeze`. A `Frozen<T>` still owns its slot, dropping the object along with
+/// the handle, but only implements `Deref`, not `DerefMut`, and there is no way back to a mutable
+/// `Rp<T>`. APIs can hand out frozen handles to objects that their callers must not change, without
+/// tying them to the lifetime of a `&T`.
+///
+/// # Examples
+///
+/// ```
+/// use reap::{Frozen, Reap, Rp};
+///
+/// let reap = Reap::new();
+/// let config: Frozen<String> = Rp::freeze(reap.allocate(String::from("release")));
+/// assert_eq!(config.len(), 7);
+/// ```
+///
+/// ```compile_fail
+/// use reap::{Reap, Rp};
+///
+/// let reap = Reap::new();
+/// let mut config = Rp::freeze(reap.allocate(String::from("release")));
+/// config.push_str("-debug");
+/// ```
+pub struct Frozen<T>(Rp<T>);
+
+impl<T> Rp<T> {
+    /// Converts this handle into one that only gives shared access to the object.
+    #[inline]
+    pub fn freeze(this: Rp<T>) -> Frozen<T> {
+        Frozen(this)
+    }
+}
+
+impl<T> Frozen<T> {
+    /// Returns a reference to this handle's associated `Reap<T>`.
+    #[inline]
+    pub fn reap(this: &Frozen<T>) -> &Reap<T> {
+        this.0.reap()
+    }
+}
+
+impl<T> Deref for Frozen<T> {
+    type Target = T;
+
+    #[inline]
+    fn deref(&self) -> &T {
+        &self.0
+    }
+}
+
+impl<T> AsRef<T> for Frozen<T> {
+    #[inline]
+    fn as_ref(&self) -> &T {
+        &self.0
+    }
+}
+
+impl<T> Borrow<T> for Frozen<T> {
+    #[inline]
+    fn borrow(&self) -> &T {
+        &self.0
+    }
+}
+
+impl<T> PartialEq for Frozen<T>
+    where T: PartialEq
+{
+    #[inline]
+    fn eq(&self, other: &Frozen<T>) -> bool {
+        self.0 == other.0
+    }
+}
+
+impl<T> Eq for Frozen<T> where T: Eq {}
+
+impl<T> PartialOrd for Frozen<T>
+    where T: PartialOrd
+{
+    #[inline]
+    fn partial_cmp(&self, other: &Frozen<T>) -> Option<Ordering> {
+        self.0.partial_cmp(&other.0)
+    }
+}
+
+impl<T> Ord for Frozen<T>
+    where T: Ord
+{
+    #[inline]
+    fn cmp(&self, other: &Frozen<T>) -> Ordering {
+        self.0.cmp(&other.0)
+    }
+}
+
+impl<T> Hash for Frozen<T>
+    where T: Hash
+{
+    #[inline]
+    fn hash<H>(&self, state: &mut H)
+        where H: Hasher
+    {
+        self.0.hash(state);
+    }
+}
+
+impl<T> fmt::Display for Frozen<T>
+    where T: fmt::Display
+{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Display::fmt(&self.0, f)
+    }
+}
+
+impl<T> fmt::Debug for Frozen<T>
+    where T: fmt::Debug
+{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Debug::fmt(&self.0, f)
+    }
+}
+
+impl<T> fmt::Pointer for Frozen<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Pointer::fmt(&self.0, f)
+    }
+}
@@ -0,0 +1,23 @@
+// Generation counters for tables that reuse their slots.
+//
+// The executor, `DensePool` and `TtlCache` all hand out `Copy` handles that name a slot by its
+// index, and recycle slots once they are vacated. Each slot carries a `Generation` that moves on
+// every time it is vacated, and each handle the generation it was issued under, so that a handle
+// outliving its value fails to match instead of naming whatever took the slot next.
+
+// The generation of a slot, or the one a handle was issued under.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub(super) struct Generation(u64);
+
+impl Generation {
+    #[inline]
+    pub(super) fn new() -> Generation {
+        Generation(0)
+    }
+
+    // Moves on to the next generation, leaving every handle issued under this one stale.
+    #[inline]
+    pub(super) fn bump(&mut self) {
+        self.0 = self.0.wrapping_add(1);
+    }
+}
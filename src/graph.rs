@@ -0,0 +1,734 @@
+//! A forest of trees whose nodes live in a `Reap`.
+//!
+//! Each node owns its first child and its next sibling through an `Rp`, so a node's handle owns
+//! its whole subtree. The edges the other way, to the parent, the previous sibling and the last
+//! child, are plain pointers that the `Graph` keeps valid, just like the back links of a `DList`.
+//! They never leave the module: nodes are reached through `NodeRef`s borrowed from the graph, or
+//! a `CursorMut`, so a back edge can't be followed to a node that is gone.
+//!
+//! A subtree can also be cut out of a graph whole, as a `Subtree` that owns its nodes, and grafted
+//! back in elsewhere, in the same graph or another. The `tree` module builds a single-rooted tree
+//! for widget hierarchies on top of this.
+
+use std::fmt;
+use std::marker;
+use std::ptr;
+
+use super::{Reap, Rp};
+
+// A single node in a `Graph`.
+struct Node<T> {
+    value: T,
+    // Owning links to the first child, `None` for a leaf, and to the next sibling, `None` for the
+    // last one.
+    first_child: Option<Rp<Node<T>>>,
+    next_sibling: Option<Rp<Node<T>>>,
+    // Back links to the parent, null for a root, to the previous sibling, null for the first one,
+    // and to the last child, null for a leaf.
+    parent: *mut Node<T>,
+    prev_sibling: *mut Node<T>,
+    last_child: *mut Node<T>,
+}
+
+// Returns a raw pointer to the node owned by `link`, or null.
+//
+// Taken straight from the `Rp`'s own pointer, for the same reason as in `dlist`: the back links
+// must stay valid across mutable borrows of the node through its `Rp`.
+#[inline]
+fn as_ptr<T>(link: &Option<Rp<Node<T>>>) -> *mut Node<T> {
+    match *link {
+        Some(ref node) => node.ptr.as_ptr(),
+        None => ptr::null_mut(),
+    }
+}
+
+// Returns the node following `node` in a depth-first, pre-order walk of the subtree rooted at
+// `root`, or null once the walk is over. With a null `root` the walk covers the whole forest.
+//
+// Going back up through the parent links, the walk needs no stack.
+unsafe fn next_preorder<T>(mut node: *const Node<T>, root: *const Node<T>) -> *const Node<T> {
+    let child = as_ptr(&(*node).first_child);
+    if !child.is_null() {
+        return child;
+    }
+    while node != root {
+        let sibling = as_ptr(&(*node).next_sibling);
+        if !sibling.is_null() {
+            return sibling;
+        }
+        node = (*node).parent;
+    }
+    ptr::null()
+}
+
+// Drops the chain of siblings starting at `link` along with all their descendants, returning how
+// many nodes that was.
+//
+// Each node's children are spliced into the chain ahead of its next sibling, so the drop glue
+// never recurses down the owning links, which would blow the stack on deep trees.
+fn drop_chain<T>(mut link: Option<Rp<Node<T>>>) -> usize {
+    let mut dropped = 0;
+    while let Some(mut node) = link {
+        link = node.next_sibling.take();
+        if let Some(child) = node.first_child.take() {
+            unsafe { (*node.last_child).next_sibling = link };
+            link = Some(child);
+        }
+        dropped += 1;
+    }
+    dropped
+}
+
+/// A forest of ordered trees with arena-allocated nodes.
+///
+/// Nodes are added and removed through a `CursorMut`, and read through `NodeRef`s, which can move
+/// to the parent, children and siblings of a node. Removing a node removes its subtree with it.
+///
+/// # Examples
+///
+/// ```
+/// use reap::graph::Graph;
+///
+/// let mut graph = Graph::new();
+/// {
+///     let mut cursor = graph.cursor_mut();
+///     cursor.push_child("root");
+///     cursor.move_to_first_child();
+///     cursor.push_child("a");
+///     cursor.push_child("b");
+///     cursor.move_to_first_child();
+///     cursor.push_child("a.1");
+/// }
+/// assert_eq!(graph.iter().cloned().collect::<Vec<_>>(), ["root", "a", "a.1", "b"]);
+///
+/// let leaf = graph.roots().next().unwrap().descendants().last().unwrap();
+/// assert_eq!(*leaf.value(), "b");
+/// assert_eq!(leaf.ancestors().map(|node| *node.value()).collect::<Vec<_>>(), ["root"]);
+/// ```
+pub struct Graph<T> {
+    reap: Reap<Node<T>>,
+    first_root: Option<Rp<Node<T>>>,
+    last_root: *mut Node<T>,
+    len: usize,
+}
+
+impl<T> Graph<T> {
+    /// Creates an empty `Graph<T>`.
+    #[inline]
+    pub fn new() -> Graph<T> {
+        Graph {
+            reap: Reap::new(),
+            first_root: None,
+            last_root: ptr::null_mut(),
+            len: 0,
+        }
+    }
+
+    /// Returns the number of nodes in the graph.
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Returns `true` if the graph contains no nodes.
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Adds a tree of a single node holding `value`, after the existing ones.
+    #[inline]
+    pub fn push_root(&mut self, value: T) {
+        unsafe { self.append(ptr::null_mut(), value) }
+    }
+
+    /// Returns an iterator over the roots of the trees, in order.
+    #[inline]
+    pub fn roots(&self) -> Siblings<'_, T> {
+        Siblings::new(as_ptr(&self.first_root))
+    }
+
+    /// Returns an iterator over references to the values of all nodes, tree by tree, each
+    /// depth-first with every node before its children.
+    #[inline]
+    pub fn iter(&self) -> Iter<'_, T> {
+        Iter {
+            node: as_ptr(&self.first_root),
+            len: self.len,
+            _marker: marker::PhantomData,
+        }
+    }
+
+    /// Returns a cursor positioned at the "ghost" node, which stands in for the parent of the
+    /// roots.
+    #[inline]
+    pub fn cursor_mut(&mut self) -> CursorMut<'_, T> {
+        CursorMut {
+            graph: self,
+            current: ptr::null_mut(),
+            top: ptr::null_mut(),
+        }
+    }
+
+    // Returns a cursor positioned at the first root that can't leave its tree, for `Tree`.
+    #[inline]
+    pub(super) fn root_cursor_mut(&mut self) -> CursorMut<'_, T> {
+        let root = as_ptr(&self.first_root);
+        CursorMut {
+            graph: self,
+            current: root,
+            top: root,
+        }
+    }
+
+    // Returns a mutable reference to the value of the first root, if any.
+    #[inline]
+    pub(super) fn first_root_mut(&mut self) -> Option<&mut T> {
+        self.first_root.as_mut().map(|root| &mut root.value)
+    }
+
+    // Returns the owning link to the first child of `parent` and the back link to its last one,
+    // or to the first and last root for a null `parent`.
+    unsafe fn children_of(&mut self,
+                          parent: *mut Node<T>)
+                          -> (*mut Option<Rp<Node<T>>>, *mut *mut Node<T>) {
+        if parent.is_null() {
+            (&mut self.first_root as *mut _, &mut self.last_root as *mut _)
+        } else {
+            (ptr::addr_of_mut!((*parent).first_child), ptr::addr_of_mut!((*parent).last_child))
+        }
+    }
+
+    // Links a new node holding `value` as the last child of `parent`, or as the last root for a
+    // null `parent`.
+    //
+    // `parent` must be null or a node of this graph.
+    unsafe fn append(&mut self, parent: *mut Node<T>, value: T) {
+        let node = self.reap.allocate(Node {
+            value,
+            first_child: None,
+            next_sibling: None,
+            parent: ptr::null_mut(),
+            prev_sibling: ptr::null_mut(),
+            last_child: ptr::null_mut(),
+        });
+        self.link(parent, node);
+        self.len += 1;
+    }
+
+    // Links the unlinked `node` as the last child of `parent`, or as the last root for a null
+    // `parent`, with its subtree. The caller accounts for the nodes in `len`.
+    //
+    // `parent` must be null or a node of this graph.
+    unsafe fn link(&mut self, parent: *mut Node<T>, node: Rp<Node<T>>) {
+        let (first, last) = self.children_of(parent);
+        let prev = *last;
+        let node = Some(node);
+        let this = as_ptr(&node);
+        (*this).parent = parent;
+        (*this).prev_sibling = prev;
+        *last = this;
+        if prev.is_null() {
+            *first = node;
+        } else {
+            (*prev).next_sibling = node;
+        }
+    }
+
+    // Unlinks `node` from its parent and siblings, returning its handle along with its subtree.
+    //
+    // `node` must be a non-null node of this graph.
+    unsafe fn unlink(&mut self, node: *mut Node<T>) -> Rp<Node<T>> {
+        let prev = (*node).prev_sibling;
+        let (first, last) = self.children_of((*node).parent);
+        let owner = if prev.is_null() {
+            first
+        } else {
+            ptr::addr_of_mut!((*prev).next_sibling)
+        };
+        let this = (*owner).take().expect("linked node without an owner");
+        let next = (*node).next_sibling.take();
+        match as_ptr(&next) {
+            next if next.is_null() => *last = prev,
+            next => (*next).prev_sibling = prev,
+        }
+        *owner = next;
+        (*node).parent = ptr::null_mut();
+        (*node).prev_sibling = ptr::null_mut();
+        this
+    }
+
+    // Removes `node` and its subtree, returning its value.
+    //
+    // `node` must be a non-null node of this graph.
+    unsafe fn remove(&mut self, node: *mut Node<T>) -> T {
+        let Node { value, first_child, .. } = Rp::take(self.unlink(node));
+        self.len -= 1 + drop_chain(first_child);
+        value
+    }
+
+    // Cuts `node` and its subtree out of the graph.
+    //
+    // `node` must be a non-null node of this graph.
+    unsafe fn detach(&mut self, node: *mut Node<T>) -> Subtree<T> {
+        let mut len = 0;
+        let mut next = node as *const Node<T>;
+        while !next.is_null() {
+            len += 1;
+            next = next_preorder(next, node);
+        }
+        self.len -= len;
+        Subtree {
+            root: Some(self.unlink(node)),
+            len,
+        }
+    }
+}
+
+impl<T> Drop for Graph<T> {
+    fn drop(&mut self) {
+        drop_chain(self.first_root.take());
+    }
+}
+
+impl<T> Default for Graph<T> {
+    #[inline]
+    fn default() -> Graph<T> {
+        Graph::new()
+    }
+}
+
+impl<T> fmt::Debug for Graph<T>
+    where T: fmt::Debug
+{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_list().entries(self.roots()).finish()
+    }
+}
+
+impl<'a, T> IntoIterator for &'a Graph<T> {
+    type Item = &'a T;
+    type IntoIter = Iter<'a, T>;
+
+    #[inline]
+    fn into_iter(self) -> Iter<'a, T> {
+        self.iter()
+    }
+}
+
+/// A shared reference to a node of a `Graph`, from which its relatives can be reached.
+pub struct NodeRef<'a, T: 'a> {
+    node: *const Node<T>,
+    _marker: marker::PhantomData<&'a T>,
+}
+
+impl<'a, T> NodeRef<'a, T> {
+    // Returns a reference to `node`, or `None` if it is null.
+    #[inline]
+    fn new(node: *const Node<T>) -> Option<NodeRef<'a, T>> {
+        if node.is_null() {
+            None
+        } else {
+            Some(NodeRef {
+                node,
+                _marker: marker::PhantomData,
+            })
+        }
+    }
+
+    /// Returns a reference to the value of the node.
+    #[inline]
+    pub fn value(&self) -> &'a T {
+        unsafe { &(*self.node).value }
+    }
+
+    /// Returns the parent of the node, or `None` for a root.
+    #[inline]
+    pub fn parent(&self) -> Option<NodeRef<'a, T>> {
+        NodeRef::new(unsafe { (*self.node).parent })
+    }
+
+    /// Returns the first child of the node, if any.
+    #[inline]
+    pub fn first_child(&self) -> Option<NodeRef<'a, T>> {
+        NodeRef::new(unsafe { as_ptr(&(*self.node).first_child) })
+    }
+
+    /// Returns the last child of the node, if any.
+    #[inline]
+    pub fn last_child(&self) -> Option<NodeRef<'a, T>> {
+        NodeRef::new(unsafe { (*self.node).last_child })
+    }
+
+    /// Returns the sibling after the node, if any.
+    #[inline]
+    pub fn next_sibling(&self) -> Option<NodeRef<'a, T>> {
+        NodeRef::new(unsafe { as_ptr(&(*self.node).next_sibling) })
+    }
+
+    /// Returns the sibling before the node, if any.
+    #[inline]
+    pub fn prev_sibling(&self) -> Option<NodeRef<'a, T>> {
+        NodeRef::new(unsafe { (*self.node).prev_sibling })
+    }
+
+    /// Returns an iterator over the children of the node, in order.
+    #[inline]
+    pub fn children(&self) -> Siblings<'a, T> {
+        Siblings::new(unsafe { as_ptr(&(*self.node).first_child) })
+    }
+
+    /// Returns an iterator over the ancestors of the node, from its parent up to its root.
+    #[inline]
+    pub fn ancestors(&self) -> Ancestors<'a, T> {
+        Ancestors(self.parent())
+    }
+
+    /// Returns an iterator over the subtree rooted at the node, depth-first with every node before
+    /// its children, starting with the node itself.
+    #[inline]
+    pub fn descendants(&self) -> Descendants<'a, T> {
+        Descendants {
+            node: self.node,
+            root: self.node,
+            _marker: marker::PhantomData,
+        }
+    }
+}
+
+impl<'a, T> Clone for NodeRef<'a, T> {
+    #[inline]
+    fn clone(&self) -> NodeRef<'a, T> {
+        *self
+    }
+}
+
+impl<'a, T> Copy for NodeRef<'a, T> {}
+
+impl<'a, T> fmt::Debug for NodeRef<'a, T>
+    where T: fmt::Debug
+{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mut tuple = f.debug_tuple("Node");
+        tuple.field(self.value());
+        for child in self.children() {
+            tuple.field(&child);
+        }
+        tuple.finish()
+    }
+}
+
+/// A subtree cut out of a `Graph` by `CursorMut::detach_current`, owning its nodes.
+///
+/// `CursorMut::push_subtree` grafts it back in, into the same graph or another one. Dropping it
+/// drops every node of the subtree instead, without recursing however deep it is.
+pub struct Subtree<T> {
+    // `None` only once grafted.
+    root: Option<Rp<Node<T>>>,
+    len: usize,
+}
+
+#[allow(clippy::len_without_is_empty)]
+impl<T> Subtree<T> {
+    /// Returns the number of nodes in the subtree, its root included.
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Returns a reference to the root of the subtree.
+    #[inline]
+    pub fn root(&self) -> NodeRef<'_, T> {
+        NodeRef::new(as_ptr(&self.root)).expect("grafted subtree")
+    }
+
+    /// Returns a mutable reference to the value of the root of the subtree.
+    #[inline]
+    pub fn root_mut(&mut self) -> &mut T {
+        &mut self.root.as_mut().expect("grafted subtree").value
+    }
+}
+
+impl<T> Drop for Subtree<T> {
+    fn drop(&mut self) {
+        drop_chain(self.root.take());
+    }
+}
+
+impl<T> fmt::Debug for Subtree<T>
+    where T: fmt::Debug
+{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Debug::fmt(&self.root(), f)
+    }
+}
+
+/// An iterator over references to the values of the nodes of a `Graph`.
+pub struct Iter<'a, T: 'a> {
+    node: *const Node<T>,
+    len: usize,
+    _marker: marker::PhantomData<&'a T>,
+}
+
+impl<'a, T> Iterator for Iter<'a, T> {
+    type Item = &'a T;
+
+    #[inline]
+    fn next(&mut self) -> Option<&'a T> {
+        if self.node.is_null() {
+            None
+        } else {
+            unsafe {
+                let node = self.node;
+                self.node = next_preorder(node, ptr::null());
+                self.len -= 1;
+                Some(&(*node).value)
+            }
+        }
+    }
+
+    #[inline]
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.len, Some(self.len))
+    }
+}
+
+impl<'a, T> ExactSizeIterator for Iter<'a, T> {}
+
+/// An iterator over a run of sibling nodes of a `Graph`.
+pub struct Siblings<'a, T: 'a>(Option<NodeRef<'a, T>>);
+
+impl<'a, T> Siblings<'a, T> {
+    #[inline]
+    fn new(first: *const Node<T>) -> Siblings<'a, T> {
+        Siblings(NodeRef::new(first))
+    }
+}
+
+impl<'a, T> Iterator for Siblings<'a, T> {
+    type Item = NodeRef<'a, T>;
+
+    #[inline]
+    fn next(&mut self) -> Option<NodeRef<'a, T>> {
+        let node = self.0?;
+        self.0 = node.next_sibling();
+        Some(node)
+    }
+}
+
+/// An iterator over the ancestors of a node of a `Graph`.
+pub struct Ancestors<'a, T: 'a>(Option<NodeRef<'a, T>>);
+
+impl<'a, T> Iterator for Ancestors<'a, T> {
+    type Item = NodeRef<'a, T>;
+
+    #[inline]
+    fn next(&mut self) -> Option<NodeRef<'a, T>> {
+        let node = self.0?;
+        self.0 = node.parent();
+        Some(node)
+    }
+}
+
+/// A depth-first iterator over a subtree of a `Graph`.
+pub struct Descendants<'a, T: 'a> {
+    // Null once the walk is over.
+    node: *const Node<T>,
+    root: *const Node<T>,
+    _marker: marker::PhantomData<&'a T>,
+}
+
+impl<'a, T> Iterator for Descendants<'a, T> {
+    type Item = NodeRef<'a, T>;
+
+    #[inline]
+    fn next(&mut self) -> Option<NodeRef<'a, T>> {
+        let node = NodeRef::new(self.node)?;
+        self.node = unsafe { next_preorder(self.node, self.root) };
+        Some(node)
+    }
+}
+
+/// A cursor over a `Graph` that can add and remove nodes.
+///
+/// A cursor always rests either on a node or on the "ghost" node, which stands in for the parent
+/// of the roots: moving to the first child of the ghost moves to the first root, pushing a child
+/// onto it adds a root, and moving to the parent of a root moves back to the ghost.
+///
+/// # Examples
+///
+/// ```
+/// use reap::graph::Graph;
+///
+/// let mut graph = Graph::new();
+/// let mut cursor = graph.cursor_mut();
+/// for i in 0..3 {
+///     cursor.push_child(i);
+///     cursor.move_to_last_child();
+/// }
+/// // Removing the middle node takes its child along, and leaves the cursor at its parent.
+/// cursor.move_to_parent();
+/// assert_eq!(cursor.remove_current(), Some(1));
+/// assert_eq!(cursor.current(), Some(&mut 0));
+/// assert_eq!(graph.len(), 1);
+/// ```
+///
+/// The cursor of a `Tree` rests on its root instead of the ghost, and never leaves it: moving to
+/// the parent of the root fails, and the root can't be removed.
+pub struct CursorMut<'a, T: 'a> {
+    graph: &'a mut Graph<T>,
+    // Null for the ghost node.
+    current: *mut Node<T>,
+    // The node the cursor can't move above, null for the ghost node.
+    top: *mut Node<T>,
+}
+
+impl<'a, T> CursorMut<'a, T> {
+    /// Returns a mutable reference to the value of the node under the cursor, or `None` at the
+    /// ghost node.
+    #[inline]
+    pub fn current(&mut self) -> Option<&mut T> {
+        if self.current.is_null() {
+            None
+        } else {
+            unsafe { Some(&mut (*self.current).value) }
+        }
+    }
+
+    /// Returns a shared reference to the node under the cursor, or `None` at the ghost node.
+    #[inline]
+    pub fn as_node_ref(&self) -> Option<NodeRef<'_, T>> {
+        NodeRef::new(self.current)
+    }
+
+    // Moves the cursor to `node` unless it is null, returning whether it moved.
+    #[inline]
+    fn move_to(&mut self, node: *mut Node<T>) -> bool {
+        if node.is_null() {
+            false
+        } else {
+            self.current = node;
+            true
+        }
+    }
+
+    /// Moves the cursor to the parent of the node under it, returning `false` and staying put at
+    /// the ghost node, or at the root of a `Tree`.
+    #[inline]
+    pub fn move_to_parent(&mut self) -> bool {
+        if self.current == self.top {
+            false
+        } else {
+            self.current = unsafe { (*self.current).parent };
+            true
+        }
+    }
+
+    /// Moves the cursor to the first child of the node under it, returning `false` and staying
+    /// put if there is none.
+    #[inline]
+    pub fn move_to_first_child(&mut self) -> bool {
+        let child = if self.current.is_null() {
+            as_ptr(&self.graph.first_root)
+        } else {
+            unsafe { as_ptr(&(*self.current).first_child) }
+        };
+        self.move_to(child)
+    }
+
+    /// Moves the cursor to the last child of the node under it, returning `false` and staying put
+    /// if there is none.
+    #[inline]
+    pub fn move_to_last_child(&mut self) -> bool {
+        let child = if self.current.is_null() {
+            self.graph.last_root
+        } else {
+            unsafe { (*self.current).last_child }
+        };
+        self.move_to(child)
+    }
+
+    /// Moves the cursor to the next sibling of the node under it, returning `false` and staying
+    /// put if there is none.
+    #[inline]
+    pub fn move_to_next_sibling(&mut self) -> bool {
+        let sibling = if self.current.is_null() {
+            ptr::null_mut()
+        } else {
+            unsafe { as_ptr(&(*self.current).next_sibling) }
+        };
+        self.move_to(sibling)
+    }
+
+    /// Moves the cursor to the previous sibling of the node under it, returning `false` and
+    /// staying put if there is none.
+    #[inline]
+    pub fn move_to_prev_sibling(&mut self) -> bool {
+        let sibling = if self.current.is_null() {
+            ptr::null_mut()
+        } else {
+            unsafe { (*self.current).prev_sibling }
+        };
+        self.move_to(sibling)
+    }
+
+    /// Adds a node holding `value` as the last child of the node under the cursor, or as the last
+    /// root at the ghost node. The cursor stays where it is.
+    #[inline]
+    pub fn push_child(&mut self, value: T) {
+        unsafe { self.graph.append(self.current, value) }
+    }
+
+    /// Adds `subtree` as the last child of the node under the cursor, or as the last tree at the
+    /// ghost node. The cursor stays where it is.
+    pub fn push_subtree(&mut self, mut subtree: Subtree<T>) {
+        let root = subtree.root.take().expect("grafted subtree");
+        unsafe { self.graph.link(self.current, root) }
+        self.graph.len += subtree.len;
+    }
+
+    /// Removes the node under the cursor along with its subtree, returning its value and moving
+    /// the cursor to its parent.
+    ///
+    /// Returns `None` and does nothing at the ghost node, or at the root of a `Tree`.
+    pub fn remove_current(&mut self) -> Option<T> {
+        if self.current == self.top {
+            None
+        } else {
+            unsafe {
+                let node = self.current;
+                self.current = (*node).parent;
+                Some(self.graph.remove(node))
+            }
+        }
+    }
+
+    /// Cuts the node under the cursor out of the graph along with its subtree, returning them and
+    /// moving the cursor to its parent.
+    ///
+    /// Returns `None` and does nothing at the ghost node, or at the root of a `Tree`.
+    pub fn detach_current(&mut self) -> Option<Subtree<T>> {
+        if self.current == self.top {
+            None
+        } else {
+            unsafe {
+                let node = self.current;
+                self.current = (*node).parent;
+                Some(self.graph.detach(node))
+            }
+        }
+    }
+
+    /// Removes every child of the node under the cursor, with their subtrees, returning how many
+    /// nodes that was. At the ghost node this removes every tree.
+    pub fn remove_children(&mut self) -> usize {
+        unsafe {
+            let (first, last) = self.graph.children_of(self.current);
+            let children = (*first).take();
+            *last = ptr::null_mut();
+            let removed = drop_chain(children);
+            self.graph.len -= removed;
+            removed
+        }
+    }
+}
@@ -0,0 +1,816 @@
+//! Intrusive linked lists and search trees over arena-allocated objects.
+//!
+//! An intrusive list threads its links through the objects themselves: a type embeds one `Link`
+//! per list it can belong to, and an `Adapter` tells the list how to get from an object to its
+//! link and back. Linking an object costs no allocation at all, and an object can be unlinked in
+//! `O(1)` given only a reference to it. A `SearchTree` does the same with a `TreeLink` and a
+//! `TreeAdapter`, which also tells it the key to order the objects by.
+//!
+//! This relies on objects never moving while linked; a `Reap` slot never moves for as long as its
+//! `Rp` lives, and a `LinkedList` or `SearchTree` owns the `Rp` of every object in it.
+//!
+//! # Examples
+//!
+//! ```
+//! #[macro_use]
+//! extern crate reap;
+//!
+//! use reap::Reap;
+//! use reap::intrusive::{Link, LinkedList};
+//!
+//! struct Task {
+//!     id: u32,
+//!     link: Link,
+//! }
+//!
+//! intrusive_adapter!(TaskAdapter = Task { link });
+//!
+//! fn main() {
+//!     let reap = Reap::new();
+//!     let mut queue = LinkedList::<TaskAdapter>::new(&reap);
+//!
+//!     for id in 0..3 {
+//!         queue.push_back(reap.allocate(Task { id, link: Link::new() }));
+//!     }
+//!
+//!     assert_eq!(queue.iter().map(|t| t.id).collect::<Vec<_>>(), vec![0, 1, 2]);
+//!     assert_eq!(queue.pop_front().map(|t| t.id), Some(0));
+//! }
+//! ```
+
+use std::borrow::Borrow;
+use std::cell::Cell;
+use std::cmp::Ordering;
+use std::marker;
+use std::ptr::{self, NonNull};
+use std::rc::Rc;
+
+use super::{Reap, Rp};
+
+// Returns a pointer to `link`, a field of the object at `object`, carrying the provenance of
+// `object`.
+//
+// Adapters hand out links as references, which are only good for the link itself. The pointers the
+// collections keep must be good for the whole object, so that it can be recovered from them, and
+// its `Rp` rebuilt.
+#[inline]
+unsafe fn field_ptr<V, L>(object: *const V, link: &L) -> *const L {
+    let offset = link as *const L as usize - object as usize;
+    (object as *const u8).add(offset) as *const L
+}
+
+// Returns a reference to the object linked by `link`, or `None` for null.
+//
+// `link` must be a pointer kept by a list, see `field_ptr`, and not one made from a `&Link`.
+#[inline]
+unsafe fn object<'a, A>(link: *const Link) -> Option<&'a A::Value>
+    where A: Adapter
+{
+    if link.is_null() {
+        None
+    } else {
+        Some(&*A::get_value(link))
+    }
+}
+
+// Marker stored in `Link::next` while the link is not on any list.
+#[inline]
+fn unlinked() -> *const Link {
+    NonNull::dangling().as_ptr()
+}
+
+/// A link embedded in an object that can be placed on a `LinkedList`.
+pub struct Link {
+    prev: Cell<*const Link>,
+    next: Cell<*const Link>,
+}
+
+impl Link {
+    /// Creates a new, unlinked `Link`.
+    #[inline]
+    pub fn new() -> Link {
+        Link {
+            prev: Cell::new(ptr::null()),
+            next: Cell::new(unlinked()),
+        }
+    }
+
+    /// Returns `true` if this link is currently on a list.
+    #[inline]
+    pub fn is_linked(&self) -> bool {
+        self.next.get() != unlinked()
+    }
+}
+
+impl Default for Link {
+    #[inline]
+    fn default() -> Link {
+        Link::new()
+    }
+}
+
+/// Maps between an object and one of its embedded `Link`s.
+///
+/// Usually implemented with the `intrusive_adapter!` macro.
+///
+/// # Safety
+///
+/// `get_value` must be the exact inverse of `get_link`: given a pointer to the link returned by
+/// `get_link(value)`, it must return a pointer to `value`.
+pub unsafe trait Adapter {
+    /// The type of object threaded onto the list.
+    type Value;
+
+    /// Returns the link in `value` used by this adapter.
+    fn get_link(value: &Self::Value) -> &Link;
+
+    /// Returns a pointer to the object containing the given link.
+    ///
+    /// # Safety
+    ///
+    /// `link` must point to the link of a live `Self::Value`.
+    unsafe fn get_value(link: *const Link) -> *const Self::Value;
+}
+
+/// Defines a unit struct implementing `Adapter` for the named `Link` field of a type.
+///
+/// ```ignore
+/// intrusive_adapter!(pub MyAdapter = MyType { link_field });
+/// ```
+#[macro_export]
+macro_rules! intrusive_adapter {
+    ($vis:vis $name:ident = $value:ty { $field:ident }) => {
+        $vis struct $name;
+
+        unsafe impl $crate::intrusive::Adapter for $name {
+            type Value = $value;
+
+            #[inline]
+            fn get_link(value: &$value) -> &$crate::intrusive::Link {
+                &value.$field
+            }
+
+            #[inline]
+            unsafe fn get_value(link: *const $crate::intrusive::Link) -> *const $value {
+                (link as *const u8).sub(::std::mem::offset_of!($value, $field)) as *const $value
+            }
+        }
+    };
+}
+
+/// A doubly-linked intrusive list owning objects allocated in a single `Reap`.
+pub struct LinkedList<A>
+    where A: Adapter
+{
+    reap: Reap<A::Value>,
+    head: *const Link,
+    tail: *const Link,
+    len: usize,
+    _marker: marker::PhantomData<A>,
+}
+
+impl<A> LinkedList<A>
+    where A: Adapter
+{
+    /// Creates an empty list for objects allocated in `reap`.
+    #[inline]
+    pub fn new(reap: &Reap<A::Value>) -> LinkedList<A> {
+        LinkedList {
+            reap: reap.clone(),
+            head: ptr::null(),
+            tail: ptr::null(),
+            len: 0,
+            _marker: marker::PhantomData,
+        }
+    }
+
+    /// Returns the number of objects on the list.
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Returns `true` if the list is empty.
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Returns a reference to the first object, if any.
+    #[inline]
+    pub fn front(&self) -> Option<&A::Value> {
+        unsafe { object::<A>(self.head) }
+    }
+
+    /// Returns a reference to the last object, if any.
+    #[inline]
+    pub fn back(&self) -> Option<&A::Value> {
+        unsafe { object::<A>(self.tail) }
+    }
+
+    /// Links `object` at the front of the list, taking ownership of it.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `object` was not allocated in this list's `Reap`, or if its link is already on a
+    /// list.
+    pub fn push_front(&mut self, object: Rp<A::Value>) {
+        let link = self.adopt(object);
+        unsafe {
+            (*link).next.set(self.head);
+            match self.head.as_ref() {
+                Some(head) => head.prev.set(link),
+                None => self.tail = link,
+            }
+        }
+        self.head = link;
+    }
+
+    /// Links `object` at the back of the list, taking ownership of it.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `object` was not allocated in this list's `Reap`, or if its link is already on a
+    /// list.
+    pub fn push_back(&mut self, object: Rp<A::Value>) {
+        let link = self.adopt(object);
+        unsafe {
+            (*link).prev.set(self.tail);
+            match self.tail.as_ref() {
+                Some(tail) => tail.next.set(link),
+                None => self.head = link,
+            }
+        }
+        self.tail = link;
+    }
+
+    /// Unlinks the first object and returns ownership of it.
+    #[inline]
+    pub fn pop_front(&mut self) -> Option<Rp<A::Value>> {
+        let head = self.head;
+        if head.is_null() {
+            None
+        } else {
+            unsafe { Some(self.unlink(head)) }
+        }
+    }
+
+    /// Unlinks the last object and returns ownership of it.
+    #[inline]
+    pub fn pop_back(&mut self) -> Option<Rp<A::Value>> {
+        let tail = self.tail;
+        if tail.is_null() {
+            None
+        } else {
+            unsafe { Some(self.unlink(tail)) }
+        }
+    }
+
+    /// Unlinks `object` from the list in `O(1)` and returns ownership of it.
+    ///
+    /// # Safety
+    ///
+    /// `object` must currently be on this list.
+    #[inline]
+    pub unsafe fn remove(&mut self, object: &A::Value) -> Rp<A::Value> {
+        // The list's own pointer to the link, rather than one derived from `object`.
+        let link = match A::get_link(object).prev.get().as_ref() {
+            Some(prev) => prev.next.get(),
+            None => self.head,
+        };
+        self.unlink(link)
+    }
+
+    /// Returns a front-to-back iterator over the objects on the list.
+    #[inline]
+    pub fn iter(&self) -> Iter<'_, A> {
+        Iter {
+            link: self.head,
+            _marker: marker::PhantomData,
+        }
+    }
+
+    // Takes ownership of `object`'s slot, returning its link.
+    fn adopt(&mut self, object: Rp<A::Value>) -> *const Link {
+        assert!(Rc::ptr_eq(&object.reap().0, &self.reap.0),
+                "object was not allocated in this list's reap");
+        assert!(!A::get_link(&object).is_linked(), "object is already linked");
+
+        // The list's own `Reap` handle stands in for the one inside the `Rp`.
+        let (ptr, _) = Rp::into_parts(object);
+        self.len += 1;
+        unsafe {
+            let link = field_ptr(ptr, A::get_link(&*ptr));
+            (*link).prev.set(ptr::null());
+            (*link).next.set(ptr::null());
+            link
+        }
+    }
+
+    // Unlinks `link` and reconstitutes the `Rp` of its object.
+    //
+    // `link` must be on this list.
+    unsafe fn unlink(&mut self, link: *const Link) -> Rp<A::Value> {
+        let prev = (*link).prev.get();
+        let next = (*link).next.get();
+        match prev.as_ref() {
+            Some(prev) => prev.next.set(next),
+            None => self.head = next,
+        }
+        match next.as_ref() {
+            Some(next) => next.prev.set(prev),
+            None => self.tail = prev,
+        }
+        (*link).prev.set(ptr::null());
+        (*link).next.set(unlinked());
+        self.len -= 1;
+        Rp::from_parts(A::get_value(link) as *mut A::Value, self.reap.clone())
+    }
+}
+
+impl<A> Drop for LinkedList<A>
+    where A: Adapter
+{
+    fn drop(&mut self) {
+        while self.pop_front().is_some() {}
+    }
+}
+
+/// An iterator over the objects on a `LinkedList`.
+pub struct Iter<'a, A>
+    where A: Adapter + 'a
+{
+    link: *const Link,
+    _marker: marker::PhantomData<&'a LinkedList<A>>,
+}
+
+impl<'a, A> Iterator for Iter<'a, A>
+    where A: Adapter + 'a
+{
+    type Item = &'a A::Value;
+
+    #[inline]
+    fn next(&mut self) -> Option<&'a A::Value> {
+        unsafe {
+            let value = object::<A>(self.link)?;
+            self.link = (*self.link).next.get();
+            Some(value)
+        }
+    }
+}
+
+// Returns a reference to the object linked by `link`, or `None` for null.
+//
+// `link` must be a pointer kept by a tree, see `field_ptr`, and not one made from a `&TreeLink`.
+#[inline]
+unsafe fn tree_object<'a, A>(link: *const TreeLink) -> Option<&'a A::Value>
+    where A: TreeAdapter
+{
+    if link.is_null() {
+        None
+    } else {
+        Some(&*A::get_value(link))
+    }
+}
+
+// Marker stored in `TreeLink::parent` while the link is not in any tree.
+#[inline]
+fn unlinked_tree() -> *const TreeLink {
+    NonNull::dangling().as_ptr()
+}
+
+/// A link embedded in an object that can be placed in a `SearchTree`.
+pub struct TreeLink {
+    parent: Cell<*const TreeLink>,
+    left: Cell<*const TreeLink>,
+    right: Cell<*const TreeLink>,
+}
+
+impl TreeLink {
+    /// Creates a new, unlinked `TreeLink`.
+    #[inline]
+    pub fn new() -> TreeLink {
+        TreeLink {
+            parent: Cell::new(unlinked_tree()),
+            left: Cell::new(ptr::null()),
+            right: Cell::new(ptr::null()),
+        }
+    }
+
+    /// Returns `true` if this link is currently in a tree.
+    #[inline]
+    pub fn is_linked(&self) -> bool {
+        self.parent.get() != unlinked_tree()
+    }
+}
+
+impl Default for TreeLink {
+    #[inline]
+    fn default() -> TreeLink {
+        TreeLink::new()
+    }
+}
+
+/// Maps between an object, one of its embedded `TreeLink`s, and the key it is ordered by.
+///
+/// Usually implemented with the `intrusive_tree_adapter!` macro.
+///
+/// # Safety
+///
+/// `get_value` must be the exact inverse of `get_link`: given a pointer to the link returned by
+/// `get_link(value)`, it must return a pointer to `value`. `get_key` must keep returning an equal
+/// key for as long as the object is in a tree.
+pub unsafe trait TreeAdapter {
+    /// The type of object threaded onto the tree.
+    type Value;
+
+    /// The type of key the tree is ordered by.
+    type Key: Ord;
+
+    /// Returns the link in `value` used by this adapter.
+    fn get_link(value: &Self::Value) -> &TreeLink;
+
+    /// Returns a pointer to the object containing the given link.
+    ///
+    /// # Safety
+    ///
+    /// `link` must point to the link of a live `Self::Value`.
+    unsafe fn get_value(link: *const TreeLink) -> *const Self::Value;
+
+    /// Returns the key of `value`.
+    fn get_key(value: &Self::Value) -> &Self::Key;
+}
+
+/// Defines a unit struct implementing `TreeAdapter` for the named `TreeLink` field of a type,
+/// ordering objects by another of its fields.
+///
+/// ```ignore
+/// intrusive_tree_adapter!(pub MyAdapter = MyType { link_field } by key_field: KeyType);
+/// ```
+#[macro_export]
+macro_rules! intrusive_tree_adapter {
+    ($vis:vis $name:ident = $value:ty { $field:ident } by $key:ident: $key_ty:ty) => {
+        $vis struct $name;
+
+        unsafe impl $crate::intrusive::TreeAdapter for $name {
+            type Value = $value;
+            type Key = $key_ty;
+
+            #[inline]
+            fn get_link(value: &$value) -> &$crate::intrusive::TreeLink {
+                &value.$field
+            }
+
+            #[inline]
+            unsafe fn get_value(link: *const $crate::intrusive::TreeLink) -> *const $value {
+                (link as *const u8).sub(::std::mem::offset_of!($value, $field)) as *const $value
+            }
+
+            #[inline]
+            fn get_key(value: &$value) -> &$key_ty {
+                &value.$key
+            }
+        }
+    };
+}
+
+// Returns the heap priority of the node at `link`, a hash of its address.
+//
+// The tree is a treap: kept in heap order of these, it is shaped as if its nodes had been inserted
+// in random order, whatever the order of their keys, and so balanced with high probability.
+#[inline]
+fn priority(link: *const TreeLink) -> u64 {
+    // The finalizer of SplitMix64.
+    let mut x = link as usize as u64;
+    x = (x ^ (x >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    x = (x ^ (x >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    x ^ (x >> 31)
+}
+
+// Returns the leftmost node of the subtree at `link`, or null for an empty one.
+#[inline]
+unsafe fn leftmost(mut link: *const TreeLink) -> *const TreeLink {
+    while let Some(node) = link.as_ref() {
+        match node.left.get() {
+            left if left.is_null() => break,
+            left => link = left,
+        }
+    }
+    link
+}
+
+// Returns the rightmost node of the subtree at `link`, or null for an empty one.
+#[inline]
+unsafe fn rightmost(mut link: *const TreeLink) -> *const TreeLink {
+    while let Some(node) = link.as_ref() {
+        match node.right.get() {
+            right if right.is_null() => break,
+            right => link = right,
+        }
+    }
+    link
+}
+
+/// An intrusive binary search tree owning objects allocated in a single `Reap`.
+///
+/// Objects are kept in order of their keys, those with equal keys in the order they were inserted.
+/// Inserting, finding and removing take `O(log n)` with high probability; the tree doesn't
+/// allocate, and it never moves the objects.
+///
+/// # Examples
+///
+/// ```
+/// #[macro_use]
+/// extern crate reap;
+///
+/// use reap::Reap;
+/// use reap::intrusive::{SearchTree, TreeLink};
+///
+/// struct Timer {
+///     deadline: u64,
+///     link: TreeLink,
+/// }
+///
+/// intrusive_tree_adapter!(ByDeadline = Timer { link } by deadline: u64);
+///
+/// fn main() {
+///     let reap = Reap::new();
+///     let mut timers = SearchTree::<ByDeadline>::new(&reap);
+///
+///     for &deadline in &[30, 10, 20] {
+///         timers.insert(reap.allocate(Timer { deadline, link: TreeLink::new() }));
+///     }
+///
+///     assert_eq!(timers.iter().map(|t| t.deadline).collect::<Vec<_>>(), vec![10, 20, 30]);
+///     assert_eq!(timers.pop_first().map(|t| t.deadline), Some(10));
+///     assert!(timers.find(&20).is_some());
+/// }
+/// ```
+pub struct SearchTree<A>
+    where A: TreeAdapter
+{
+    reap: Reap<A::Value>,
+    root: *const TreeLink,
+    len: usize,
+    _marker: marker::PhantomData<A>,
+}
+
+impl<A> SearchTree<A>
+    where A: TreeAdapter
+{
+    /// Creates an empty tree for objects allocated in `reap`.
+    #[inline]
+    pub fn new(reap: &Reap<A::Value>) -> SearchTree<A> {
+        SearchTree {
+            reap: reap.clone(),
+            root: ptr::null(),
+            len: 0,
+            _marker: marker::PhantomData,
+        }
+    }
+
+    /// Returns the number of objects in the tree.
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Returns `true` if the tree is empty.
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Returns a reference to the object with the smallest key, if any.
+    #[inline]
+    pub fn first(&self) -> Option<&A::Value> {
+        unsafe { tree_object::<A>(leftmost(self.root)) }
+    }
+
+    /// Returns a reference to the object with the largest key, if any.
+    #[inline]
+    pub fn last(&self) -> Option<&A::Value> {
+        unsafe { tree_object::<A>(rightmost(self.root)) }
+    }
+
+    /// Returns a reference to an object whose key equals `key`, if any.
+    #[inline]
+    pub fn find<Q>(&self, key: &Q) -> Option<&A::Value>
+        where A::Key: Borrow<Q>,
+              Q: ?Sized + Ord
+    {
+        unsafe { tree_object::<A>(self.search(key)) }
+    }
+
+    /// Links `object` into the tree, after any objects with an equal key, taking ownership of it.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `object` was not allocated in this tree's `Reap`, or if its link is already in a
+    /// tree.
+    pub fn insert(&mut self, object: Rp<A::Value>) {
+        assert!(Rc::ptr_eq(&object.reap().0, &self.reap.0),
+                "object was not allocated in this tree's reap");
+        assert!(!A::get_link(&object).is_linked(), "object is already linked");
+
+        // The tree's own `Reap` handle stands in for the one inside the `Rp`.
+        let (ptr, _) = Rp::into_parts(object);
+        self.len += 1;
+        unsafe {
+            let value = &*ptr;
+            let link = field_ptr(ptr, A::get_link(value));
+            let mut parent = ptr::null::<TreeLink>();
+            let mut child = self.root;
+            let mut left = false;
+            while let Some(node) = child.as_ref() {
+                parent = child;
+                left = A::get_key(value) < A::get_key(&*A::get_value(child));
+                child = if left { node.left.get() } else { node.right.get() };
+            }
+            (*link).parent.set(parent);
+            (*link).left.set(ptr::null());
+            (*link).right.set(ptr::null());
+            match parent.as_ref() {
+                None => self.root = link,
+                Some(parent) if left => parent.left.set(link),
+                Some(parent) => parent.right.set(link),
+            }
+            while !(*link).parent.get().is_null() &&
+                  priority(link) > priority((*link).parent.get()) {
+                self.rotate_up(link);
+            }
+        }
+    }
+
+    /// Unlinks an object whose key equals `key` and returns ownership of it.
+    #[inline]
+    pub fn remove<Q>(&mut self, key: &Q) -> Option<Rp<A::Value>>
+        where A::Key: Borrow<Q>,
+              Q: ?Sized + Ord
+    {
+        let link = self.search(key);
+        if link.is_null() {
+            None
+        } else {
+            unsafe { Some(self.unlink(link)) }
+        }
+    }
+
+    /// Unlinks the object with the smallest key and returns ownership of it.
+    #[inline]
+    pub fn pop_first(&mut self) -> Option<Rp<A::Value>> {
+        let first = unsafe { leftmost(self.root) };
+        if first.is_null() {
+            None
+        } else {
+            unsafe { Some(self.unlink(first)) }
+        }
+    }
+
+    /// Unlinks the object with the largest key and returns ownership of it.
+    #[inline]
+    pub fn pop_last(&mut self) -> Option<Rp<A::Value>> {
+        let last = unsafe { rightmost(self.root) };
+        if last.is_null() {
+            None
+        } else {
+            unsafe { Some(self.unlink(last)) }
+        }
+    }
+
+    /// Returns an iterator over the objects in the tree, in order of their keys.
+    #[inline]
+    pub fn iter(&self) -> TreeIter<'_, A> {
+        TreeIter {
+            link: unsafe { leftmost(self.root) },
+            _marker: marker::PhantomData,
+        }
+    }
+
+    // Returns the link of an object whose key equals `key`, or null.
+    fn search<Q>(&self, key: &Q) -> *const TreeLink
+        where A::Key: Borrow<Q>,
+              Q: ?Sized + Ord
+    {
+        let mut link = self.root;
+        unsafe {
+            while let Some(node) = link.as_ref() {
+                link = match key.cmp(A::get_key(&*A::get_value(link)).borrow()) {
+                    Ordering::Less => node.left.get(),
+                    Ordering::Greater => node.right.get(),
+                    Ordering::Equal => break,
+                };
+            }
+        }
+        link
+    }
+
+    // Puts `new` in the place of `old` as a child of `parent`, or as the root for a null `parent`.
+    unsafe fn replace_child(&mut self,
+                            parent: *const TreeLink,
+                            old: *const TreeLink,
+                            new: *const TreeLink) {
+        match parent.as_ref() {
+            None => self.root = new,
+            Some(parent) if parent.left.get() == old => parent.left.set(new),
+            Some(parent) => parent.right.set(new),
+        }
+        if let Some(new) = new.as_ref() {
+            new.parent.set(parent);
+        }
+    }
+
+    // Rotates `link` up into the place of its parent, keeping the nodes in order.
+    //
+    // `link` must be in this tree, and not its root.
+    unsafe fn rotate_up(&mut self, link: *const TreeLink) {
+        let node = &*link;
+        let parent = node.parent.get();
+        let grandparent = (*parent).parent.get();
+        if (*parent).left.get() == link {
+            let inner = node.right.get();
+            (*parent).left.set(inner);
+            node.right.set(parent);
+            if let Some(inner) = inner.as_ref() {
+                inner.parent.set(parent);
+            }
+        } else {
+            let inner = node.left.get();
+            (*parent).right.set(inner);
+            node.left.set(parent);
+            if let Some(inner) = inner.as_ref() {
+                inner.parent.set(parent);
+            }
+        }
+        (*parent).parent.set(link);
+        self.replace_child(grandparent, parent, link);
+    }
+
+    // Unlinks `link` and reconstitutes the `Rp` of its object.
+    //
+    // `link` must be in this tree.
+    unsafe fn unlink(&mut self, link: *const TreeLink) -> Rp<A::Value> {
+        let node = &*link;
+        // Rotate it down until it has at most one child, which then takes its place.
+        loop {
+            let (left, right) = (node.left.get(), node.right.get());
+            if left.is_null() || right.is_null() {
+                let child = if left.is_null() { right } else { left };
+                self.replace_child(node.parent.get(), link, child);
+                break;
+            }
+            self.rotate_up(if priority(left) > priority(right) { left } else { right });
+        }
+        node.parent.set(unlinked_tree());
+        node.left.set(ptr::null());
+        node.right.set(ptr::null());
+        self.len -= 1;
+        Rp::from_parts(A::get_value(link) as *mut A::Value, self.reap.clone())
+    }
+}
+
+impl<A> Drop for SearchTree<A>
+    where A: TreeAdapter
+{
+    fn drop(&mut self) {
+        while self.pop_first().is_some() {}
+    }
+}
+
+/// An iterator over the objects in a `SearchTree`, in order of their keys.
+pub struct TreeIter<'a, A>
+    where A: TreeAdapter + 'a
+{
+    link: *const TreeLink,
+    _marker: marker::PhantomData<&'a SearchTree<A>>,
+}
+
+impl<'a, A> Iterator for TreeIter<'a, A>
+    where A: TreeAdapter + 'a
+{
+    type Item = &'a A::Value;
+
+    #[inline]
+    fn next(&mut self) -> Option<&'a A::Value> {
+        unsafe {
+            self.link.as_ref().map(|node| {
+                // The in-order successor: the leftmost node right of this one, or else the first
+                // ancestor this one is left of.
+                let link = self.link;
+                self.link = match node.right.get() {
+                    right if !right.is_null() => leftmost(right),
+                    _ => {
+                        let mut child = link;
+                        let mut parent = node.parent.get();
+                        while !parent.is_null() && (*parent).right.get() == child {
+                            child = parent;
+                            parent = (*parent).parent.get();
+                        }
+                        parent
+                    }
+                };
+                &*A::get_value(link)
+            })
+        }
+    }
+}
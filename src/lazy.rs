@@ -0,0 +1,190 @@
+// Handles to objects constructed on first access, for `Reap::allocate_lazy`.
+
+use std::cell::Cell;
+use std::fmt;
+use std::mem::{self, ManuallyDrop};
+use std::ops::{Deref, DerefMut};
+use std::ptr::{self, NonNull};
+
+use super::{Reap, Rp};
+
+/// An owning handle to a slot whose object is only constructed when first accessed.
+///
+/// Created with `Reap::allocate_lazy`, which reserves the slot right away but keeps the
+/// initializer until the object is first dereferenced, like `once_cell::Lazy` with the value in
+/// the arena. Large tables of expensive entries, of which only a few are ever touched, only pay
+/// for those. The slot counts as live from the start, and dropping a handle never initialized
+/// just gives the slot back.
+///
+/// If the initializer panics, or dereferences the handle it is initializing, the handle is
+/// poisoned and every further access panics.
+///
+/// # Examples
+///
+/// ```
+/// use reap::{Reap, RpLazy};
+/// use std::cell::Cell;
+///
+/// let reap = Reap::new();
+/// let built = Cell::new(0);
+/// let table: Vec<_> = (0..100u64)
+///     .map(|i| {
+///         let built = &built;
+///         reap.allocate_lazy(move || {
+///             built.set(built.get() + 1);
+///             i * i
+///         })
+///     })
+///     .collect();
+///
+/// assert_eq!(*table[7] + *table[9], 130);
+/// assert_eq!(built.get(), 2);
+/// assert_eq!(RpLazy::get(&table[8]), None);
+/// ```
+pub struct RpLazy<T, F = fn() -> T> {
+    ptr: NonNull<T>,
+    // Only taken out when the handle is consumed.
+    reap: ManuallyDrop<Reap<T>>,
+    // Taken out when the object is constructed.
+    init: Cell<Option<F>>,
+    // Whether the slot holds the object.
+    ready: Cell<bool>,
+}
+
+impl<T> Reap<T> {
+    /// Reserves a slot for the object returned by `init`, to be called on first access.
+    #[inline]
+    #[cfg_attr(any(feature = "backtrace", feature = "callsites"), track_caller)]
+    pub fn allocate_lazy<F>(&self, init: F) -> RpLazy<T, F>
+        where F: FnOnce() -> T
+    {
+        let ptr = self.reserve();
+        #[cfg(any(feature = "backtrace", feature = "callsites"))]
+        self.track(ptr);
+        RpLazy {
+            ptr: unsafe { NonNull::new_unchecked(ptr) },
+            reap: ManuallyDrop::new(self.clone()),
+            init: Cell::new(Some(init)),
+            ready: Cell::new(false),
+        }
+    }
+}
+
+impl<T, F> RpLazy<T, F>
+    where F: FnOnce() -> T
+{
+    /// Constructs the object if it wasn't yet, and returns a reference to it.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the handle is poisoned.
+    #[inline]
+    pub fn force(this: &RpLazy<T, F>) -> &T {
+        if !this.ready.get() {
+            this.initialize();
+        }
+        unsafe { &*this.ptr.as_ptr() }
+    }
+
+    #[cold]
+    fn initialize(&self) {
+        let init = match self.init.take() {
+            Some(init) => init,
+            None => panic!("reap: RpLazy accessed during or after a failed initialization"),
+        };
+        // The slot stays reserved, and empty, if `init` panics.
+        unsafe {
+            ptr::write(self.ptr.as_ptr(), init());
+        }
+        self.ready.set(true);
+    }
+
+    /// Constructs the object if it wasn't yet, and converts this handle into a plain `Rp<T>`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the handle is poisoned.
+    pub fn into_rp(mut this: RpLazy<T, F>) -> Rp<T> {
+        RpLazy::force(&this);
+        let ptr = this.ptr.as_ptr();
+        unsafe {
+            let reap = ManuallyDrop::take(&mut this.reap);
+            // The initializer is gone already.
+            mem::forget(this);
+            Rp::from_parts(ptr, reap)
+        }
+    }
+}
+
+impl<T, F> RpLazy<T, F> {
+    /// Returns a reference to the object, or `None` if it wasn't constructed yet.
+    #[inline]
+    pub fn get(this: &RpLazy<T, F>) -> Option<&T> {
+        if this.ready.get() {
+            Some(unsafe { &*this.ptr.as_ptr() })
+        } else {
+            None
+        }
+    }
+
+    /// Returns a mutable reference to the object, or `None` if it wasn't constructed yet.
+    #[inline]
+    pub fn get_mut(this: &mut RpLazy<T, F>) -> Option<&mut T> {
+        if this.ready.get() {
+            Some(unsafe { &mut *this.ptr.as_ptr() })
+        } else {
+            None
+        }
+    }
+
+    /// Returns a reference to this handle's associated `Reap<T>`.
+    #[inline]
+    pub fn reap(this: &RpLazy<T, F>) -> &Reap<T> {
+        &this.reap
+    }
+}
+
+impl<T, F> Drop for RpLazy<T, F> {
+    fn drop(&mut self) {
+        if self.ready.get() {
+            self.reap.deallocate(self.ptr.as_ptr());
+        } else {
+            self.reap.release(self.ptr.as_ptr());
+        }
+        unsafe {
+            ManuallyDrop::drop(&mut self.reap);
+        }
+    }
+}
+
+impl<T, F> Deref for RpLazy<T, F>
+    where F: FnOnce() -> T
+{
+    type Target = T;
+
+    #[inline]
+    fn deref(&self) -> &T {
+        RpLazy::force(self)
+    }
+}
+
+impl<T, F> DerefMut for RpLazy<T, F>
+    where F: FnOnce() -> T
+{
+    #[inline]
+    fn deref_mut(&mut self) -> &mut T {
+        RpLazy::force(self);
+        unsafe { &mut *self.ptr.as_ptr() }
+    }
+}
+
+impl<T, F> fmt::Debug for RpLazy<T, F>
+    where T: fmt::Debug
+{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match RpLazy::get(self) {
+            Some(value) => f.debug_tuple("RpLazy").field(value).finish(),
+            None => f.write_str("RpLazy(<uninit>)"),
+        }
+    }
+}
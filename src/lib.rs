@@ -1,91 +1,354 @@
 #![cfg_attr(test, feature(test))]
 
+#[cfg(all(feature = "mlock", not(unix)))]
+compile_error!("the `mlock` feature is only supported on Unix");
+
+#[cfg(feature = "mlock")]
+extern crate libc;
+
 use std::cell::{RefCell, Cell};
 use std::rc::Rc;
 use std::ops::{Deref, DerefMut};
-use std::ptr;
+use std::ptr::{self, NonNull};
 use std::mem;
 use std::cmp::{self, Ordering};
 use std::marker;
 use std::hash::{self, Hash};
 use std::fmt;
 use std::borrow;
+use std::error;
+use std::sync::atomic;
+use std::alloc::{self, Layout};
+use std::panic::{RefUnwindSafe, UnwindSafe};
+use std::pin::Pin;
+use std::future::Future;
+use std::task::{Context, Poll};
+#[cfg(any(debug_assertions, feature = "backtrace"))]
+use std::collections::HashMap;
+#[cfg(feature = "backtrace")]
+use std::backtrace::Backtrace;
+#[cfg(any(feature = "backtrace", feature = "callsites"))]
+use std::panic::Location;
+#[cfg(feature = "trace")]
+use std::time::Instant;
+
+#[macro_use]
+mod macros;
+
+mod alloc_in;
+pub mod array;
+mod background;
+pub mod bintree;
+pub mod brand;
+pub mod budget;
+pub mod buffer;
+mod by_addr;
+pub mod cache;
+#[cfg(feature = "callsites")]
+pub mod callsite;
+pub mod chunk_cache;
+mod collect;
+pub mod dense;
+pub mod dlist;
+pub mod erased;
+mod extend;
+#[cfg(feature = "failpoints")]
+pub mod failpoint;
+pub mod fixed;
+pub mod frame;
+mod freelist;
+mod frozen;
+mod generation;
+pub mod graph;
+#[macro_use]
+pub mod intrusive;
+mod lazy;
+#[cfg(feature = "lifetimes")]
+pub mod lifetime;
+pub mod map;
+mod near;
+mod observer;
+pub mod owner;
+pub mod session;
+pub mod spsc;
+mod stats;
+mod tagged;
+pub mod task;
+#[cfg(feature = "trace")]
+pub mod trace;
+pub mod transaction;
+pub mod tree;
+mod weak;
+
+pub use alloc_in::{AllocateIn, AllocatedIn, AllocatedInExt};
+pub use by_addr::ByAddr;
+pub use collect::ReapCollect;
+pub use frozen::Frozen;
+pub use lazy::RpLazy;
+pub use observer::Growth;
+pub use stats::{ChunkStats, ReapPeaks, ReapStats, StatsDiff};
+pub use tagged::TaggedRp;
+pub use weak::WeakReap;
 
 #[cfg(test)]
 mod test;
 
-// Default initial capacity in bytes.
+// Id of the next `Reap` to be created.
+static NEXT_ID: atomic::AtomicU64 = atomic::AtomicU64::new(0);
+
+// Default initial capacity in bytes, and the size of the pages chunks are aligned to.
 const PAGE: usize = 4096;
 
+/// Byte pattern written over freed slots when the `poison` feature is enabled.
+pub const POISON: u8 = 0xDE;
+
+// Guard word written on either side of every slot when the `canary` feature is enabled.
+#[cfg(feature = "canary")]
+const CANARY: usize = 0xCA4A_71E5_CA4A_71E5_u64 as usize;
+
 // A `Chunk` represents a single contiguous allocation within the `Reap`.
 //
-// TODO: If/when `RawVec` is stabilized, use it instead of a raw pointer and capacity. Or just find
-// a better alternative.
-struct Chunk<T> {
-    // Pointer to the allocation. `heap::EMPTY` (1 as *mut T) for ZSTs.
-    ptr: *mut T,
-    // Capacity of the allocation. `!0` (usize::MAX) for ZSTs.
+// The element type is passed to each method rather than being part of `Chunk`'s type, so that
+// `InnerReap<T>` can stay covariant in `T`.
+//
+// Zero-sized types are never stored in chunks, so a `Chunk` always has a non-zero size in bytes.
+struct Chunk {
+    // Pointer to the allocation.
+    ptr: *mut u8,
+    // Capacity of the allocation, in elements.
     cap: usize,
+    // Layout the allocation was made with.
+    layout: Layout,
+    // Whether the allocation is `mlock`ed.
+    #[cfg(feature = "mlock")]
+    locked: bool,
 }
 
-impl<T> Chunk<T> {
-    // Creates a new `Chunk` with the given `capacity`.
+// Reports the failure of an infallible chunk allocation of `layout`, like `Vec` does.
+#[cold]
+fn alloc_failed(layout: Layout) -> ! {
+    #[cfg(feature = "failpoints")]
+    failpoint::check_injected(layout);
+    alloc::handle_alloc_error(layout)
+}
+
+impl Chunk {
+    // Creates a new `Chunk` with the given `capacity`, panicking or aborting on failure like
+    // `Vec::with_capacity`.
     #[inline]
-    fn new(capacity: usize) -> Chunk<T> {
-        let mut v = Vec::with_capacity(capacity);
-        let ptr = v.as_mut_ptr();
-        // We have all the information necessary to take ownership of `Vec`'s allocation and
-        // reconstitute it later.
-        mem::forget(v);
+    fn new<T>(capacity: usize) -> Chunk {
+        let layout = Chunk::layout::<T>(capacity).expect("capacity overflow");
+        Chunk::alloc(layout, capacity).unwrap_or_else(|| alloc_failed(layout))
+    }
+
+    // Creates a new `Chunk` with the given `capacity`.
+    fn try_new<T>(capacity: usize) -> Result<Chunk, AllocError> {
+        let layout = Chunk::layout::<T>(capacity).ok_or(AllocError::CapacityOverflow)?;
+        Chunk::alloc(layout, capacity).ok_or(AllocError::OutOfMemory)
+    }
+
+    // Creates a new `Chunk` with room for at least `capacity` elements, and tries to `mlock` it.
+    //
+    // The allocation is rounded out to whole pages, so that unlocking it can't unlock a page that
+    // some other locked allocation still needs. The extra space goes to extra elements.
+    #[cfg(feature = "mlock")]
+    fn new_locked<T>(capacity: usize) -> Chunk {
+        let layout = Chunk::layout::<T>(capacity)
+            .and_then(|layout| layout.align_to(page_size()).ok())
+            .expect("capacity overflow")
+            .pad_to_align();
+        let mut chunk = Chunk::alloc(layout, layout.size() / mem::size_of::<T>())
+            .unwrap_or_else(|| alloc_failed(layout));
+        // Failing, e.g. because of `RLIMIT_MEMLOCK`, leaves us with an ordinary chunk.
+        chunk.locked = unsafe { libc::mlock(chunk.ptr as *const libc::c_void, layout.size()) == 0 };
+        chunk
+    }
 
-        Chunk {
-            ptr: ptr,
+    // Allocates a `Chunk` of `capacity` elements with the given `layout`, or returns `None` if the
+    // allocator fails.
+    #[inline]
+    fn alloc(layout: Layout, capacity: usize) -> Option<Chunk> {
+        debug_assert!(layout.size() != 0, "zero-sized chunk");
+        #[cfg(feature = "failpoints")]
+        {
+            if failpoint::should_fail() {
+                return None;
+            }
+        }
+        if let Some((ptr, layout)) = chunk_cache::take(layout) {
+            return Some(Chunk {
+                ptr,
+                cap: capacity,
+                layout,
+                #[cfg(feature = "mlock")]
+                locked: false,
+            });
+        }
+        let ptr = unsafe { alloc::alloc(layout) };
+        if ptr.is_null() {
+            return None;
+        }
+        Some(Chunk {
+            ptr,
             cap: capacity,
+            layout,
+            #[cfg(feature = "mlock")]
+            locked: false,
+        })
+    }
+
+    // Returns the layout of a `Chunk` of `capacity` `T`s, or `None` if it would take more than
+    // `isize::MAX` bytes.
+    //
+    // Chunks of a page or more start on a page boundary, so that they occupy as few pages as they
+    // can, and `Reap::prefault` can tell exactly which pages lie ahead.
+    #[inline]
+    fn layout<T>(capacity: usize) -> Option<Layout> {
+        let layout = Layout::array::<T>(capacity).ok()?;
+        if layout.size() >= PAGE {
+            layout.align_to(PAGE).ok()
+        } else {
+            Some(layout)
         }
     }
 
     // Returns a pointer to the start of the allocated space.
     #[inline]
-    fn start(&self) -> *mut T {
-        self.ptr
+    fn start<T>(&self) -> *mut T {
+        self.ptr as *mut T
     }
 
     // Returns a pointer to the end of the allocated space.
     #[inline]
-    fn end(&self) -> *mut T {
-        unsafe {
-            if mem::size_of::<T>() == 0 {
-                // A pointer as large as possible for ZSTs.
-                !0 as *mut T
-            } else {
-                self.start().offset(self.capacity() as isize)
-            }
-        }
+    fn end<T>(&self) -> *mut T {
+        unsafe { self.start::<T>().add(self.cap) }
     }
 
     // Returns the capacity of the `Chunk`.
     #[inline]
     fn capacity(&self) -> usize {
-        if mem::size_of::<T>() == 0 {
-            !0
-        } else {
-            self.cap
-        }
+        self.cap
     }
 }
 
-impl<T> Drop for Chunk<T> {
+impl Drop for Chunk {
     fn drop(&mut self) {
-        // Give the allocation back to `Vec` so that it may be deallocated.
-        //
-        // Since calling `Drop::drop` for individual elements within a `Chunk` is handled by `Rp`,
-        // and a `Chunk` will not be dropped until its owning `Reap` is, which in turn will not
-        // be dropped until its refcount is zero, it is guaranteed that when a `Chunk` is dropped,
-        // destructors have already run on all appropriate elements in its allocation.
-        //
-        // That was a lot of words, I hope they made as much sense to you as they did to me.
+        // A `Chunk` is only ever dropped by the arena owning it, once nothing can reach the
+        // objects in it anymore: each was dropped by its handle, or has nothing to drop, like
+        // those of `Reap::alloc_extend`. Either way, what's left is plain memory, fit to be
+        // freed or retired to the chunk cache for an arena of any type to reuse.
         unsafe {
-            Vec::from_raw_parts(self.ptr, 0, self.cap);
+            #[cfg(feature = "mlock")]
+            {
+                if self.locked {
+                    // Secrets may be left in freed slots, don't leave them to the next user.
+                    for i in 0..self.layout.size() {
+                        ptr::write_volatile(self.ptr.add(i), 0);
+                    }
+                    atomic::compiler_fence(atomic::Ordering::SeqCst);
+                    libc::munlock(self.ptr as *const libc::c_void, self.layout.size());
+                    return alloc::dealloc(self.ptr, self.layout);
+                }
+            }
+            if !chunk_cache::put(self.ptr, self.layout) {
+                alloc::dealloc(self.ptr, self.layout);
+            }
+        }
+    }
+}
+
+// Returns the size of a memory page.
+#[cfg(feature = "mlock")]
+fn page_size() -> usize {
+    match unsafe { libc::sysconf(libc::_SC_PAGESIZE) } {
+        size if size > 0 => size as usize,
+        _ => PAGE,
+    }
+}
+
+// Storage for a single object within a `Chunk`.
+//
+// In release builds without the `canary` and `tags` features this has exactly the layout of a
+// `T`.
+#[repr(C)]
+struct Slot<T> {
+    // The generation of the object in the slot, fresh for every object, see `Rp::into_raw`.
+    #[cfg(debug_assertions)]
+    generation: u64,
+    // The tag given to `Reap::allocate_named`, if any.
+    #[cfg(feature = "tags")]
+    tag: Option<&'static str>,
+    #[cfg(feature = "canary")]
+    head: usize,
+    value: T,
+    #[cfg(feature = "canary")]
+    tail: usize,
+}
+
+impl<T> Slot<T> {
+    // Returns a pointer to the value stored in `slot`.
+    #[inline]
+    unsafe fn value(slot: *mut Slot<T>) -> *mut T {
+        ptr::addr_of_mut!((*slot).value)
+    }
+
+    // Returns a pointer to the slot storing the value at `ptr`.
+    #[inline]
+    #[cfg_attr(not(any(debug_assertions, feature = "canary", feature = "tags")),
+               allow(dead_code))]
+    unsafe fn from_value(ptr: *mut T) -> *mut Slot<T> {
+        (ptr as *mut u8).sub(mem::offset_of!(Slot<T>, value)) as *mut Slot<T>
+    }
+
+    // Returns the generation of the object in `slot`.
+    #[cfg(debug_assertions)]
+    #[inline]
+    unsafe fn generation(slot: *mut Slot<T>) -> u64 {
+        ptr::read(ptr::addr_of!((*slot).generation))
+    }
+
+    // Sets the generation of the object in `slot`.
+    #[cfg(debug_assertions)]
+    #[inline]
+    unsafe fn set_generation(slot: *mut Slot<T>, generation: u64) {
+        ptr::write(ptr::addr_of_mut!((*slot).generation), generation);
+    }
+
+    // Returns the tag of the object in `slot`.
+    //
+    // Only the tag is read, so this is fine to call with the value mutably borrowed.
+    #[cfg(feature = "tags")]
+    #[inline]
+    unsafe fn tag(slot: *mut Slot<T>) -> Option<&'static str> {
+        ptr::read(ptr::addr_of!((*slot).tag))
+    }
+
+    // Tags the object in `slot`.
+    #[cfg(feature = "tags")]
+    #[inline]
+    unsafe fn set_tag(slot: *mut Slot<T>, tag: Option<&'static str>) {
+        ptr::write(ptr::addr_of_mut!((*slot).tag), tag);
+    }
+
+    // Writes fresh guard words around `slot`.
+    #[cfg(feature = "canary")]
+    #[inline]
+    unsafe fn arm(slot: *mut Slot<T>) {
+        ptr::write(ptr::addr_of_mut!((*slot).head), CANARY);
+        ptr::write(ptr::addr_of_mut!((*slot).tail), CANARY);
+    }
+
+    // Panics if either guard word around `slot` has been overwritten.
+    //
+    // Only the guard words are read, so this is fine to call with the value mutably borrowed.
+    #[cfg(feature = "canary")]
+    #[inline]
+    unsafe fn check(slot: *mut Slot<T>) {
+        let head = ptr::read(ptr::addr_of!((*slot).head));
+        let tail = ptr::read(ptr::addr_of!((*slot).tail));
+        if head != CANARY || tail != CANARY {
+            panic!("reap: guard words around the slot at {:p} were overwritten",
+                   Slot::value(slot));
         }
     }
 }
@@ -93,76 +356,684 @@ impl<T> Drop for Chunk<T> {
 pub struct Reap<T>(Rc<InnerReap<T>>);
 
 // This struct is a necessary evil for `Rc`'s purposes; it is always kept behind an `Rc`.
+//
+// A `T` inside a `Cell` or `RefCell` would make `Reap<T>`, and with it `Rp<T>`, invariant in `T`,
+// so every pointer in here is stored as a `*mut u8` and `T` only appears in `_marker`. In `ptr`,
+// `end` and `chunks` they point to `Slot<T>`s, everywhere else to the `T` within a slot.
+//
+// SAFETY: covariance is only sound because nothing in here ever reads or drops a `T`, which is why
+// there is no `Drop` impl. A `Reap<&'static str>` may share its arena with a `Reap<&'a str>`
+// handle, so the objects in it may borrow from data that expires long before the arena does. Only
+// an object's `Rp`, whose type carries the lifetimes it was allocated with, may drop it, and
+// objects without one, like those of `alloc_extend`, are never dropped at all, which is why
+// `alloc_extend` only takes types with nothing to drop.
 struct InnerReap<T> {
-    // Pointer to the next object to be allocated. (If the freelist is empty).
-    ptr: Cell<*mut T>,
+    // Unique among all arenas of the process, see `Reap::id`.
+    id: u64,
+    // Pointer to the next slot to be allocated. (If the freelist is empty).
+    ptr: Cell<*mut u8>,
     // Pointer to the end of the current `Chunk`, when this pointer is reached a new `Chunk` is
     // allocated.
-    end: Cell<*mut T>,
-    // Reap chunks, each double the size of the last.
-    chunks: RefCell<Vec<Chunk<T>>>,
+    end: Cell<*mut u8>,
+    // Reap chunks of `Slot<T>`s, each double the size of the last.
+    chunks: RefCell<Vec<Chunk>>,
+    // Handing out the slot here requests the next chunk from a background thread, null if no slot
+    // does.
+    grow_at: Cell<*mut u8>,
+    background: RefCell<background::Background>,
+    // Called whenever a chunk is added.
+    observer: RefCell<observer::Observer>,
     // Stack of pointers to memory locations able to be reused.
-    freelist: RefCell<Vec<*mut T>>,
+    freelist: RefCell<freelist::FreeList>,
+    // Pointers currently given out by `Rp::into_raw`, with how many times each was (this only
+    // exceeds one for ZSTs, which all share an address). Checked by `Rp::from_raw`.
+    #[cfg(debug_assertions)]
+    escaped: RefCell<HashMap<*mut u8, usize>>,
+    // Generation of the latest object allocated, each new one gets the next.
+    #[cfg(debug_assertions)]
+    generation: Cell<u64>,
+    // Number of live objects.
+    live: Cell<usize>,
+    // The most objects live, and slots on the freelist, at once since creation or `reset_peaks`.
+    peak_live: Cell<usize>,
+    peak_free: Cell<usize>,
+    // Number of slots handed out from the freelist, and fresh from a chunk.
+    reused: Cell<usize>,
+    bumped: Cell<usize>,
+    // Whether released slots are zeroed.
+    zeroize: Cell<bool>,
+    // Whether new chunks are `mlock`ed.
+    #[cfg(feature = "mlock")]
+    mlock: Cell<bool>,
+    // Number of allocations made from each call site.
+    #[cfg(feature = "callsites")]
+    call_sites: RefCell<callsite::CallSites>,
+    // Where each live object was allocated, several entries per address for ZSTs.
+    #[cfg(feature = "backtrace")]
+    sites: RefCell<HashMap<*mut u8, Vec<(&'static Location<'static>, Backtrace)>>>,
+    // The trace being recorded, if any, and when it was started.
+    #[cfg(feature = "trace")]
+    trace: RefCell<Option<(Instant, trace::Trace)>>,
+    // When each live object was allocated, and the lifetimes of those freed.
+    #[cfg(feature = "lifetimes")]
+    lifetimes: RefCell<lifetime::Lifetimes>,
+    // Objects allocated by `alloc_extend`, which have nothing to drop.
+    extents: RefCell<extend::Extents>,
+    _marker: marker::PhantomData<T>,
 }
 
-impl<T> Reap<T> {
-    /// Creates a new `Reap<T>`.
+impl<T> Reap<T> {
+    /// Creates a new `Reap<T>`.
+    #[inline]
+    pub fn new() -> Reap<T> {
+        Reap(Rc::new(InnerReap {
+            id: NEXT_ID.fetch_add(1, atomic::Ordering::Relaxed),
+            // Set both `ptr` and `end` to 0 so that the first call to `allocate()` will trigger a
+            // `grow()`
+            ptr: Cell::new(ptr::null_mut()),
+            end: Cell::new(ptr::null_mut()),
+            chunks: RefCell::new(Vec::new()),
+            grow_at: Cell::new(ptr::null_mut()),
+            background: RefCell::new(background::Background::new()),
+            observer: RefCell::new(observer::Observer::new()),
+            freelist: RefCell::new(freelist::FreeList::new()),
+            #[cfg(debug_assertions)]
+            escaped: RefCell::new(HashMap::new()),
+            #[cfg(debug_assertions)]
+            generation: Cell::new(0),
+            live: Cell::new(0),
+            peak_live: Cell::new(0),
+            peak_free: Cell::new(0),
+            reused: Cell::new(0),
+            bumped: Cell::new(0),
+            zeroize: Cell::new(false),
+            #[cfg(feature = "mlock")]
+            mlock: Cell::new(false),
+            #[cfg(feature = "callsites")]
+            call_sites: RefCell::new(callsite::CallSites::new()),
+            #[cfg(feature = "backtrace")]
+            sites: RefCell::new(HashMap::new()),
+            #[cfg(feature = "trace")]
+            trace: RefCell::new(None),
+            #[cfg(feature = "lifetimes")]
+            lifetimes: RefCell::new(lifetime::Lifetimes::new()),
+            extents: RefCell::new(extend::Extents::new()),
+            _marker: marker::PhantomData,
+        }))
+    }
+
+    /// Creates a new `Reap<T>` with room for `capacity` objects before it needs to grow.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the size of `capacity` objects exceeds `isize::MAX` bytes.
+    pub fn with_capacity(capacity: usize) -> Reap<T> {
+        let reap = Reap::new();
+        // Zero-sized types are never stored in a chunk.
+        if capacity != 0 && mem::size_of::<T>() != 0 {
+            reap.push_chunk(Chunk::new::<Slot<T>>(capacity));
+        }
+        reap
+    }
+
+    /// Creates a new `Reap<T>` whose first chunk takes at most `bytes` bytes, with room for as
+    /// many objects as fit in them.
+    ///
+    /// Memory budgets are set in bytes, and generic code can't easily turn them into a count of
+    /// objects: a slot may be larger than a `T`, e.g. with the `canary` feature. The capacity is
+    /// rounded down to whole slots, so a budget smaller than one slot reserves nothing.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use reap::Reap;
+    ///
+    /// let reap = Reap::<[u8; 100]>::with_capacity_bytes(64 * 1024);
+    /// let stats = reap.stats();
+    /// assert!(stats.reserved_bytes() <= 64 * 1024);
+    /// assert_eq!(stats.capacity, 64 * 1024 / stats.slot_size);
+    /// ```
+    pub fn with_capacity_bytes(bytes: usize) -> Reap<T> {
+        let slot_size = cmp::max(1, mem::size_of::<Slot<T>>());
+        Reap::with_capacity(bytes / slot_size)
+    }
+
+    /// Creates a new `Reap<T>` with room for `capacity` objects, returning an error instead of
+    /// panicking or aborting if that memory can't be allocated.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use reap::{AllocError, Reap};
+    ///
+    /// assert!(Reap::<u64>::try_with_capacity(1024).is_ok());
+    /// assert_eq!(Reap::<u64>::try_with_capacity(usize::MAX).err(),
+    ///            Some(AllocError::CapacityOverflow));
+    /// ```
+    pub fn try_with_capacity(capacity: usize) -> Result<Reap<T>, AllocError> {
+        let reap = Reap::new();
+        // Zero-sized types are never stored in a chunk.
+        if capacity != 0 && mem::size_of::<T>() != 0 {
+            reap.push_chunk(Chunk::try_new::<Slot<T>>(capacity)?);
+        }
+        Ok(reap)
+    }
+
+    /// Creates a new `Reap<T>` holding every item of `iter`, returning it along with a handle to
+    /// each item, in order.
+    ///
+    /// The first chunk is sized from the iterator's lower size hint, so loading an exactly sized
+    /// iterator takes a single allocation for the arena.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use reap::Reap;
+    ///
+    /// let (reap, rps) = Reap::from_iter((0..100).map(|i| i * 2));
+    /// assert_eq!(*rps[50], 100);
+    /// assert_eq!(reap.stats().chunks, 1);
+    /// ```
+    #[allow(clippy::should_implement_trait)]
+    #[cfg_attr(any(feature = "backtrace", feature = "callsites"), track_caller)]
+    pub fn from_iter<I>(iter: I) -> (Reap<T>, Vec<Rp<T>>)
+        where I: IntoIterator<Item = T>
+    {
+        let iter = iter.into_iter();
+        let reap = Reap::with_capacity(iter.size_hint().0);
+        let mut rps = Vec::with_capacity(iter.size_hint().0);
+        for object in iter {
+            rps.push(reap.allocate(object));
+        }
+        (reap, rps)
+    }
+
+    /// Sets whether the memory of every object leaving this `Reap` is overwritten with zeros.
+    ///
+    /// When enabled, a slot is zeroed after its object's destructor has run, or after the object
+    /// was moved out, before the slot can be handed out again. Keys and passwords held in the
+    /// arena then don't linger in recycled memory. The writes are volatile, so they aren't
+    /// optimized away. This applies to every handle of the arena, and is off by default.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use reap::Reap;
+    ///
+    /// let reap = Reap::new();
+    /// reap.set_zeroize(true);
+    /// let key = reap.allocate([0x5au8; 32]);
+    /// drop(key);
+    /// ```
+    #[inline]
+    pub fn set_zeroize(&self, zeroize: bool) {
+        self.0.zeroize.set(zeroize);
+    }
+
+    /// Returns `true` if objects leaving this `Reap` are overwritten with zeros.
+    #[inline]
+    pub fn zeroizes(&self) -> bool {
+        self.0.zeroize.get()
+    }
+
+    /// Sets whether chunks allocated by this `Reap` from now on are locked into memory with
+    /// `mlock`, so that secrets stored in them can't be swapped out to disk.
+    ///
+    /// Only available on Unix with the `mlock` feature. Locked chunks are rounded out to whole
+    /// pages, and are zeroed before being unlocked and freed along with the `Reap`. Chunks that
+    /// already exist aren't affected, so enable this on a `Reap::new()` before allocating from
+    /// it, and together with `set_zeroize` to also clear slots as they are recycled.
+    ///
+    /// If a chunk can't be locked, e.g. because `RLIMIT_MEMLOCK` is too low, it is used unlocked.
+    /// `mlocked_bytes` tells how much memory actually is locked.
+    #[cfg(feature = "mlock")]
+    #[inline]
+    pub fn set_mlock(&self, mlock: bool) {
+        self.0.mlock.set(mlock);
+    }
+
+    /// Returns the number of bytes this `Reap` has successfully locked into memory.
+    #[cfg(feature = "mlock")]
+    pub fn mlocked_bytes(&self) -> usize {
+        self.0
+            .chunks
+            .borrow()
+            .iter()
+            .filter(|chunk| chunk.locked)
+            .map(|chunk| chunk.layout.size())
+            .sum()
+    }
+
+    #[inline]
+    #[cfg_attr(any(feature = "backtrace", feature = "callsites"), track_caller)]
+    pub fn allocate(&self, object: T) -> Rp<T> {
+        let rp = self.allocate_untracked(object);
+        #[cfg(any(feature = "backtrace", feature = "callsites"))]
+        self.track(rp.ptr.as_ptr());
+        rp
+    }
+
+    /// Allocates `object`, tagged with `tag`, e.g. the name of the subsystem allocating it.
+    ///
+    /// Only available with the `tags` feature, which stores a tag in a header of every slot. Tags
+    /// show up in `dump_live` and `dump_layout`, so that diagnostics point at whoever allocated an
+    /// object instead of at an anonymous address, and `Rp::tag` returns them. Objects allocated
+    /// any other way are untagged. Zero-sized types have no slots, and so no tags.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use reap::{Reap, Rp};
+    ///
+    /// let reap = Reap::new();
+    /// let expr = reap.allocate_named(String::from("1 + 2"), "parser::expr");
+    /// assert_eq!(Rp::tag(&expr), Some("parser::expr"));
+    /// assert_eq!(Rp::tag(&reap.allocate(String::new())), None);
+    /// ```
+    #[cfg(feature = "tags")]
+    #[inline]
+    #[cfg_attr(any(feature = "backtrace", feature = "callsites"), track_caller)]
+    pub fn allocate_named(&self, object: T, tag: &'static str) -> Rp<T> {
+        let rp = self.allocate(object);
+        if mem::size_of::<T>() != 0 {
+            unsafe {
+                Slot::set_tag(Slot::from_value(rp.ptr.as_ptr()), Some(tag));
+            }
+        }
+        rp
+    }
+
+    /// Allocates `object`, pinned in its slot.
+    ///
+    /// Like `Box::pin`. A slot never moves its object, and is only reused once the object has
+    /// been dropped, so futures and intrusive nodes can live in the arena pinned, with no `unsafe`
+    /// at the call site. `Pin::as_mut` gives the `Pin<&mut T>` that `poll` and pin projections
+    /// like those of `pin-project` work with.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use reap::Reap;
+    /// use std::cell::Cell;
+    /// use std::future::Future;
+    /// use std::marker::PhantomPinned;
+    /// use std::pin::Pin;
+    /// use std::task::{Context, Poll, Waker};
+    ///
+    /// // Not `Unpin`, so it can only be polled once pinned.
+    /// struct Countdown(Cell<u32>, PhantomPinned);
+    ///
+    /// impl Future for Countdown {
+    ///     type Output = ();
+    ///
+    ///     fn poll(self: Pin<&mut Self>, cx: &mut Context) -> Poll<()> {
+    ///         match self.0.get() {
+    ///             0 => Poll::Ready(()),
+    ///             n => {
+    ///                 self.0.set(n - 1);
+    ///                 cx.waker().wake_by_ref();
+    ///                 Poll::Pending
+    ///             }
+    ///         }
+    ///     }
+    /// }
+    ///
+    /// let reap = Reap::new();
+    /// let mut countdown = reap.allocate_pinned(Countdown(Cell::new(1), PhantomPinned));
+    /// let mut cx = Context::from_waker(Waker::noop());
+    /// assert_eq!(countdown.as_mut().poll(&mut cx), Poll::Pending);
+    /// assert_eq!(countdown.as_mut().poll(&mut cx), Poll::Ready(()));
+    /// ```
+    #[inline]
+    #[cfg_attr(any(feature = "backtrace", feature = "callsites"), track_caller)]
+    pub fn allocate_pinned(&self, object: T) -> Pin<Rp<T>> {
+        Rp::into_pin(self.allocate(object))
+    }
+
+    /// Allocates the object returned by `f`, constructing it directly in its slot.
+    ///
+    /// `f` may itself allocate from this `Reap`. If `f` panics, the slot reserved for its result
+    /// goes back to the freelist and the `Reap` is left as it was.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use reap::Reap;
+    ///
+    /// let reap = Reap::new();
+    /// let table = reap.allocate_with(|| [0u64; 512]);
+    /// assert_eq!(table[511], 0);
+    /// ```
+    #[inline]
+    #[cfg_attr(any(feature = "backtrace", feature = "callsites"), track_caller)]
+    pub fn allocate_with<F>(&self, f: F) -> Rp<T>
+        where F: FnOnce() -> T
+    {
+        let ptr = self.reserve();
+        // Gives the slot back if `f` unwinds.
+        let guard = Release::new(|| self.release(ptr));
+        unsafe {
+            ptr::write(ptr, f());
+        }
+        mem::forget(guard);
+        #[cfg(any(feature = "backtrace", feature = "callsites"))]
+        self.track(ptr);
+        unsafe { Rp::from_parts(ptr, self.clone()) }
+    }
+
+    /// Allocates a clone of `value`, cloning it directly into its slot.
+    ///
+    /// A freed slot no longer holds an object, so there is nothing for `Clone::clone_from` to
+    /// reuse: the clone is written into the reserved slot instead of being made on the stack
+    /// and moved, as with `allocate_with`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use reap::Reap;
+    ///
+    /// let reap = Reap::new();
+    /// let template = [1u64; 256];
+    /// let copy = reap.allocate_clone_from(&template);
+    /// assert_eq!(*copy, template);
+    /// ```
+    #[inline]
+    #[cfg_attr(any(feature = "backtrace", feature = "callsites"), track_caller)]
+    pub fn allocate_clone_from(&self, value: &T) -> Rp<T>
+        where T: Clone
+    {
+        self.allocate_with(|| value.clone())
+    }
+
+    /// Takes over the buffer of `vec` as a chunk of this `Reap`, returning a handle to each of its
+    /// elements, in order.
+    ///
+    /// The elements stay where they are, and the buffer's spare capacity becomes room for new
+    /// objects. In debug builds, and with the `canary` or `tags` features, slots carry a header
+    /// and so don't have the layout of a bare `T`, and in a `Reap` that `mlock`s its chunks the
+    /// buffer wouldn't be locked, so in those cases the elements are moved into slots one by one
+    /// instead.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use reap::Reap;
+    ///
+    /// let reap = Reap::new();
+    /// let loaded: Vec<String> = "a b c".split(' ').map(String::from).collect();
+    /// let rps = reap.adopt_vec(loaded);
+    /// assert_eq!(*rps[2], "c");
+    /// assert_eq!(reap.stats().live, 3);
+    /// ```
+    #[cfg_attr(any(feature = "backtrace", feature = "callsites"), track_caller)]
+    pub fn adopt_vec(&self, vec: Vec<T>) -> Vec<Rp<T>> {
+        #[allow(unused_mut)]
+        let mut adopt = mem::size_of::<T>() != 0 && vec.capacity() != 0 &&
+                        !cfg!(debug_assertions) && !cfg!(feature = "canary") &&
+                        !cfg!(feature = "tags");
+        #[cfg(feature = "mlock")]
+        {
+            adopt &= !self.0.mlock.get();
+        }
+        if !adopt {
+            let mut rps = Vec::with_capacity(vec.len());
+            for object in vec {
+                rps.push(self.allocate(object));
+            }
+            return rps;
+        }
+
+        let (len, cap) = (vec.len(), vec.capacity());
+        let start = mem::ManuallyDrop::new(vec).as_mut_ptr();
+        // Without a header a `Slot<T>` is laid out exactly like a `T`, so the buffer already is an
+        // array of slots, allocated with the layout `Vec` documents.
+        let chunk = Chunk {
+            ptr: start as *mut u8,
+            cap,
+            layout: Layout::array::<T>(cap).unwrap(),
+            #[cfg(feature = "mlock")]
+            locked: false,
+        };
+        // The current chunk's untouched slots would be left behind, put them on the freelist.
+        {
+            let mut freelist = self.0.freelist.borrow_mut();
+            let mut slot = self.0.end.get() as *mut Slot<T>;
+            while slot > self.0.ptr.get() as *mut Slot<T> {
+                unsafe {
+                    slot = slot.offset(-1);
+                    freelist.push(Slot::value(slot) as *mut u8);
+                }
+            }
+            self.0.peak_free.set(cmp::max(self.0.peak_free.get(), freelist.len()));
+        }
+        self.push_chunk(chunk);
+        unsafe {
+            self.0.ptr.set(start.add(len) as *mut u8);
+        }
+        self.arm_background_growth();
+        self.0.live.set(self.0.live.get() + len);
+        self.0.bumped.set(self.0.bumped.get() + len);
+        self.0.peak_live.set(cmp::max(self.0.peak_live.get(), self.0.live.get()));
+
+        let mut rps = Vec::with_capacity(len);
+        for i in 0..len {
+            let ptr = unsafe { start.add(i) };
+            #[cfg(feature = "trace")]
+            self.record(trace::Op::Alloc, ptr);
+            #[cfg(feature = "lifetimes")]
+            self.0.lifetimes.borrow_mut().born(ptr as *mut u8);
+            #[cfg(any(feature = "backtrace", feature = "callsites"))]
+            self.track(ptr);
+            rps.push(unsafe { Rp::from_parts(ptr, self.clone()) });
+        }
+        rps
+    }
+
+    /// Returns `true` if `rp` was allocated in this `Reap`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use reap::Reap;
+    ///
+    /// let (a, b) = (Reap::new(), Reap::new());
+    /// let x = a.allocate(1);
+    /// assert!(a.owns(&x));
+    /// assert!(!b.owns(&x));
+    /// ```
+    #[inline]
+    pub fn owns(&self, rp: &Rp<T>) -> bool {
+        Rc::ptr_eq(&self.0, &rp.reap.0)
+    }
+
+    /// Returns this arena's id, unique among all arenas created by the process.
+    ///
+    /// Handles of the same arena share its id, see `Rp::arena_id`, which makes for a cheap tag
+    /// in logs and assertions when several arenas are in play.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use reap::Reap;
+    ///
+    /// let (a, b) = (Reap::new(), Reap::<i32>::new());
+    /// assert_ne!(a.id(), b.id());
+    /// assert_eq!(a.allocate(1).arena_id(), a.id());
+    /// ```
+    #[inline]
+    pub fn id(&self) -> u64 {
+        self.0.id
+    }
+
+    /// Returns `true` if `ptr` points into one of this `Reap`'s chunks.
+    ///
+    /// This only checks the address, not whether an object currently lives there. Zero-sized
+    /// types take no space in any chunk, so this always returns `false` for them.
+    pub fn contains_ptr(&self, ptr: *const T) -> bool {
+        if mem::size_of::<T>() == 0 {
+            return false;
+        }
+        let addr = ptr as usize;
+        self.0
+            .chunks
+            .borrow()
+            .iter()
+            .any(|chunk| {
+                chunk.start::<Slot<T>>() as usize <= addr && addr < chunk.end::<Slot<T>>() as usize
+            })
+    }
+
+    // Returns the index of the chunk holding `addr` and of the slot within it, if any.
+    fn position(&self, addr: usize) -> Option<(usize, usize)> {
+        let slot_size = mem::size_of::<Slot<T>>();
+        self.0
+            .chunks
+            .borrow()
+            .iter()
+            .enumerate()
+            .filter_map(|(i, chunk)| {
+                let start = chunk.start::<Slot<T>>() as usize;
+                let end = chunk.end::<Slot<T>>() as usize;
+                if start <= addr && addr < end {
+                    Some((i, (addr - start) / slot_size))
+                } else {
+                    None
+                }
+            })
+            .next()
+    }
+
+    /// Consumes this `Reap`, returning its final statistics if it was the last handle to the
+    /// arena and no objects remain in it, or handing it back otherwise.
+    ///
+    /// Every `Rp` holds a handle to its `Reap`, so this fails as long as any object is alive,
+    /// including ones leaked with `mem::forget` or whose handles were taken apart by
+    /// `Rp::into_raw`. On success the arena's memory is freed.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use reap::Reap;
+    ///
+    /// let reap = Reap::new();
+    /// let x = reap.allocate(1);
+    ///
+    /// let reap = reap.try_finalize().unwrap_err();
+    /// drop(x);
+    /// let stats = reap.try_finalize().unwrap();
+    /// assert_eq!(stats.live, 0);
+    /// ```
+    pub fn try_finalize(self) -> Result<ReapStats, Reap<T>> {
+        if Rc::strong_count(&self.0) != 1 || self.0.live.get() != 0 {
+            return Err(self);
+        }
+        Ok(self.stats())
+    }
+
+    /// Leaks this handle, returning a reference to the arena that lives for the rest of the
+    /// program.
+    ///
+    /// The arena is never freed, so its objects can be turned into plain `&'static mut T` with
+    /// `Rp::leak`: configuration trees, interned tables and the like, which live as long as the
+    /// program anyway, can then be passed around without handles. Objects that are dropped still
+    /// give their slots back as usual.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use reap::{Reap, Rp};
+    ///
+    /// let names: &'static Reap<String> = Reap::new().leak();
+    /// let greeting: &'static str = Rp::leak(names.allocate(String::from("hello")));
+    /// assert_eq!(greeting, "hello");
+    /// ```
+    #[inline]
+    pub fn leak(self) -> &'static Reap<T>
+        where T: 'static
+    {
+        Box::leak(Box::new(self))
+    }
+
+    #[inline]
+    fn allocate_untracked(&self, object: T) -> Rp<T> {
+        let ptr = self.reserve();
+        unsafe {
+            ptr::write(ptr, object);
+            Rp::from_parts(ptr, self.clone())
+        }
+    }
+
+    // Reserves a slot for a new object, returning a pointer to where the object goes.
+    //
+    // The slot counts as live from here on: the caller must either initialize it and hand it to
+    // an `Rp`, or give it back uninitialized with `release`.
     #[inline]
-    pub fn new() -> Reap<T> {
-        Reap(Rc::new(InnerReap {
-            // Set both `ptr` and `end` to 0 so that the first call to `allocate()` will trigger a
-            // `grow()`
-            ptr: Cell::new(0 as *mut T),
-            end: Cell::new(0 as *mut T),
-            chunks: RefCell::new(Vec::new()),
-            freelist: RefCell::new(Vec::new()),
-        }))
+    fn reserve(&self) -> *mut T {
+        let ptr = self.next_slot();
+        self.claim(ptr)
     }
 
-    pub fn with_capacity(capacity: usize) -> Reap<T> {
-        if capacity == 0 {
-            Reap::new()
+    // Counts the slot at `ptr`, just found by `next_slot` or the like, as live, returning `ptr`.
+    #[inline]
+    fn claim(&self, ptr: *mut T) -> *mut T {
+        self.0.live.set(self.0.live.get() + 1);
+        self.0.peak_live.set(cmp::max(self.0.peak_live.get(), self.0.live.get()));
+        #[cfg(feature = "trace")]
+        self.record(trace::Op::Alloc, ptr);
+        #[cfg(feature = "lifetimes")]
+        self.0.lifetimes.borrow_mut().born(ptr as *mut u8);
+        #[cfg(debug_assertions)]
+        {
+            if mem::size_of::<T>() != 0 {
+                let generation = self.0.generation.get() + 1;
+                self.0.generation.set(generation);
+                unsafe { Slot::set_generation(Slot::from_value(ptr), generation) }
+            }
+        }
+        ptr
+    }
+
+    // Finds a slot for a new object, from the freelist if possible.
+    #[inline]
+    fn next_slot(&self) -> *mut T {
+        // First, deal with ZSTs. They all share a dangling, but well aligned, address that is never
+        // actually written to.
+        if mem::size_of::<T>() == 0 {
+            return NonNull::dangling().as_ptr();
+        }
+        // Reaching this point means we're not dealing with a ZST, on with the fun stuff.
+        //
+        // First, check the freelist.
+        let reused = self.0.freelist.borrow_mut().pop();
+        if let Some(loc) = reused {
+            self.0.reused.set(self.0.reused.get() + 1);
+            loc as *mut T
         } else {
-            let chunk = Chunk::new(capacity);
-            Reap(Rc::new(InnerReap {
-                ptr: Cell::new(chunk.start()),
-                end: Cell::new(chunk.end()),
-                chunks: RefCell::new(vec![chunk]),
-                freelist: RefCell::new(Vec::new()),
-            }))
+            // No dice on the freelist, now we act like a normal arena.
+            self.bump()
         }
     }
 
+    // Hands out the next untouched slot of the current chunk, growing first if there is none.
+    //
+    // Not for ZSTs, which have no slots.
     #[inline]
-    pub fn allocate(&self, object: T) -> Rp<T> {
+    fn bump(&self) -> *mut T {
+        if self.0.ptr == self.0.end {
+            self.grow()
+        }
+        self.0.bumped.set(self.0.bumped.get() + 1);
+        let slot = self.0.ptr.get() as *mut Slot<T>;
+        if slot as *mut u8 == self.0.grow_at.get() {
+            self.grow_in_background();
+        }
         unsafe {
-            // First, deal with ZSTs.
-            if mem::size_of::<T>() == 0 {
-                // Bump our imaginary pointer.
-                self.0.ptr.set((self.0.ptr.get() as *mut u8).offset(1) as *mut T);
-                // `heap::EMPTY` is unstable so this will have to do.
-                let ptr = 1 as *mut T;
-                // Don't drop the object, this `ptr::write` is equivalent to `mem::forget`.
-                ptr::write(ptr, object);
-                Rp::from_raw(ptr, self.clone())
-            } else {
-                // Reaching this branch means we're not dealing with a ZST, on with the fun stuff.
-                //
-                // First, check the freelist.
-                if let Some(loc) = self.0.freelist.borrow_mut().pop() {
-                    ptr::write(loc, object);
-                    Rp::from_raw(loc, self.clone())
-                } else {
-                    // No dice on the freelist, now we act like a normal arena.
-                    if self.0.ptr == self.0.end {
-                        self.grow()
-                    }
-                    let ptr = self.0.ptr.get();
-                    self.0.ptr.set(self.0.ptr.get().offset(1));
-                    ptr::write(ptr, object);
-                    Rp::from_raw(ptr, self.clone())
-                }
-            }
+            self.0.ptr.set(slot.offset(1) as *mut u8);
+            #[cfg(feature = "canary")]
+            Slot::arm(slot);
+            #[cfg(feature = "tags")]
+            Slot::set_tag(slot, None);
+            Slot::value(slot)
         }
     }
 
@@ -172,15 +1043,72 @@ impl<T> Reap<T> {
     // the given `ptr` is valid, and actually part of an allocation owned by this `Reap<T>`.
     #[inline]
     fn deallocate(&self, ptr: *mut T) {
+        // No borrow of the arena is held while the destructor runs, so it may allocate from and
+        // free into this very `Reap`, like a node dropping the handles to its children. Its own
+        // slot is only released afterwards, and isn't handed out in the meantime.
+        //
+        // A panicking destructor still counts as having dropped the object, its slot is released
+        // on the way out.
+        let _release = Release::new(|| self.release(ptr));
         unsafe {
+            // Not while unwinding, most likely from a failed check in the first place, lest the
+            // second panic abort.
+            #[cfg(feature = "canary")]
+            {
+                if mem::size_of::<T>() != 0 && !::std::thread::panicking() {
+                    Slot::check(Slot::from_value(ptr));
+                }
+            }
             ptr::drop_in_place(ptr);
         }
-        self.0.freelist.borrow_mut().push(ptr);
+    }
+
+    // Return the given raw pointer's slot to the freelist without running any destructor.
+    //
+    // Same contract as `deallocate`, but the caller is responsible for the slot's contents having
+    // already been moved out or dropped.
+    #[inline]
+    fn release(&self, ptr: *mut T) {
+        self.0.live.set(self.0.live.get() - 1);
+        #[cfg(feature = "trace")]
+        self.record(trace::Op::Free, ptr);
+        #[cfg(feature = "lifetimes")]
+        self.0.lifetimes.borrow_mut().died(ptr as *mut u8);
+        if self.0.zeroize.get() {
+            let bytes = ptr as *mut u8;
+            unsafe {
+                for i in 0..mem::size_of::<T>() {
+                    ptr::write_volatile(bytes.add(i), 0);
+                }
+            }
+            // Keep later accesses to the slot from being reordered before the zeroing.
+            atomic::compiler_fence(atomic::Ordering::SeqCst);
+        }
+        #[cfg(feature = "backtrace")]
+        self.untrack(ptr);
+        // Whatever stale pointers still lead here should read garbage, not a plausible `T`.
+        #[cfg(feature = "poison")]
+        unsafe {
+            ptr::write_bytes(ptr as *mut u8, POISON, mem::size_of::<T>());
+        }
+        // There is nothing to reuse in the slot of a ZST.
+        if mem::size_of::<T>() != 0 {
+            // The next object in the slot starts out untagged.
+            #[cfg(feature = "tags")]
+            unsafe {
+                Slot::set_tag(Slot::from_value(ptr), None);
+            }
+            let mut freelist = self.0.freelist.borrow_mut();
+            freelist.push(ptr as *mut u8);
+            self.0.peak_free.set(cmp::max(self.0.peak_free.get(), freelist.len()));
+        }
     }
 
     #[inline(never)]
     #[cold]
     fn grow(&self) {
+        // Only ever called once the current chunk is used up, so no slot is left behind in it.
+        debug_assert!(self.0.ptr == self.0.end, "reap: growing with slots left in the chunk");
         let mut chunks = self.0.chunks.borrow_mut();
         let new_cap;
         if let Some(last_chunk) = chunks.last_mut() {
@@ -191,13 +1119,297 @@ impl<T> Reap<T> {
             // Something something fail early, fail loudly.
             new_cap = prev_cap.checked_mul(2).expect("capacity overflow");
         } else {
-            let elem_size = cmp::max(1, mem::size_of::<T>());
-            new_cap = PAGE / elem_size;
+            let elem_size = cmp::max(1, mem::size_of::<Slot<T>>());
+            new_cap = cmp::max(1, PAGE / elem_size);
+        }
+        drop(chunks);
+        #[cfg(feature = "mlock")]
+        {
+            if self.0.mlock.get() {
+                return self.push_chunk(Chunk::new_locked::<Slot<T>>(new_cap));
+            }
+        }
+        let prefetched = self.0.background.borrow_mut().take(new_cap);
+        match prefetched {
+            Some(chunk) => self.push_chunk(chunk),
+            None => self.push_chunk(Chunk::new::<Slot<T>>(new_cap)),
+        }
+    }
+
+    /// Touches every page of memory this `Reap` has reserved but not handed out yet, so that the
+    /// operating system backs them with memory now rather than on the first allocation to reach
+    /// them.
+    ///
+    /// Freshly allocated chunks are often only mapped lazily, and the first write to each page
+    /// then stalls on a page fault. Calling this after `with_capacity`, outside of the
+    /// latency-sensitive path, takes those faults up front. Slots on the freelist have been used
+    /// before and are left alone, as are zero-sized types, which take no memory. Returns the
+    /// number of 4096-byte pages touched.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use reap::Reap;
+    ///
+    /// let reap = Reap::<[u8; 64]>::with_capacity(1024);
+    /// assert!(reap.prefault() >= 16);
+    /// ```
+    pub fn prefault(&self) -> usize {
+        let (mut ptr, end) = (self.0.ptr.get() as usize, self.0.end.get() as usize);
+        let mut pages = 0;
+        while ptr < end {
+            // Never handed out, so nothing can be reading these bytes.
+            unsafe {
+                ptr::write_volatile(ptr as *mut u8, 0);
+            }
+            pages += 1;
+            ptr = (ptr & !(PAGE - 1)) + PAGE;
         }
-        let chunk = Chunk::new(new_cap);
+        pages
+    }
+
+    // Makes `chunk` the current chunk, to be bump allocated from.
+    #[inline]
+    fn push_chunk(&self, chunk: Chunk) {
         self.0.ptr.set(chunk.start());
-        self.0.end.set(chunk.end());
-        chunks.push(chunk);
+        self.0.end.set(chunk.end::<Slot<T>>() as *mut u8);
+        self.0.chunks.borrow_mut().push(chunk);
+        self.arm_background_growth();
+        self.notify_growth();
+    }
+
+    // Records the caller's location, and a backtrace, for the new object at `ptr`.
+    #[cfg(any(feature = "backtrace", feature = "callsites"))]
+    #[inline(never)]
+    #[track_caller]
+    fn track(&self, ptr: *mut T) {
+        #[cfg(feature = "callsites")]
+        self.0.call_sites.borrow_mut().count(Location::caller());
+        #[cfg(feature = "backtrace")]
+        {
+            let entry = (Location::caller(), Backtrace::force_capture());
+            self.0.sites.borrow_mut().entry(ptr as *mut u8).or_default().push(entry);
+        }
+        #[cfg(not(feature = "backtrace"))]
+        let _ = ptr;
+    }
+
+    // Forgets the allocation record of the object at `ptr`.
+    #[cfg(feature = "backtrace")]
+    fn untrack(&self, ptr: *mut T) {
+        let ptr = ptr as *mut u8;
+        let mut sites = self.0.sites.borrow_mut();
+        if let Some(entries) = sites.get_mut(&ptr) {
+            entries.pop();
+            if entries.is_empty() {
+                sites.remove(&ptr);
+            }
+        }
+    }
+
+    /// Returns a listing of every live object in this `Reap`, with the location and backtrace of
+    /// the call that allocated it.
+    ///
+    /// Only available with the `backtrace` feature, which records this information for every
+    /// allocation. That is expensive, it is meant for tracking down what is keeping objects (and
+    /// so the arena) alive.
+    #[cfg(feature = "backtrace")]
+    pub fn dump_live(&self) -> String {
+        use std::fmt::Write;
+
+        let sites = self.0.sites.borrow();
+        let mut out = String::new();
+        let _ = writeln!(out, "{} live object(s)", self.0.live.get());
+        for (ptr, entries) in sites.iter() {
+            #[cfg(feature = "tags")]
+            let tag = self.tag_of(*ptr as *mut T)
+                .map_or(String::new(), |tag| format!(" ({})", tag));
+            #[cfg(not(feature = "tags"))]
+            let tag = "";
+            for &(location, ref backtrace) in entries {
+                let _ = writeln!(out,
+                                 "\n{:p}{} allocated at {}\n{}",
+                                 *ptr,
+                                 tag,
+                                 location,
+                                 backtrace);
+            }
+        }
+        out
+    }
+
+    // Returns the tag of the live object at `ptr`.
+    #[cfg(feature = "tags")]
+    #[inline]
+    fn tag_of(&self, ptr: *mut T) -> Option<&'static str> {
+        if mem::size_of::<T>() == 0 {
+            None
+        } else {
+            unsafe { Slot::tag(Slot::from_value(ptr)) }
+        }
+    }
+
+    /// Checks the internal bookkeeping of this `Reap` for corruption.
+    ///
+    /// Only available with the `verify` feature. Every pointer on the freelist must point to the
+    /// start of a slot handed out before, and appear at most once, and the freed and live slots
+    /// must account for all handed out slots. Meant to be sprinkled through tests of unsafe code
+    /// built on the arena.
+    ///
+    /// # Panics
+    ///
+    /// Panics with a description of the first inconsistency found, if any.
+    #[cfg(feature = "verify")]
+    pub fn verify(&self) {
+        use std::collections::HashSet;
+
+        if mem::size_of::<T>() == 0 {
+            return;
+        }
+        let slot_size = mem::size_of::<Slot<T>>();
+        let offset = mem::offset_of!(Slot<T>, value);
+        let chunks = self.0.chunks.borrow();
+        let bump = self.0.ptr.get() as usize;
+        // Address ranges of the slots handed out so far, in each chunk.
+        let touched: Vec<_> = chunks.iter()
+            .enumerate()
+            .map(|(i, chunk)| {
+                let start = chunk.start::<Slot<T>>() as usize;
+                let end = if i + 1 == chunks.len() {
+                    bump
+                } else {
+                    chunk.end::<Slot<T>>() as usize
+                };
+                assert!(start <= end && end <= chunk.end::<Slot<T>>() as usize,
+                        "reap: bump pointer {:#x} is outside of the current chunk",
+                        bump);
+                start..end
+            })
+            .collect();
+
+        let freelist = self.0.freelist.borrow();
+        let mut seen = HashSet::with_capacity(freelist.len());
+        for &ptr in freelist.iter() {
+            let addr = (ptr as usize).wrapping_sub(offset);
+            let range = touched.iter()
+                .find(|range| range.contains(&addr))
+                .unwrap_or_else(|| {
+                    panic!("reap: free pointer {:p} is not in a handed out slot", ptr)
+                });
+            assert!((addr - range.start) % slot_size == 0,
+                    "reap: free pointer {:p} is not at the start of a slot",
+                    ptr);
+            assert!(seen.insert(ptr), "reap: free pointer {:p} is on the freelist twice", ptr);
+        }
+
+        let handed_out: usize =
+            touched.iter().map(|range| (range.end - range.start) / slot_size).sum();
+        assert!(self.0.live.get() + freelist.len() == handed_out,
+                "reap: {} live and {} free slots don't add up to the {} handed out",
+                self.0.live.get(),
+                freelist.len(),
+                handed_out);
+    }
+
+    /// Checks the guard words around every slot ever allocated in this `Reap`.
+    ///
+    /// Only available with the `canary` feature, which places a guard word before and after each
+    /// slot. Guard words are also checked whenever an object is deallocated.
+    ///
+    /// # Panics
+    ///
+    /// Panics if any guard word has been overwritten, e.g. by unsafe code writing past the end of
+    /// an object through a pointer into the arena.
+    #[cfg(feature = "canary")]
+    pub fn check_canaries(&self) {
+        if mem::size_of::<T>() == 0 {
+            return;
+        }
+        let chunks = self.0.chunks.borrow();
+        for (i, chunk) in chunks.iter().enumerate() {
+            // Every chunk but the current one was filled up before the next was allocated.
+            let end = if i + 1 == chunks.len() {
+                self.0.ptr.get() as *mut Slot<T>
+            } else {
+                chunk.end()
+            };
+            let mut slot = chunk.start::<Slot<T>>();
+            while slot < end {
+                unsafe {
+                    Slot::check(slot);
+                    slot = slot.offset(1);
+                }
+            }
+        }
+    }
+}
+
+// A `Reap` never lets user code run while any of its internal state is borrowed or half-updated,
+// and it never hands out the objects it stores except through their `Rp`s, so a panic can't leave
+// anything observable through a `Reap` broken.
+impl<T> UnwindSafe for Reap<T> {}
+impl<T> RefUnwindSafe for Reap<T> {}
+
+// Gives a slot back to whatever it came from when dropped, unless forgotten.
+//
+// Shared by every arena in the crate, to release a slot even if the code holding it unwinds: an
+// initializer that panics before the slot is filled, or a destructor that panics on the way out.
+struct Release<F>
+    where F: FnOnce()
+{
+    release: Option<F>,
+}
+
+impl<F> Release<F>
+    where F: FnOnce()
+{
+    #[inline]
+    fn new(release: F) -> Release<F> {
+        Release { release: Some(release) }
+    }
+}
+
+impl<F> Drop for Release<F>
+    where F: FnOnce()
+{
+    #[inline]
+    fn drop(&mut self) {
+        if let Some(release) = self.release.take() {
+            release();
+        }
+    }
+}
+
+impl<T> fmt::Debug for Reap<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_tuple("Reap").field(&self.stats()).finish()
+    }
+}
+
+/// The error returned when a `Reap` fails to allocate memory.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum AllocError {
+    /// The requested number of objects would take more than `isize::MAX` bytes.
+    CapacityOverflow,
+    /// The allocator failed to provide the memory.
+    OutOfMemory,
+}
+
+impl fmt::Display for AllocError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            AllocError::CapacityOverflow => f.write_str("capacity overflow"),
+            AllocError::OutOfMemory => f.write_str("memory allocation failed"),
+        }
+    }
+}
+
+impl error::Error for AllocError {}
+
+impl<T> Default for Reap<T> {
+    #[inline]
+    fn default() -> Reap<T> {
+        Reap::new()
     }
 }
 
@@ -212,8 +1424,32 @@ impl<T> Clone for Reap<T> {
 }
 
 /// Reap smart pointer.
+///
+/// Like `Box<T>`, an `Rp<T>` is covariant in `T`, so e.g. an `Rp<&'static str>` can be used where
+/// an `Rp<&'a str>` is expected. So is `Reap<T>`: it never reads an object back out of a slot, nor
+/// drops one, so a `Reap<&'static str>` handing out slots to a `Reap<&'a str>` of the same arena is
+/// harmless. Each object is dropped by its own handle, which can't outlive what the object
+/// borrows:
+///
+/// ```compile_fail
+/// use reap::Reap;
+///
+/// let reap: Reap<&'static str> = Reap::new();
+/// let rp;
+/// {
+///     let local = String::from("local");
+///     let shorter: Reap<&str> = reap.clone();
+///     rp = shorter.allocate(&local[..]);
+/// }
+/// drop(rp);
+/// ```
+///
+/// Dropping an `Rp` drops its object before giving the slot back, and the destructor may use the
+/// same `Reap` in the meantime: allocate from it, or drop other handles into it. If the destructor
+/// panics, the slot is given back all the same, and the arena stays usable once the panic is
+/// caught.
 pub struct Rp<T> {
-    ptr: *mut T,
+    ptr: NonNull<T>,
     reap: Reap<T>,
     _marker: marker::PhantomData<T>,
 }
@@ -226,8 +1462,17 @@ impl<T> Rp<T> {
     /// This function is highly unsafe and can lead to all sorts of laundry-eating bad if its
     /// invariants are not maintained.
     ///
-    /// * `ptr` **must** have been previously returned from a call to `Rp::into_raw`.
-    /// * `reap` **must** be the same `Reap` that allocated `ptr`.
+    /// * `raw` **must** have been previously returned from a call to `Rp::into_raw`, and not
+    ///   converted back since.
+    /// * `reap` **must** be the same `Reap` that allocated `raw`.
+    ///
+    /// In debug builds the `Reap` keeps track of the pointers given out by `Rp::into_raw`, and
+    /// this function panics if `raw` is not currently one of them, e.g. because it was already
+    /// converted back, or belongs to another `Reap`.
+    ///
+    /// Debug builds also give every object a generation of its own, kept in its slot and in the
+    /// `RawRp` for it. So a stale `RawRp`, whose slot has since been reused *and* handed out by
+    /// `into_raw` again, is caught as well, and the new one can still be converted back.
     ///
     /// # Examples
     ///
@@ -237,23 +1482,51 @@ impl<T> Rp<T> {
     /// let reap = Reap::new();
     ///
     /// let x = reap.allocate(101);
-    /// let (x_ptr, r) = Rp::into_raw(x);
+    /// let (x_raw, r) = Rp::into_raw(x);
     ///
     /// unsafe {
     ///     // Convert back to an `Rp` to prevent leak.
-    ///     let x = Rp::from_raw(x_ptr, r);
+    ///     let x = Rp::from_raw(x_raw, r);
     ///     assert_eq!(*x, 101);
     ///
-    ///     // Further calls to `Rc::from_raw(x_ptr, r)` would be memory unsafe.
+    ///     // Further calls to `Rp::from_raw(x_raw, r)` would be memory unsafe.
     /// }
     ///
-    /// // `x` went out of scope above so the memory is considered free, so `x_ptr` is now dangling!
+    /// // `x` went out of scope above so the memory is considered free, so `x_raw` is now dangling!
     /// ```
     #[inline]
-    pub unsafe fn from_raw(ptr: *mut T, reap: Reap<T>) -> Rp<T> {
+    pub unsafe fn from_raw(raw: RawRp<T>, reap: Reap<T>) -> Rp<T> {
+        let ptr = raw.ptr;
+        #[cfg(debug_assertions)]
+        {
+            let mut escaped = reap.0.escaped.borrow_mut();
+            match escaped.get_mut(&(ptr as *mut u8)) {
+                // Only slots have generations, ZSTs all share an address instead.
+                Some(_) if mem::size_of::<T>() != 0 &&
+                           Slot::generation(Slot::from_value(ptr)) != raw.generation => {
+                    panic!("Rp::from_raw: {:p} is stale, its slot was reused since Rp::into_raw",
+                           ptr)
+                }
+                Some(n) if *n > 1 => *n -= 1,
+                Some(_) => {
+                    escaped.remove(&(ptr as *mut u8));
+                }
+                None => {
+                    panic!("Rp::from_raw: {:p} was not given out by Rp::into_raw on this Reap",
+                           ptr)
+                }
+            }
+        }
+        Rp::from_parts(ptr, reap)
+    }
+
+    // Constructs an `Rp` from its parts, with none of `from_raw`'s checks.
+    #[inline]
+    unsafe fn from_parts(ptr: *mut T, reap: Reap<T>) -> Rp<T> {
         Rp {
-            ptr: ptr,
-            reap: reap,
+            // Every slot pointer, even the made up one for ZSTs, is non-null.
+            ptr: NonNull::new_unchecked(ptr),
+            reap,
             _marker: marker::PhantomData,
         }
     }
@@ -261,7 +1534,7 @@ impl<T> Rp<T> {
     /// Consumes the `Rp`, returning the wrapped pointer and associated `Reap`.
     ///
     /// To avoid a memory leak the pointer must be converted back to an `Rp` using `Rp::from_raw`
-    /// with its associated `Reap`.
+    /// with its associated `Reap`. `RawRp::as_ptr` gives access to the object in the meantime.
     ///
     /// # Examples
     ///
@@ -271,26 +1544,167 @@ impl<T> Rp<T> {
     /// let reap = Reap::new();
     ///
     /// let x = reap.allocate(101);
-    /// let (x_ptr, r) = Rp::into_raw(x);
-    /// assert_eq!(unsafe { *x_ptr }, 101);
+    /// let (x_raw, r) = Rp::into_raw(x);
+    /// assert_eq!(unsafe { *x_raw.as_ptr() }, 101);
     ///
     /// unsafe {
     ///     // Convert back to an `Rp` to prevent leak.
-    ///     let x = Rp::from_raw(x_ptr, r);
+    ///     let x = Rp::from_raw(x_raw, r);
     ///     assert_eq!(*x, 101);
     /// }
     ///
     #[inline]
-    pub fn into_raw(mut this: Rp<T>) -> (*mut T, Reap<T>) {
-        let ptr = this.ptr;
-        // If there is another way to do this someone please tell me, this just feels wrong.
-        // I know I could just clone the `Reap` but I'd rather not unnecessarily increment the
-        // refcount.
-        let reap = unsafe { mem::replace(&mut this.reap, mem::uninitialized()) };
+    pub fn into_raw(this: Rp<T>) -> (RawRp<T>, Reap<T>) {
+        let (ptr, reap) = Rp::into_parts(this);
+        #[cfg(debug_assertions)]
+        {
+            *reap.0.escaped.borrow_mut().entry(ptr as *mut u8).or_insert(0) += 1;
+        }
+        let raw = RawRp {
+            ptr,
+            #[cfg(debug_assertions)]
+            generation: if mem::size_of::<T>() == 0 {
+                0
+            } else {
+                unsafe { Slot::generation(Slot::from_value(ptr)) }
+            },
+        };
+        (raw, reap)
+    }
+
+    // Takes an `Rp` apart without running its destructor, with none of `into_raw`'s bookkeeping.
+    #[inline]
+    fn into_parts(this: Rp<T>) -> (*mut T, Reap<T>) {
+        let ptr = this.ptr.as_ptr();
+        // Move the `Reap` out without touching the refcount, then forget `this` so that neither
+        // the destructor nor the `Reap` field's drop glue run.
+        let reap = unsafe { ptr::read(&this.reap) };
         mem::forget(this);
         (ptr, reap)
     }
 
+    // Moves the value out of the `Rp`, returning its slot to the `Reap` without dropping it.
+    #[inline]
+    fn take(this: Rp<T>) -> T {
+        let (ptr, reap) = Rp::into_parts(this);
+        unsafe {
+            let value = ptr::read(ptr);
+            reap.release(ptr);
+            value
+        }
+    }
+
+    /// Moves the value out of the arena into a `Box<T>`, freeing its slot.
+    ///
+    /// This is for the odd object that needs to outlive its arena, without requiring `T: Clone`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use reap::{Reap, Rp};
+    ///
+    /// let reap = Reap::new();
+    /// let x = Rp::into_box(reap.allocate(101));
+    /// drop(reap);
+    /// assert_eq!(*x, 101);
+    /// ```
+    #[inline]
+    pub fn into_box(this: Rp<T>) -> Box<T> {
+        Box::new(Rp::take(this))
+    }
+
+    /// Pins the object in its slot, like `Box::into_pin`.
+    ///
+    /// See `Reap::allocate_pinned`.
+    #[inline]
+    pub fn into_pin(this: Rp<T>) -> Pin<Rp<T>> {
+        // The object is never moved out of its slot again without `Pin::into_inner_unchecked`, and
+        // the slot is only reused after the object has been dropped.
+        unsafe { Pin::new_unchecked(this) }
+    }
+
+    /// Consumes the `Rp`, returning a reference to its object for as long as `T` lives.
+    ///
+    /// Like `Box::leak`, the object is never dropped and its slot is never reused. The handle to
+    /// the `Reap` is leaked along with it, so the arena and all of its memory are never freed
+    /// either: this is meant for arenas that live for the rest of the program, see `Reap::leak`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use reap::{Reap, Rp};
+    ///
+    /// let reap = Reap::new();
+    /// let x: &'static mut u32 = Rp::leak(reap.allocate(1));
+    /// *x += 1;
+    /// drop(reap);
+    /// assert_eq!(*x, 2);
+    /// ```
+    #[inline]
+    pub fn leak<'a>(this: Rp<T>) -> &'a mut T
+        where T: 'a
+    {
+        let (ptr, reap) = Rp::into_parts(this);
+        mem::forget(reap);
+        unsafe { &mut *ptr }
+    }
+
+    /// Moves the value out of the arena into an `Rc<T>`, freeing its slot.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use reap::{Reap, Rp};
+    ///
+    /// let reap = Reap::new();
+    /// let x = Rp::into_rc(reap.allocate(101));
+    /// assert_eq!(reap.stats().live, 0);
+    /// assert_eq!(*x.clone(), 101);
+    /// ```
+    #[inline]
+    pub fn into_rc(this: Rp<T>) -> Rc<T> {
+        Rc::new(Rp::take(this))
+    }
+
+    /// Moves the value into `target`, freeing its slot in its current arena.
+    ///
+    /// Returns `this` untouched if it already is in `target`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use reap::{Reap, Rp};
+    ///
+    /// let (worker, main) = (Reap::new(), Reap::new());
+    /// let result = worker.allocate(String::from("done"));
+    /// let result = Rp::migrate(result, &main);
+    /// assert!(main.owns(&result));
+    /// assert_eq!(worker.stats().live, 0);
+    /// ```
+    #[inline]
+    #[cfg_attr(any(feature = "backtrace", feature = "callsites"), track_caller)]
+    pub fn migrate(this: Rp<T>, target: &Reap<T>) -> Rp<T> {
+        if target.owns(&this) {
+            return this;
+        }
+        target.allocate(Rp::take(this))
+    }
+
+    /// Returns the id of the arena this `Rp<T>` was allocated in, as returned by `Reap::id`.
+    #[inline]
+    pub fn arena_id(&self) -> u64 {
+        self.reap.id()
+    }
+
+    /// Returns the tag the object was allocated with by `Reap::allocate_named`, if any.
+    ///
+    /// Only available with the `tags` feature.
+    #[cfg(feature = "tags")]
+    #[inline]
+    pub fn tag(this: &Rp<T>) -> Option<&'static str> {
+        this.reap.tag_of(this.ptr.as_ptr())
+    }
+
     /// Returns a reference to this `Rp<T>`'s associated `Reap<T>`.
     #[inline]
     pub fn reap(&self) -> &Reap<T> {
@@ -298,6 +1712,10 @@ impl<T> Rp<T> {
     }
 }
 
+// Same as for `Box<T>`.
+impl<T> UnwindSafe for Rp<T> where T: UnwindSafe {}
+impl<T> RefUnwindSafe for Rp<T> where T: RefUnwindSafe {}
+
 impl<T> PartialEq for Rp<T>
     where T: PartialEq
 {
@@ -305,11 +1723,6 @@ impl<T> PartialEq for Rp<T>
     fn eq(&self, other: &Rp<T>) -> bool {
         PartialEq::eq(&**self, &**other)
     }
-
-    #[inline]
-    fn ne(&self, other: &Rp<T>) -> bool {
-        PartialEq::ne(&**self, &**other)
-    }
 }
 
 impl<T> PartialOrd for Rp<T>
@@ -370,10 +1783,24 @@ impl<T> fmt::Display for Rp<T>
     }
 }
 
+// With the `layout` feature, `{:#?}` also shows where the object lives, in the terms of
+// `Reap::dump_layout`.
 impl<T> fmt::Debug for Rp<T>
     where T: fmt::Debug
 {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        #[cfg(feature = "layout")]
+        {
+            if f.alternate() {
+                let mut rp = f.debug_struct("Rp");
+                rp.field("value", &**self).field("arena", &self.reap.id());
+                // Zero-sized objects live in no chunk.
+                if let Some((chunk, slot)) = self.reap.position(self.ptr.as_ptr() as usize) {
+                    rp.field("chunk", &chunk).field("slot", &slot);
+                }
+                return rp.finish();
+            }
+        }
         fmt::Debug::fmt(&**self, f)
     }
 }
@@ -384,6 +1811,27 @@ impl<T> fmt::Pointer for Rp<T> {
     }
 }
 
+// Moving an `Rp` doesn't move its object, so it can be `Unpin` even when `T` isn't, like `Box`.
+impl<T> Unpin for Rp<T> {}
+
+impl<T> From<Rp<T>> for Pin<Rp<T>> {
+    #[inline]
+    fn from(rp: Rp<T>) -> Pin<Rp<T>> {
+        Rp::into_pin(rp)
+    }
+}
+
+impl<F> Future for Rp<F>
+    where F: Future + Unpin
+{
+    type Output = F::Output;
+
+    #[inline]
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<F::Output> {
+        F::poll(Pin::new(&mut **self), cx)
+    }
+}
+
 impl<I> Iterator for Rp<I>
     where I: Iterator
 {
@@ -410,25 +1858,39 @@ impl<I> ExactSizeIterator for Rp<I> where I: ExactSizeIterator {}
 
 impl<T> borrow::Borrow<T> for Rp<T> {
     fn borrow(&self) -> &T {
-        &**self
+        self
     }
 }
 
 impl<T> borrow::BorrowMut<T> for Rp<T> {
     fn borrow_mut(&mut self) -> &mut T {
-        &mut **self
+        self
+    }
+}
+
+// `String` and `Vec` hash and compare like what they deref to, so sets and maps keyed by these
+// handles can be queried with a plain `&str` or slice.
+impl borrow::Borrow<str> for Rp<String> {
+    fn borrow(&self) -> &str {
+        self
+    }
+}
+
+impl<T> borrow::Borrow<[T]> for Rp<Vec<T>> {
+    fn borrow(&self) -> &[T] {
+        self
     }
 }
 
 impl<T> AsRef<T> for Rp<T> {
     fn as_ref(&self) -> &T {
-        &**self
+        self
     }
 }
 
 impl<T> AsMut<T> for Rp<T> {
     fn as_mut(&mut self) -> &mut T {
-        &mut **self
+        self
     }
 }
 
@@ -437,19 +1899,52 @@ impl<T> Deref for Rp<T> {
 
     #[inline]
     fn deref(&self) -> &T {
-        unsafe { &*self.ptr }
+        unsafe { self.ptr.as_ref() }
     }
 }
 
 impl<T> DerefMut for Rp<T> {
     #[inline]
     fn deref_mut(&mut self) -> &mut T {
-        unsafe { &mut *self.ptr }
+        unsafe { self.ptr.as_mut() }
     }
 }
 
 impl<T> Drop for Rp<T> {
     fn drop(&mut self) {
-        self.reap.deallocate(self.ptr)
+        self.reap.deallocate(self.ptr.as_ptr())
+    }
+}
+
+/// A raw pointer to an object of a `Reap`, as returned by `Rp::into_raw`.
+///
+/// In release builds this is just the pointer. Debug builds add the generation of the object, so
+/// that `Rp::from_raw` can tell a stale `RawRp` from one for the object now in the same slot.
+pub struct RawRp<T> {
+    ptr: *mut T,
+    #[cfg(debug_assertions)]
+    generation: u64,
+}
+
+impl<T> RawRp<T> {
+    /// Returns the pointer to the object.
+    #[inline]
+    pub fn as_ptr(self) -> *mut T {
+        self.ptr
+    }
+}
+
+impl<T> Clone for RawRp<T> {
+    #[inline]
+    fn clone(&self) -> RawRp<T> {
+        *self
+    }
+}
+
+impl<T> Copy for RawRp<T> {}
+
+impl<T> fmt::Debug for RawRp<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_tuple("RawRp").field(&self.ptr).finish()
     }
 }
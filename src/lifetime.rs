@@ -0,0 +1,168 @@
+//! Profiling how long objects live.
+//!
+//! Only available with the `lifetimes` feature. A `Reap` then counts its allocations, notes the
+//! count at which every object was allocated, and when the object is freed, files the number of
+//! allocations made in between under its power of two in a histogram. Measuring lifetimes in
+//! allocations rather than time makes the result independent of the machine and load, and says
+//! directly how many slots would have to be kept around to serve them: the data needed to tune
+//! chunk sizes and freelist policy.
+//!
+//! # Examples
+//!
+//! ```
+//! use reap::Reap;
+//!
+//! let reap = Reap::new();
+//! let long_lived = reap.allocate(0);
+//! for i in 0..100 {
+//!     drop(reap.allocate(i));
+//! }
+//! drop(long_lived);
+//!
+//! let histogram = reap.lifetime_histogram();
+//! assert_eq!(histogram.total(), 101);
+//! // The temporaries were freed before the next allocation.
+//! assert_eq!(histogram.counts()[0], 100);
+//! println!("{}", histogram);
+//! ```
+
+use std::collections::HashMap;
+use std::fmt;
+
+use super::Reap;
+
+// Number of buckets: lifetimes of 0, and of [2^(i - 1), 2^i) for every bit of a `u64`.
+const BUCKETS: usize = 65;
+
+/// A histogram of object lifetimes, measured in allocations, as returned by
+/// `Reap::lifetime_histogram`.
+#[derive(Clone, PartialEq, Eq)]
+pub struct LifetimeHistogram {
+    counts: [u64; BUCKETS],
+}
+
+impl LifetimeHistogram {
+    /// Returns the number of freed objects in each bucket.
+    ///
+    /// Bucket 0 counts objects freed before any other allocation was made, and bucket `i` those
+    /// freed after between `2^(i - 1)` and `2^i - 1` other allocations.
+    #[inline]
+    pub fn counts(&self) -> &[u64] {
+        &self.counts
+    }
+
+    /// Returns the range of lifetimes counted by bucket `i`.
+    #[inline]
+    pub fn bucket_range(i: usize) -> (u64, u64) {
+        match i {
+            0 => (0, 0),
+            _ => (1 << (i - 1), ((1u128 << i) - 1) as u64),
+        }
+    }
+
+    /// Returns the number of freed objects recorded.
+    #[inline]
+    pub fn total(&self) -> u64 {
+        self.counts.iter().sum()
+    }
+
+    // Returns the bucket of objects freed after `lifetime` other allocations.
+    #[inline]
+    fn bucket(lifetime: u64) -> usize {
+        (64 - lifetime.leading_zeros()) as usize
+    }
+}
+
+impl fmt::Debug for LifetimeHistogram {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        // Only the buckets in use, the rest are noise.
+        f.debug_map()
+            .entries(self.counts
+                .iter()
+                .enumerate()
+                .filter(|&(_, &count)| count != 0)
+                .map(|(i, count)| (LifetimeHistogram::bucket_range(i), count)))
+            .finish()
+    }
+}
+
+impl fmt::Display for LifetimeHistogram {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let total = self.total();
+        writeln!(f, "{} object(s) freed, lifetimes in allocations:", total)?;
+        let widest = self.counts.iter().cloned().max().unwrap_or(0);
+        for (i, &count) in self.counts.iter().enumerate() {
+            if count == 0 {
+                continue;
+            }
+            let (low, high) = LifetimeHistogram::bucket_range(i);
+            let bar = (40 * count / widest) as usize;
+            writeln!(f,
+                     "  {:>20} - {:<20} {:>10} {:5.1}% {}",
+                     low,
+                     high,
+                     count,
+                     100.0 * count as f64 / total as f64,
+                     "#".repeat(bar))?;
+        }
+        Ok(())
+    }
+}
+
+// The profiler state of a `Reap`.
+pub(super) struct Lifetimes {
+    // Number of allocations made so far.
+    clock: u64,
+    // When each live object was allocated, several entries per address for ZSTs.
+    births: HashMap<*mut u8, Vec<u64>>,
+    histogram: LifetimeHistogram,
+}
+
+impl Lifetimes {
+    #[inline]
+    pub(super) fn new() -> Lifetimes {
+        Lifetimes {
+            clock: 0,
+            births: HashMap::new(),
+            histogram: LifetimeHistogram { counts: [0; BUCKETS] },
+        }
+    }
+
+    // Notes that an object was allocated at `ptr`.
+    #[inline]
+    pub(super) fn born(&mut self, ptr: *mut u8) {
+        self.births.entry(ptr).or_default().push(self.clock);
+        self.clock += 1;
+    }
+
+    // Files the lifetime of the object at `ptr`, which was just freed.
+    #[inline]
+    pub(super) fn died(&mut self, ptr: *mut u8) {
+        let birth = match self.births.get_mut(&ptr) {
+            Some(births) => births.pop(),
+            None => None,
+        };
+        if let Some(birth) = birth {
+            if self.births[&ptr].is_empty() {
+                self.births.remove(&ptr);
+            }
+            // Not counting the object's own allocation.
+            let lifetime = self.clock - birth - 1;
+            self.histogram.counts[LifetimeHistogram::bucket(lifetime)] += 1;
+        }
+    }
+}
+
+impl<T> Reap<T> {
+    /// Returns the histogram of the lifetimes of every object freed so far.
+    pub fn lifetime_histogram(&self) -> LifetimeHistogram {
+        self.0.lifetimes.borrow().histogram.clone()
+    }
+
+    /// Clears the histogram, e.g. to leave out the warm-up of the program from the profile.
+    ///
+    /// Objects still live are counted when freed as usual.
+    pub fn reset_lifetime_histogram(&self) {
+        self.0.lifetimes.borrow_mut().histogram = LifetimeHistogram { counts: [0; BUCKETS] };
+    }
+}
@@ -0,0 +1,72 @@
+// Macros for allocating in one expression.
+
+/// Allocates a value in a `Reap`, for building nested structures in a single expression.
+///
+/// `rp!(in reap; value)` is `reap.allocate(value)`, with the arena in front where it reads best
+/// when nesting. `reap` may be a `Reap` or a reference to one.
+///
+/// # Examples
+///
+/// ```
+/// #[macro_use]
+/// extern crate reap;
+///
+/// use reap::{Reap, Rp};
+///
+/// enum Tree {
+///     Leaf(u32),
+///     Node(Rp<Tree>, Rp<Tree>),
+/// }
+///
+/// fn sum(tree: &Tree) -> u32 {
+///     match *tree {
+///         Tree::Leaf(n) => n,
+///         Tree::Node(ref left, ref right) => sum(left) + sum(right),
+///     }
+/// }
+///
+/// # fn main() {
+/// let reap = Reap::new();
+/// let tree = rp!(in reap; Tree::Node(
+///     rp!(in reap; Tree::Leaf(1)),
+///     rp!(in reap; Tree::Node(rp!(in reap; Tree::Leaf(2)), rp!(in reap; Tree::Leaf(3)))),
+/// ));
+/// assert_eq!(sum(&tree), 6);
+/// # }
+/// ```
+#[macro_export]
+macro_rules! rp {
+    (in $reap:expr; $value:expr) => {
+        ($reap).allocate($value)
+    };
+}
+
+/// Creates a `Reap` holding the given values, like `vec!`.
+///
+/// Expands to a call to `Reap::from_iter`, returning the arena along with a `Vec` of handles to
+/// the values, in order.
+///
+/// # Examples
+///
+/// ```
+/// #[macro_use]
+/// extern crate reap;
+///
+/// # fn main() {
+/// let (reap, rps) = reap!["a", "b", "c"];
+/// assert_eq!(*rps[1], "b");
+/// assert_eq!(reap.stats().live, 3);
+///
+/// let (_, zeros) = reap![0u8; 4];
+/// assert_eq!(zeros.len(), 4);
+/// # }
+/// ```
+#[macro_export]
+macro_rules! reap {
+    ($value:expr; $n:expr) => {
+        $crate::Reap::from_iter(::std::iter::repeat($value).take($n))
+    };
+    ($($value:expr),* $(,)?) => {
+        $crate::Reap::from_iter(vec![$($value),*])
+    };
+}
@@ -0,0 +1,372 @@
+//! A hash map whose entries live in a `Reap`.
+//!
+//! A `HashMap` moves its keys and values whenever it rehashes, so references into it can't
+//! outlive the next insertion. `ReapMap` only rehashes pointers: every key-value pair sits in its
+//! own arena slot and stays put until it is removed, so `&V`s handed out by `get` and
+//! `get_or_insert` stay valid across any number of further insertions.
+
+use std::borrow::Borrow;
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::collections::hash_map::RandomState;
+use std::fmt;
+use std::hash::{BuildHasher, Hash, Hasher};
+use std::ptr;
+
+use super::{Reap, Rp};
+
+// A key-value pair, allocated in the map's `Reap`.
+struct Entry<K, V> {
+    key: K,
+    value: V,
+}
+
+impl<K, V> Entry<K, V> {
+    // Pointers to the fields of the entry behind `entry`, taken from the `Rp`'s own pointer.
+    //
+    // Going through `Deref` or `DerefMut` instead would retag the whole entry, invalidating the
+    // `KeyPtr` in the index whenever a `&mut V` is handed out, and the other way round.
+    #[inline]
+    fn key(entry: &Rp<Entry<K, V>>) -> *const K {
+        unsafe { ptr::addr_of!((*entry.ptr.as_ptr()).key) }
+    }
+
+    #[inline]
+    fn value(entry: &Rp<Entry<K, V>>) -> *mut V {
+        unsafe { ptr::addr_of_mut!((*entry.ptr.as_ptr()).value) }
+    }
+}
+
+// Pointer to the key of an `Entry`, used as the key of the index.
+//
+// The pointee is owned by the `Rp` this pointer is stored next to, and never moves.
+struct KeyPtr<K>(*const K);
+
+impl<K> KeyPtr<K> {
+    #[inline]
+    fn get(&self) -> &K {
+        unsafe { &*self.0 }
+    }
+}
+
+impl<K> Hash for KeyPtr<K>
+    where K: Hash
+{
+    fn hash<H>(&self, state: &mut H)
+        where H: Hasher
+    {
+        self.get().hash(state);
+    }
+}
+
+impl<K> PartialEq for KeyPtr<K>
+    where K: PartialEq
+{
+    #[inline]
+    fn eq(&self, other: &KeyPtr<K>) -> bool {
+        self.get() == other.get()
+    }
+}
+
+impl<K> Eq for KeyPtr<K> where K: Eq {}
+
+// Lookup wrapper, lets the index be queried with any `Q` that `K` borrows as.
+//
+// `KeyPtr<K>` can't implement `Borrow<Q>` for all `K: Borrow<Q>` directly without overlapping the
+// blanket `Borrow<T> for T`.
+#[repr(transparent)]
+struct Lookup<Q: ?Sized>(Q);
+
+impl<Q: ?Sized> Lookup<Q> {
+    #[inline]
+    fn new(q: &Q) -> &Lookup<Q> {
+        unsafe { &*(q as *const Q as *const Lookup<Q>) }
+    }
+}
+
+impl<Q: ?Sized> Hash for Lookup<Q>
+    where Q: Hash
+{
+    fn hash<H>(&self, state: &mut H)
+        where H: Hasher
+    {
+        self.0.hash(state);
+    }
+}
+
+impl<Q: ?Sized> PartialEq for Lookup<Q>
+    where Q: PartialEq
+{
+    #[inline]
+    fn eq(&self, other: &Lookup<Q>) -> bool {
+        self.0 == other.0
+    }
+}
+
+impl<Q: ?Sized> Eq for Lookup<Q> where Q: Eq {}
+
+impl<K, Q: ?Sized> Borrow<Lookup<Q>> for KeyPtr<K>
+    where K: Borrow<Q>
+{
+    #[inline]
+    fn borrow(&self) -> &Lookup<Q> {
+        Lookup::new(self.get().borrow())
+    }
+}
+
+/// A hash map with arena-allocated entries and stable value references.
+///
+/// Insertion through `get_or_insert` only needs `&self`, while replacing or removing entries,
+/// which would invalidate outstanding references, needs `&mut self`.
+///
+/// # Examples
+///
+/// ```
+/// use reap::map::ReapMap;
+///
+/// let map = ReapMap::new();
+///
+/// let one = map.get_or_insert(1, "one");
+/// for i in 2..1000 {
+///     map.get_or_insert(i, "many");
+/// }
+///
+/// // `one` is still valid after all of the above insertions.
+/// assert_eq!(*one, "one");
+/// assert_eq!(map.get(&500), Some(&"many"));
+/// ```
+pub struct ReapMap<K, V, S = RandomState> {
+    reap: Reap<Entry<K, V>>,
+    index: RefCell<Index<K, V, S>>,
+}
+
+// Maps each key to the handle of the entry holding it.
+type Index<K, V, S> = HashMap<KeyPtr<K>, Rp<Entry<K, V>>, S>;
+
+impl<K, V> ReapMap<K, V, RandomState>
+    where K: Hash + Eq
+{
+    /// Creates an empty `ReapMap`.
+    #[inline]
+    pub fn new() -> ReapMap<K, V, RandomState> {
+        ReapMap::with_hasher(RandomState::new())
+    }
+
+    /// Creates an empty `ReapMap` with room for at least `capacity` entries.
+    #[inline]
+    pub fn with_capacity(capacity: usize) -> ReapMap<K, V, RandomState> {
+        ReapMap {
+            reap: Reap::with_capacity(capacity),
+            index: RefCell::new(HashMap::with_capacity(capacity)),
+        }
+    }
+}
+
+impl<K, V, S> ReapMap<K, V, S>
+    where K: Hash + Eq,
+          S: BuildHasher
+{
+    /// Creates an empty `ReapMap` which will use the given hash builder.
+    #[inline]
+    pub fn with_hasher(hash_builder: S) -> ReapMap<K, V, S> {
+        ReapMap {
+            reap: Reap::new(),
+            index: RefCell::new(HashMap::with_hasher(hash_builder)),
+        }
+    }
+
+    /// Returns the number of entries in the map.
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.index.borrow().len()
+    }
+
+    /// Returns `true` if the map contains no entries.
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.index.borrow().is_empty()
+    }
+
+    /// Returns `true` if the map contains an entry for `key`.
+    #[inline]
+    pub fn contains_key<Q>(&self, key: &Q) -> bool
+        where K: Borrow<Q>,
+              Q: ?Sized + Hash + Eq
+    {
+        self.index.borrow().contains_key(Lookup::new(key))
+    }
+
+    /// Returns a reference to the value for `key`, if present.
+    ///
+    /// The reference remains valid until the entry is removed or replaced through `&mut self`.
+    #[inline]
+    pub fn get<Q>(&self, key: &Q) -> Option<&V>
+        where K: Borrow<Q>,
+              Q: ?Sized + Hash + Eq
+    {
+        self.index
+            .borrow()
+            .get(Lookup::new(key))
+            // The entry's slot outlives the borrow of the index, see `get_or_insert`.
+            .map(|entry| unsafe { &*Entry::value(entry) })
+    }
+
+    /// Returns a mutable reference to the value for `key`, if present.
+    #[inline]
+    pub fn get_mut<Q>(&mut self, key: &Q) -> Option<&mut V>
+        where K: Borrow<Q>,
+              Q: ?Sized + Hash + Eq
+    {
+        self.index.get_mut().get(Lookup::new(key)).map(|entry| unsafe { &mut *Entry::value(entry) })
+    }
+
+    /// Returns a reference to the value for `key`, inserting `value` first if the key is absent.
+    ///
+    /// If the key is already present, `value` is dropped and the existing value is left as is.
+    pub fn get_or_insert(&self, key: K, value: V) -> &V {
+        self.get_or_insert_with(key, || value)
+    }
+
+    /// Returns a reference to the value for `key`, inserting the result of `f` first if the key
+    /// is absent.
+    ///
+    /// `f` may itself insert into the map.
+    pub fn get_or_insert_with<F>(&self, key: K, f: F) -> &V
+        where F: FnOnce() -> V
+    {
+        if let Some(value) = self.get(&key) {
+            return value;
+        }
+        // No borrow of the index is held while calling out to `f`.
+        let value = f();
+        let mut index = self.index.borrow_mut();
+        if let Some(entry) = index.get(Lookup::new(&key)) {
+            // `f` inserted this very key behind our back, first one in wins.
+            return unsafe { &*Entry::value(entry) };
+        }
+        let entry = self.reap.allocate(Entry { key, value });
+        let key_ptr = KeyPtr(Entry::key(&entry));
+        let value_ptr = Entry::value(&entry);
+        index.insert(key_ptr, entry);
+        // Entries are only ever dropped through `&mut self`, and their slots never move, so the
+        // value outlives this borrow of `self`.
+        unsafe { &*value_ptr }
+    }
+
+    /// Inserts a key-value pair, returning the previous value for `key` if there was one.
+    pub fn insert(&mut self, key: K, value: V) -> Option<V> {
+        let index = self.index.get_mut();
+        if let Some(entry) = index.get(Lookup::new(&key)) {
+            return Some(::std::mem::replace(unsafe { &mut *Entry::value(entry) }, value));
+        }
+        let entry = self.reap.allocate(Entry { key, value });
+        index.insert(KeyPtr(Entry::key(&entry)), entry);
+        None
+    }
+
+    /// Removes the entry for `key`, returning its value if it was present.
+    ///
+    /// The entry's slot is recycled by subsequent insertions.
+    pub fn remove<Q>(&mut self, key: &Q) -> Option<V>
+        where K: Borrow<Q>,
+              Q: ?Sized + Hash + Eq
+    {
+        self.remove_entry(key).map(|(_, value)| value)
+    }
+
+    /// Removes the entry for `key`, returning the stored key and value if it was present.
+    pub fn remove_entry<Q>(&mut self, key: &Q) -> Option<(K, V)>
+        where K: Borrow<Q>,
+              Q: ?Sized + Hash + Eq
+    {
+        self.index.get_mut().remove(Lookup::new(key)).map(|entry| {
+            let Entry { key, value } = Rp::take(entry);
+            (key, value)
+        })
+    }
+
+    /// Removes all entries from the map.
+    #[inline]
+    pub fn clear(&mut self) {
+        self.index.get_mut().clear();
+    }
+
+    /// Returns an iterator over the entries of the map, in arbitrary order.
+    ///
+    /// This takes `&mut self` because an insertion through `&self` could rehash the index out from
+    /// under the iterator.
+    #[inline]
+    pub fn iter(&mut self) -> Iter<'_, K, V> {
+        Iter(self.index.get_mut().values())
+    }
+
+    /// Returns an iterator over the entries of the map with mutable references to the values.
+    #[inline]
+    pub fn iter_mut(&mut self) -> IterMut<'_, K, V> {
+        IterMut(self.index.get_mut().values_mut())
+    }
+}
+
+impl<K, V, S> Default for ReapMap<K, V, S>
+    where K: Hash + Eq,
+          S: BuildHasher + Default
+{
+    #[inline]
+    fn default() -> ReapMap<K, V, S> {
+        ReapMap::with_hasher(S::default())
+    }
+}
+
+impl<K, V, S> fmt::Debug for ReapMap<K, V, S>
+    where K: fmt::Debug,
+          V: fmt::Debug
+{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_map()
+            .entries(self.index
+                .borrow()
+                .values()
+                .map(|entry| unsafe { (&*Entry::key(entry), &*Entry::value(entry)) }))
+            .finish()
+    }
+}
+
+/// An iterator over the entries of a `ReapMap`.
+pub struct Iter<'a, K: 'a, V: 'a>(::std::collections::hash_map::Values<'a,
+                                                                      KeyPtr<K>,
+                                                                      Rp<Entry<K, V>>>);
+
+impl<'a, K, V> Iterator for Iter<'a, K, V> {
+    type Item = (&'a K, &'a V);
+
+    #[inline]
+    fn next(&mut self) -> Option<(&'a K, &'a V)> {
+        self.0.next().map(|entry| unsafe { (&*Entry::key(entry), &*Entry::value(entry)) })
+    }
+
+    #[inline]
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.0.size_hint()
+    }
+}
+
+/// An iterator over the entries of a `ReapMap` with mutable references to the values.
+pub struct IterMut<'a, K: 'a, V: 'a>(::std::collections::hash_map::ValuesMut<'a,
+                                                                            KeyPtr<K>,
+                                                                            Rp<Entry<K, V>>>);
+
+impl<'a, K, V> Iterator for IterMut<'a, K, V> {
+    type Item = (&'a K, &'a mut V);
+
+    #[inline]
+    fn next(&mut self) -> Option<(&'a K, &'a mut V)> {
+        self.0
+            .next()
+            .map(|entry| unsafe { (&*Entry::key(entry), &mut *Entry::value(entry)) })
+    }
+
+    #[inline]
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.0.size_hint()
+    }
+}
@@ -0,0 +1,116 @@
+// Callbacks on arena growth, for `Reap::set_growth_observer`.
+
+use std::mem;
+
+use super::{Reap, Slot};
+
+/// A new chunk of a `Reap`, as passed to the observer set with `Reap::set_growth_observer`.
+///
+/// Slot counts are in units of objects, as in `ReapStats`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Growth {
+    /// Number of slots in the new chunk.
+    pub capacity: usize,
+    /// Number of chunks, including the new one.
+    pub chunks: usize,
+    /// Total number of slots across all chunks, including the new one.
+    pub total_capacity: usize,
+    /// Number of live objects when the chunk was added.
+    pub live: usize,
+    /// Size of a single slot in bytes.
+    pub slot_size: usize,
+}
+
+impl Growth {
+    /// Returns the number of bytes of slots in the new chunk.
+    #[inline]
+    pub fn bytes(&self) -> usize {
+        self.capacity * self.slot_size
+    }
+
+    /// Returns the total number of bytes allocated for chunks, including the new one.
+    #[inline]
+    pub fn reserved_bytes(&self) -> usize {
+        self.total_capacity * self.slot_size
+    }
+}
+
+// The observer set with `Reap::set_growth_observer`.
+pub(super) struct Observer {
+    callback: Option<Box<dyn FnMut(Growth)>>,
+    // Whether the callback was set since it was last taken out to be called.
+    replaced: bool,
+}
+
+impl Observer {
+    #[inline]
+    pub(super) fn new() -> Observer {
+        Observer {
+            callback: None,
+            replaced: false,
+        }
+    }
+}
+
+impl<T> Reap<T> {
+    /// Sets a callback to be called every time this `Reap` adds a chunk, or removes it with
+    /// `None`.
+    ///
+    /// Applications can log unexpected growth this way, or export it as a metric, and tests can
+    /// assert on when an arena allocates. The callback is called once the chunk is in place, and
+    /// may use the arena, but would keep it alive forever if it captured a `Reap` handle: capture
+    /// a `WeakReap` from `downgrade` instead. Zero-sized types never add chunks.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use reap::Reap;
+    /// use std::cell::Cell;
+    /// use std::rc::Rc;
+    ///
+    /// let reap = Reap::with_capacity(16);
+    /// let reserved = Rc::new(Cell::new(0));
+    /// let observed = reserved.clone();
+    /// reap.set_growth_observer(Some(Box::new(move |growth| {
+    ///     observed.set(growth.reserved_bytes());
+    /// })));
+    ///
+    /// let objects: Vec<_> = (0..100u64).map(|i| reap.allocate(i)).collect();
+    /// assert_eq!(reserved.get(), reap.stats().reserved_bytes());
+    /// # drop(objects);
+    /// ```
+    pub fn set_growth_observer(&self, observer: Option<Box<dyn FnMut(Growth)>>) {
+        let mut current = self.0.observer.borrow_mut();
+        current.callback = observer;
+        current.replaced = true;
+    }
+
+    // Calls the growth observer, if any, about the chunk just added.
+    pub(super) fn notify_growth(&self) {
+        // Taken out for the call, so that the observer may allocate from the arena in turn.
+        let mut callback = {
+            let mut observer = self.0.observer.borrow_mut();
+            observer.replaced = false;
+            match observer.callback.take() {
+                Some(callback) => callback,
+                None => return,
+            }
+        };
+        let growth = {
+            let chunks = self.0.chunks.borrow();
+            Growth {
+                capacity: chunks.last().map_or(0, |chunk| chunk.capacity()),
+                chunks: chunks.len(),
+                total_capacity: chunks.iter().map(|chunk| chunk.capacity()).sum(),
+                live: self.0.live.get(),
+                slot_size: mem::size_of::<Slot<T>>(),
+            }
+        };
+        callback(growth);
+        // Unless it set another observer, or none, meanwhile.
+        let mut observer = self.0.observer.borrow_mut();
+        if !observer.replaced {
+            observer.callback = Some(callback);
+        }
+    }
+}
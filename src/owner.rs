@@ -0,0 +1,153 @@
+//! Interior mutability checked once per owner instead of once per object.
+//!
+//! Mutating objects reachable through shared references, like the nodes of a tree handed around
+//! through `Frozen` handles or `alloc_extend` slices, usually means a `RefCell` per object, each
+//! with a borrow flag updated on every access. An `OwnerCell` instead belongs to an `Owner`, and is
+//! only accessed through it: reading borrows the owner, writing borrows it mutably, so the borrow
+//! checker rules out conflicting accesses to all of the owner's cells at once. The only check left
+//! at runtime is that a cell belongs to the owner it is accessed through, a comparison of ids.
+//!
+//! This is the scheme of `QCell` from the `qcell` crate. Within a single closure, the `Token` of a
+//! branded arena does the same without any check at all, see the `brand` module.
+//!
+//! # Examples
+//!
+//! ```
+//! use reap::{Reap, Rp};
+//! use reap::owner::{Owner, OwnerCell};
+//!
+//! let mut owner = Owner::new();
+//! let reap = Reap::new();
+//! let nodes: Vec<_> = (0..3).map(|i| Rp::freeze(reap.allocate(owner.cell(i)))).collect();
+//!
+//! // The handles only give shared access, the owner grants mutable access.
+//! for node in &nodes {
+//!     *owner.rw(node) *= 10;
+//! }
+//! let (first, last) = owner.rw2(&nodes[0], &nodes[2]);
+//! *first += *last;
+//! assert_eq!(*owner.ro(&nodes[0]), 20);
+//! ```
+
+use std::cell::UnsafeCell;
+use std::fmt;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+// Source of `Owner` ids, never reused.
+static NEXT_OWNER: AtomicU64 = AtomicU64::new(0);
+
+/// The key to a set of `OwnerCell`s.
+///
+/// An owner can't be cloned, so borrowing it mutably proves that none of its cells is borrowed
+/// elsewhere.
+pub struct Owner {
+    id: u64,
+}
+
+impl Owner {
+    /// Creates a new owner, distinct from every other.
+    #[inline]
+    pub fn new() -> Owner {
+        Owner { id: NEXT_OWNER.fetch_add(1, Ordering::Relaxed) }
+    }
+
+    /// Creates a new cell holding `value`, belonging to this owner.
+    #[inline]
+    pub fn cell<T>(&self, value: T) -> OwnerCell<T> {
+        OwnerCell {
+            owner: self.id,
+            value: UnsafeCell::new(value),
+        }
+    }
+
+    /// Returns a shared reference to the value of `cell`, for as long as the owner is borrowed.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `cell` belongs to another owner.
+    #[inline]
+    pub fn ro<'a, T: ?Sized>(&'a self, cell: &'a OwnerCell<T>) -> &'a T {
+        self.check(cell);
+        unsafe { &*cell.value.get() }
+    }
+
+    /// Returns a mutable reference to the value of `cell`, for as long as the owner is mutably
+    /// borrowed.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `cell` belongs to another owner.
+    #[inline]
+    #[allow(clippy::mut_from_ref)]
+    pub fn rw<'a, T: ?Sized>(&'a mut self, cell: &'a OwnerCell<T>) -> &'a mut T {
+        self.check(cell);
+        unsafe { &mut *cell.value.get() }
+    }
+
+    /// Returns mutable references to the values of two different cells at once.
+    ///
+    /// # Panics
+    ///
+    /// Panics if either cell belongs to another owner, or if both are the same cell.
+    #[inline]
+    #[allow(clippy::mut_from_ref)]
+    pub fn rw2<'a, T: ?Sized, U: ?Sized>(&'a mut self,
+                                         a: &'a OwnerCell<T>,
+                                         b: &'a OwnerCell<U>)
+                                         -> (&'a mut T, &'a mut U) {
+        self.check(a);
+        self.check(b);
+        assert!(a as *const OwnerCell<T> as *const () != b as *const OwnerCell<U> as *const (),
+                "reap: rw2 called with the same cell twice");
+        unsafe { (&mut *a.value.get(), &mut *b.value.get()) }
+    }
+
+    // Panics unless `cell` belongs to this owner.
+    #[inline]
+    fn check<T: ?Sized>(&self, cell: &OwnerCell<T>) {
+        assert!(cell.owner == self.id, "reap: cell accessed through an owner it doesn't belong to");
+    }
+}
+
+impl Default for Owner {
+    #[inline]
+    fn default() -> Owner {
+        Owner::new()
+    }
+}
+
+impl fmt::Debug for Owner {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Owner").field("id", &self.id).finish()
+    }
+}
+
+/// A value that can only be accessed through the `Owner` that created the cell.
+pub struct OwnerCell<T: ?Sized> {
+    owner: u64,
+    value: UnsafeCell<T>,
+}
+
+impl<T> OwnerCell<T> {
+    /// Consumes the cell, returning its value.
+    #[inline]
+    pub fn into_inner(self) -> T {
+        self.value.into_inner()
+    }
+}
+
+impl<T: ?Sized> OwnerCell<T> {
+    /// Returns a mutable reference to the value, which the mutable borrow of the cell makes
+    /// unique without the owner.
+    #[inline]
+    pub fn get_mut(&mut self) -> &mut T {
+        self.value.get_mut()
+    }
+}
+
+impl<T: ?Sized> fmt::Debug for OwnerCell<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        // The value can't be read without the owner.
+        f.debug_struct("OwnerCell").field("owner", &self.owner).finish_non_exhaustive()
+    }
+}
@@ -0,0 +1,219 @@
+//! A pool of long-lived session objects with idle eviction.
+//!
+//! Servers keep connections, sessions and the like around between requests: a request checks one
+//! out of the pool, and checks it back in when done. `SessionPool` stores them in a `Reap`,
+//! remembers when each was last checked in, and evicts the ones left idle for too long.
+//!
+//! # Examples
+//!
+//! ```
+//! use std::time::Duration;
+//!
+//! use reap::session::SessionPool;
+//!
+//! let pool = SessionPool::new();
+//! {
+//!     let mut conn = pool.checkout_or_insert_with(|| String::from("connection"));
+//!     conn.push_str(" #1");
+//!     // Checked back in when dropped.
+//! }
+//! assert_eq!(pool.idle(), 1);
+//! assert_eq!(*pool.checkout().unwrap(), "connection #1");
+//!
+//! let mut closed = Vec::new();
+//! pool.evict_idle(Duration::from_secs(0), |conn| closed.push(conn));
+//! assert_eq!(closed, ["connection #1"]);
+//! assert_eq!(pool.idle(), 0);
+//! ```
+
+use std::cell::RefCell;
+use std::collections::VecDeque;
+use std::fmt;
+use std::ops::{Deref, DerefMut};
+use std::rc::Rc;
+use std::time::{Duration, Instant};
+
+use super::{Reap, Rp};
+
+/// A pool of reusable sessions of type `T`.
+///
+/// Cloning a `SessionPool` produces another handle to the same pool.
+pub struct SessionPool<T>(Rc<InnerSessionPool<T>>);
+
+struct InnerSessionPool<T> {
+    reap: Reap<T>,
+    // Checked in sessions with the time they were checked in, least recently used first.
+    idle: RefCell<VecDeque<(Instant, Rp<T>)>>,
+}
+
+impl<T> SessionPool<T> {
+    /// Creates a new, empty pool.
+    #[inline]
+    pub fn new() -> SessionPool<T> {
+        SessionPool(Rc::new(InnerSessionPool {
+            reap: Reap::new(),
+            idle: RefCell::new(VecDeque::new()),
+        }))
+    }
+
+    /// Adds a new session to the pool, returning it checked out.
+    #[inline]
+    pub fn insert(&self, session: T) -> Session<T> {
+        self.wrap(self.0.reap.allocate(session))
+    }
+
+    /// Checks out the most recently used idle session, if any.
+    ///
+    /// Reusing the warmest session first lets the rest age out through `evict_idle`.
+    #[inline]
+    pub fn checkout(&self) -> Option<Session<T>> {
+        let rp = self.0.idle.borrow_mut().pop_back().map(|(_, rp)| rp);
+        rp.map(|rp| self.wrap(rp))
+    }
+
+    /// Checks out the most recently used idle session, or adds the result of `f` if there is
+    /// none.
+    #[inline]
+    pub fn checkout_or_insert_with<F>(&self, f: F) -> Session<T>
+        where F: FnOnce() -> T
+    {
+        match self.checkout() {
+            Some(session) => session,
+            None => self.insert(f()),
+        }
+    }
+
+    /// Returns the number of checked in sessions.
+    #[inline]
+    pub fn idle(&self) -> usize {
+        self.0.idle.borrow().len()
+    }
+
+    /// Returns the number of sessions in the pool, checked out or not.
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.0.reap.stats().live
+    }
+
+    /// Returns `true` if the pool holds no sessions at all.
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Removes every checked in session that has been idle for at least `max_idle`, passing each
+    /// to `f`, and returns how many were evicted.
+    ///
+    /// `f` decides what becomes of a session: close it by dropping it, or e.g. reset it and put it
+    /// back with `insert`.
+    pub fn evict_idle<F>(&self, max_idle: Duration, mut f: F) -> usize
+        where F: FnMut(T)
+    {
+        let now = Instant::now();
+        // Sessions checked in by `f` are newer than `now`, and not to be looked at again.
+        let candidates = self.idle();
+        let mut evicted = 0;
+        while evicted < candidates {
+            // No borrow is held while calling out to `f`, which may use the pool.
+            let stale = {
+                let mut idle = self.0.idle.borrow_mut();
+                match idle.front() {
+                    Some(&(since, _)) if now.saturating_duration_since(since) >= max_idle => {
+                        idle.pop_front()
+                    }
+                    _ => None,
+                }
+            };
+            match stale {
+                Some((_, rp)) => f(Rp::take(rp)),
+                None => break,
+            }
+            evicted += 1;
+        }
+        evicted
+    }
+
+    #[inline]
+    fn wrap(&self, rp: Rp<T>) -> Session<T> {
+        Session {
+            rp: Some(rp),
+            pool: self.clone(),
+        }
+    }
+}
+
+impl<T> Clone for SessionPool<T> {
+    #[inline]
+    fn clone(&self) -> SessionPool<T> {
+        SessionPool(self.0.clone())
+    }
+}
+
+impl<T> Default for SessionPool<T> {
+    #[inline]
+    fn default() -> SessionPool<T> {
+        SessionPool::new()
+    }
+}
+
+impl<T> fmt::Debug for SessionPool<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("SessionPool")
+            .field("len", &self.len())
+            .field("idle", &self.idle())
+            .finish()
+    }
+}
+
+/// A session checked out of a `SessionPool`, checked back in when dropped.
+pub struct Session<T> {
+    // Only `None` once detached or dropped.
+    rp: Option<Rp<T>>,
+    pool: SessionPool<T>,
+}
+
+impl<T> Session<T> {
+    /// Takes the session out of the pool's management for good.
+    #[inline]
+    pub fn detach(mut this: Session<T>) -> T {
+        Rp::take(this.rp.take().unwrap())
+    }
+
+    /// Returns a reference to the pool this session will be checked back into.
+    #[inline]
+    pub fn pool(&self) -> &SessionPool<T> {
+        &self.pool
+    }
+}
+
+impl<T> Deref for Session<T> {
+    type Target = T;
+
+    #[inline]
+    fn deref(&self) -> &T {
+        self.rp.as_ref().unwrap()
+    }
+}
+
+impl<T> DerefMut for Session<T> {
+    #[inline]
+    fn deref_mut(&mut self) -> &mut T {
+        self.rp.as_mut().unwrap()
+    }
+}
+
+impl<T> fmt::Debug for Session<T>
+    where T: fmt::Debug
+{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Debug::fmt(&**self, f)
+    }
+}
+
+impl<T> Drop for Session<T> {
+    fn drop(&mut self) {
+        if let Some(rp) = self.rp.take() {
+            self.pool.0.idle.borrow_mut().push_back((Instant::now(), rp));
+        }
+    }
+}
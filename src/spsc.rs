@@ -0,0 +1,232 @@
+//! An arena for handing objects from one thread to another.
+//!
+//! In a pipeline, one thread allocates messages and sends them on, and another consumes and drops
+//! them. A `Reap` can't be shared between threads at all, and a lock around one would serialize
+//! both sides on every message. A `SpscReap` stays with the producing thread, which alone
+//! allocates from it, while its `SpscRp` handles may be sent to and dropped on any thread. Freed
+//! slots go back to the producer over a lock-free list, which it takes over wholesale once it has
+//! used up the slots it already holds.
+//!
+//! Like a `FixedReap`, a `SpscReap` allocates all of its storage up front, and allocating fails
+//! while every slot is in flight.
+//!
+//! # Examples
+//!
+//! ```
+//! use std::sync::mpsc;
+//! use std::thread;
+//!
+//! use reap::spsc::{SpscReap, SpscRp};
+//!
+//! let mut reap = SpscReap::with_capacity(16);
+//! let (tx, rx) = mpsc::channel::<SpscRp<u64>>();
+//! let consumer = thread::spawn(move || rx.iter().map(|msg| *msg).sum::<u64>());
+//!
+//! for i in 0..1000 {
+//!     let mut msg = reap.allocate(i);
+//!     // Wait for the consumer to free a slot.
+//!     while let Err(i) = msg {
+//!         thread::yield_now();
+//!         msg = reap.allocate(i);
+//!     }
+//!     tx.send(msg.unwrap()).unwrap();
+//! }
+//! drop(tx);
+//! assert_eq!(consumer.join().unwrap(), 499500);
+//! ```
+
+use std::fmt;
+use std::marker::PhantomData;
+use std::mem::{self, ManuallyDrop};
+use std::ops::{Deref, DerefMut};
+use std::ptr::{self, NonNull};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicPtr, Ordering};
+
+use super::{Chunk, Release};
+
+/// The producing side of a two-thread arena.
+///
+/// A `SpscReap` may be moved to another thread, and allocating takes `&mut self`, so that only one
+/// thread at a time allocates from it.
+pub struct SpscReap<T> {
+    shared: Arc<Shared<T>>,
+    // Free slots owned by the producer, threaded through the slots themselves.
+    free: *mut Node<T>,
+}
+
+// State shared by the producer and every handle.
+struct Shared<T> {
+    // All of the arena's storage, or `None` if its capacity is 0.
+    chunk: Option<Chunk>,
+    // Slots freed by handles, waiting to be taken back by the producer.
+    returned: AtomicPtr<Node<T>>,
+    _marker: PhantomData<T>,
+}
+
+// A slot holds either a live object, or a link to the next free slot.
+union Node<T> {
+    value: ManuallyDrop<T>,
+    next: *mut Node<T>,
+}
+
+// Slots are only ever accessed through the producer, which owns the free ones, or the handle
+// which owns the object in it.
+unsafe impl<T: Send> Send for Shared<T> {}
+unsafe impl<T: Send> Sync for Shared<T> {}
+
+unsafe impl<T: Send> Send for SpscReap<T> {}
+
+impl<T> SpscReap<T> {
+    /// Creates a new `SpscReap<T>` with room for exactly `capacity` objects in flight.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the required capacity overflows, and aborts if the allocation fails, like
+    /// `Vec::with_capacity`.
+    pub fn with_capacity(capacity: usize) -> SpscReap<T> {
+        let chunk = if capacity == 0 {
+            None
+        } else {
+            Some(Chunk::new::<Node<T>>(capacity))
+        };
+        let mut free = ptr::null_mut();
+        if let Some(ref chunk) = chunk {
+            for i in (0..capacity).rev() {
+                unsafe {
+                    let node = chunk.start::<Node<T>>().add(i);
+                    ptr::write(node, Node { next: free });
+                    free = node;
+                }
+            }
+        }
+        SpscReap {
+            shared: Arc::new(Shared {
+                chunk,
+                returned: AtomicPtr::new(ptr::null_mut()),
+                _marker: PhantomData,
+            }),
+            free,
+        }
+    }
+
+    /// Allocates `object`, or gives it back if every slot is in flight.
+    ///
+    /// Never blocks: while the producer has free slots of its own this touches no shared state,
+    /// and otherwise it takes back all of the slots freed since with a single atomic swap.
+    pub fn allocate(&mut self, object: T) -> Result<SpscRp<T>, T> {
+        if self.free.is_null() {
+            // Pairs with the release in `SpscRp::drop`, so the object's destructor has finished
+            // with the slot before it is reused.
+            self.free = self.shared.returned.swap(ptr::null_mut(), Ordering::Acquire);
+            if self.free.is_null() {
+                return Err(object);
+            }
+        }
+        unsafe {
+            let node = self.free;
+            self.free = (*node).next;
+            ptr::write(node, Node { value: ManuallyDrop::new(object) });
+            Ok(SpscRp {
+                node: NonNull::new_unchecked(node),
+                shared: self.shared.clone(),
+            })
+        }
+    }
+
+    /// Returns the number of objects the arena has room for.
+    #[inline]
+    pub fn capacity(&self) -> usize {
+        self.shared.chunk.as_ref().map_or(0, Chunk::capacity)
+    }
+}
+
+impl<T> fmt::Debug for SpscReap<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("SpscReap")
+            .field("capacity", &self.capacity())
+            .finish()
+    }
+}
+
+/// A handle to an object allocated in a `SpscReap`.
+///
+/// It may be sent to, and dropped on, any thread. Dropping it drops the object and returns its
+/// slot to the producer.
+pub struct SpscRp<T> {
+    node: NonNull<Node<T>>,
+    shared: Arc<Shared<T>>,
+}
+
+unsafe impl<T: Send> Send for SpscRp<T> {}
+unsafe impl<T: Sync> Sync for SpscRp<T> {}
+
+impl<T> SpscRp<T> {
+    /// Moves the object out of the arena, returning its slot to the producer.
+    #[inline]
+    pub fn into_inner(this: SpscRp<T>) -> T {
+        let node = this.node.as_ptr();
+        // Move the shared state out, and skip the destructor, which would drop the object.
+        let shared = unsafe { ptr::read(&this.shared) };
+        mem::forget(this);
+        unsafe {
+            let value = ManuallyDrop::into_inner(ptr::read(&(*node).value));
+            shared.push(node);
+            value
+        }
+    }
+}
+
+impl<T> Shared<T> {
+    // Hands `node`, whose object is gone, back to the producer.
+    //
+    // Only the producer ever removes nodes, and always the whole list at once, so the usual ABA
+    // problem of lock-free stacks can't arise.
+    #[inline]
+    unsafe fn push(&self, node: *mut Node<T>) {
+        let mut head = self.returned.load(Ordering::Relaxed);
+        loop {
+            ptr::write(node, Node { next: head });
+            match self.returned
+                .compare_exchange_weak(head, node, Ordering::Release, Ordering::Relaxed) {
+                Ok(_) => return,
+                Err(current) => head = current,
+            }
+        }
+    }
+}
+
+impl<T> Deref for SpscRp<T> {
+    type Target = T;
+
+    #[inline]
+    fn deref(&self) -> &T {
+        unsafe { &(*self.node.as_ptr()).value }
+    }
+}
+
+impl<T> DerefMut for SpscRp<T> {
+    #[inline]
+    fn deref_mut(&mut self) -> &mut T {
+        unsafe { &mut (*self.node.as_ptr()).value }
+    }
+}
+
+impl<T> fmt::Debug for SpscRp<T>
+    where T: fmt::Debug
+{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Debug::fmt(&**self, f)
+    }
+}
+
+impl<T> Drop for SpscRp<T> {
+    fn drop(&mut self) {
+        // Returned even if the object's destructor panics, but only once it has finished.
+        let (shared, node) = (&self.shared, self.node.as_ptr());
+        let _release = Release::new(move || unsafe { shared.push(node) });
+        unsafe {
+            ManuallyDrop::drop(&mut (*node).value);
+        }
+    }
+}
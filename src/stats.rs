@@ -0,0 +1,515 @@
+// Memory usage statistics and reporting for `Reap`.
+
+use std::fmt::{self, Write};
+use std::mem;
+
+use super::{Reap, Slot};
+
+/// A snapshot of a `Reap`'s memory usage, as returned by `Reap::stats`.
+///
+/// Slot counts are in units of objects. For zero-sized types, which take no memory, only `live`
+/// is meaningful and everything else is zero.
+///
+/// `freelist_hits` and `bump_allocations` count every slot handed out over the `Reap`'s lifetime,
+/// telling whether it reuses memory effectively or just keeps growing.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct ReapStats {
+    /// Number of live objects.
+    pub live: usize,
+    /// Number of chunks allocated.
+    pub chunks: usize,
+    /// Total number of slots across all chunks.
+    pub capacity: usize,
+    /// Number of freed slots waiting on the freelist to be reused.
+    pub free: usize,
+    /// Number of slots never handed out, at the end of the current chunk.
+    pub untouched: usize,
+    /// Size of a single slot in bytes.
+    pub slot_size: usize,
+    /// Number of allocations served from the freelist.
+    pub freelist_hits: usize,
+    /// Number of allocations served from fresh slots of a chunk, including objects adopted by
+    /// `adopt_vec`.
+    pub bump_allocations: usize,
+}
+
+impl ReapStats {
+    /// Returns the fraction of allocations served from the freelist, or 0 if there were none.
+    #[inline]
+    pub fn hit_rate(&self) -> f64 {
+        let total = self.freelist_hits + self.bump_allocations;
+        if total == 0 {
+            0.0
+        } else {
+            self.freelist_hits as f64 / total as f64
+        }
+    }
+
+    /// Returns the fraction of slots ever handed out that hold live objects, across all chunks.
+    ///
+    /// The rest sit on the freelist, so a low occupancy means the arena is fragmented: it holds
+    /// much more memory than its objects need. This is 1 while no slot was handed out.
+    #[inline]
+    pub fn occupancy(&self) -> f64 {
+        occupancy(self.live, self.capacity - self.untouched)
+    }
+
+    /// Returns the total number of bytes allocated for chunks.
+    #[inline]
+    pub fn reserved_bytes(&self) -> usize {
+        self.capacity * self.slot_size
+    }
+
+    /// Returns the number of bytes occupied by live objects.
+    #[inline]
+    pub fn live_bytes(&self) -> usize {
+        self.live * self.slot_size
+    }
+
+    /// Returns what changed from the snapshot `before` to the later snapshot `after` of the same
+    /// `Reap`.
+    ///
+    /// Taking a snapshot before an operation and diffing it with one taken after tells whether the
+    /// operation left objects behind in the arena, or made it grow. Tests can assert on it, and
+    /// long-running programs can log it between two checkpoints.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use reap::{Reap, ReapStats};
+    ///
+    /// let reap = Reap::new();
+    /// let kept = reap.allocate(0);
+    ///
+    /// let before = reap.stats();
+    /// let scratch: Vec<_> = (1..10).map(|i| reap.allocate(i)).collect();
+    /// drop(scratch);
+    /// let diff = ReapStats::diff(&before, &reap.stats());
+    ///
+    /// assert_eq!(diff.live, 0, "leaked {} object(s)", diff.live);
+    /// assert_eq!((diff.allocations(), diff.free), (9, 9));
+    /// # drop(kept);
+    /// ```
+    pub fn diff(before: &ReapStats, after: &ReapStats) -> StatsDiff {
+        StatsDiff {
+            live: delta(before.live, after.live),
+            chunks: delta(before.chunks, after.chunks),
+            capacity: delta(before.capacity, after.capacity),
+            free: delta(before.free, after.free),
+            untouched: delta(before.untouched, after.untouched),
+            slot_size: after.slot_size,
+            freelist_hits: delta(before.freelist_hits, after.freelist_hits),
+            bump_allocations: delta(before.bump_allocations, after.bump_allocations),
+        }
+    }
+}
+
+// Returns `after - before`, negative if the count went down.
+#[inline]
+fn delta(before: usize, after: usize) -> isize {
+    after.wrapping_sub(before) as isize
+}
+
+/// The change in a `Reap`'s memory usage between two snapshots, as returned by
+/// `ReapStats::diff`.
+///
+/// Every field is the change in the `ReapStats` field of the same name, later minus earlier.
+/// Chunks are never freed and the allocation counters only go up, so only `live`, `free` and
+/// `untouched` can be negative.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct StatsDiff {
+    /// Change in the number of live objects.
+    pub live: isize,
+    /// Number of chunks allocated in between.
+    pub chunks: isize,
+    /// Number of slots in the chunks allocated in between.
+    pub capacity: isize,
+    /// Change in the number of slots on the freelist.
+    pub free: isize,
+    /// Change in the number of slots never handed out.
+    pub untouched: isize,
+    /// Size of a single slot in bytes.
+    pub slot_size: usize,
+    /// Number of allocations served from the freelist in between.
+    pub freelist_hits: isize,
+    /// Number of allocations served from fresh slots in between.
+    pub bump_allocations: isize,
+}
+
+impl StatsDiff {
+    /// Returns the number of allocations made in between.
+    #[inline]
+    pub fn allocations(&self) -> isize {
+        self.freelist_hits + self.bump_allocations
+    }
+
+    /// Returns the change in the number of bytes occupied by live objects.
+    #[inline]
+    pub fn live_bytes(&self) -> isize {
+        self.live * self.slot_size as isize
+    }
+
+    /// Returns the number of bytes of the chunks allocated in between.
+    #[inline]
+    pub fn reserved_bytes(&self) -> isize {
+        self.capacity * self.slot_size as isize
+    }
+}
+
+impl fmt::Display for StatsDiff {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f,
+               "{:+} live ({:+} bytes), {:+} chunk(s) ({:+} bytes reserved), {} allocation(s) \
+                ({} from the freelist)",
+               self.live,
+               self.live_bytes(),
+               self.chunks,
+               self.reserved_bytes(),
+               self.allocations(),
+               self.freelist_hits)
+    }
+}
+
+/// The usage of one of a `Reap`'s chunks, as returned by `Reap::chunk_stats`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct ChunkStats {
+    /// Number of slots in the chunk.
+    pub capacity: usize,
+    /// Number of slots holding live objects.
+    pub live: usize,
+    /// Number of slots on the freelist.
+    pub free: usize,
+    /// Number of slots never handed out, only ever nonzero for the last chunk.
+    pub untouched: usize,
+}
+
+impl ChunkStats {
+    /// Returns the fraction of the chunk's slots ever handed out that hold live objects, or 1 if
+    /// none were.
+    #[inline]
+    pub fn occupancy(&self) -> f64 {
+        occupancy(self.live, self.capacity - self.untouched)
+    }
+}
+
+// Returns `live / touched`, counting an untouched chunk as fully occupied as none of it is wasted.
+#[inline]
+fn occupancy(live: usize, touched: usize) -> f64 {
+    if touched == 0 {
+        1.0
+    } else {
+        live as f64 / touched as f64
+    }
+}
+
+/// The high-water marks of a `Reap`'s memory usage, as returned by `Reap::peaks`.
+///
+/// These are the largest values the corresponding `ReapStats` fields reached since the `Reap` was
+/// created, or since the last `Reap::reset_peaks`, so capacity planning doesn't need to sample
+/// `stats` at exactly the right moment. Chunks are never freed while the `Reap` lives, so the
+/// peak of the memory reserved is simply `ReapStats::reserved_bytes`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct ReapPeaks {
+    /// Most objects live at once.
+    pub live: usize,
+    /// Most freed slots waiting on the freelist at once.
+    pub free: usize,
+    /// Size of a single slot in bytes.
+    pub slot_size: usize,
+}
+
+impl ReapPeaks {
+    /// Returns the most bytes occupied by live objects at once.
+    #[inline]
+    pub fn live_bytes(&self) -> usize {
+        self.live * self.slot_size
+    }
+}
+
+impl<T> Reap<T> {
+    /// Returns the high-water marks of this `Reap`'s memory usage.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use reap::Reap;
+    ///
+    /// let reap = Reap::new();
+    /// let burst: Vec<_> = (0..100).map(|i| reap.allocate(i)).collect();
+    /// drop(burst);
+    /// let _steady = reap.allocate(0);
+    ///
+    /// assert_eq!(reap.stats().live, 1);
+    /// assert_eq!((reap.peaks().live, reap.peaks().free), (100, 100));
+    /// ```
+    #[inline]
+    pub fn peaks(&self) -> ReapPeaks {
+        ReapPeaks {
+            live: self.0.peak_live.get(),
+            free: self.0.peak_free.get(),
+            slot_size: if mem::size_of::<T>() == 0 { 0 } else { mem::size_of::<Slot<T>>() },
+        }
+    }
+
+    /// Resets the high-water marks to the current usage, e.g. to measure one phase of a program
+    /// on its own.
+    #[inline]
+    pub fn reset_peaks(&self) {
+        self.0.peak_live.set(self.0.live.get());
+        self.0.peak_free.set(self.0.freelist.borrow().len());
+    }
+
+    /// Returns a snapshot of this `Reap`'s memory usage.
+    pub fn stats(&self) -> ReapStats {
+        let live = self.0.live.get();
+        if mem::size_of::<T>() == 0 {
+            return ReapStats {
+                live,
+                ..ReapStats::default()
+            };
+        }
+        let chunks = self.0.chunks.borrow();
+        ReapStats {
+            live,
+            chunks: chunks.len(),
+            capacity: chunks.iter().map(|chunk| chunk.capacity()).sum(),
+            free: self.0.freelist.borrow().len(),
+            untouched: self.untouched(),
+            slot_size: mem::size_of::<Slot<T>>(),
+            freelist_hits: self.0.reused.get(),
+            bump_allocations: self.0.bumped.get(),
+        }
+    }
+
+    /// Returns the usage of every chunk, oldest first, e.g. to see where the arena is fragmented.
+    ///
+    /// This walks the freelist, so it is not meant for hot paths. Zero-sized types have no chunks.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use reap::Reap;
+    ///
+    /// let reap = Reap::with_capacity(4);
+    /// let mut objects: Vec<_> = (0..4).map(|i| reap.allocate(i)).collect();
+    /// objects.truncate(1);
+    ///
+    /// let chunks = reap.chunk_stats();
+    /// assert_eq!((chunks[0].live, chunks[0].free), (1, 3));
+    /// assert_eq!(chunks[0].occupancy(), 0.25);
+    /// ```
+    pub fn chunk_stats(&self) -> Vec<ChunkStats> {
+        if mem::size_of::<T>() == 0 {
+            return Vec::new();
+        }
+        let untouched = self.untouched();
+        let chunks = self.0.chunks.borrow();
+        let freelist = self.0.freelist.borrow();
+        chunks.iter()
+            .enumerate()
+            .map(|(i, chunk)| {
+                let untouched = if i + 1 == chunks.len() { untouched } else { 0 };
+                let start = chunk.start::<Slot<T>>() as usize;
+                let end = chunk.end::<Slot<T>>() as usize;
+                let free = freelist.iter()
+                    .filter(|&&ptr| start <= ptr as usize && (ptr as usize) < end)
+                    .count();
+                ChunkStats {
+                    capacity: chunk.capacity(),
+                    live: chunk.capacity() - untouched - free,
+                    free,
+                    untouched,
+                }
+            })
+            .collect()
+    }
+
+    /// Returns a human-readable summary of this `Reap`'s memory usage, for logging.
+    ///
+    /// Besides the totals from `stats`, this lists every chunk with its size and how many of its
+    /// slots hold live objects. This walks the freelist, so it is not meant for hot paths.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use reap::Reap;
+    ///
+    /// let reap = Reap::with_capacity(16);
+    /// let objects: Vec<_> = (0..20u64).map(|i| reap.allocate(i)).collect();
+    /// drop(objects);
+    ///
+    /// println!("{}", reap.report());
+    /// ```
+    pub fn report(&self) -> String {
+        let stats = self.stats();
+        let mut out = String::new();
+        // Writing to a `String` can't fail.
+        let _ = writeln!(out, "Reap<{}>", ::std::any::type_name::<T>());
+        let _ = writeln!(out, "  live objects: {} ({} bytes)", stats.live, stats.live_bytes());
+        if mem::size_of::<T>() == 0 {
+            let _ = writeln!(out, "  zero-sized type, no memory in use");
+            return out;
+        }
+        let _ = writeln!(out,
+                         "  chunks: {} ({} slots of {} bytes, {} bytes)",
+                         stats.chunks,
+                         stats.capacity,
+                         stats.slot_size,
+                         stats.reserved_bytes());
+
+        for (i, chunk) in self.chunk_stats().iter().enumerate() {
+            let _ = writeln!(out,
+                             "    #{}: {} slots, {} in use ({:.1}%), {} free, {} untouched",
+                             i,
+                             chunk.capacity,
+                             chunk.live,
+                             100.0 * chunk.live as f64 / chunk.capacity as f64,
+                             chunk.free,
+                             chunk.untouched);
+        }
+        let _ = writeln!(out,
+                         "  freelist: {} slots ({} bytes)",
+                         stats.free,
+                         stats.free * stats.slot_size);
+        let _ = writeln!(out,
+                         "  untouched by growth: {} slots ({} bytes)",
+                         stats.untouched,
+                         stats.untouched * stats.slot_size);
+        let _ = writeln!(out,
+                         "  allocations: {} from the freelist ({:.1}%), {} fresh; occupancy {:.1}%",
+                         stats.freelist_hits,
+                         100.0 * stats.hit_rate(),
+                         stats.bump_allocations,
+                         100.0 * stats.occupancy());
+        out
+    }
+
+    /// Returns a JSON description of this `Reap`'s layout, for tools that render its
+    /// fragmentation.
+    ///
+    /// The document has the type name, `slot_size` and `live` count, then every chunk, oldest
+    /// first, with its address, capacity and the state of each of its slots as a string: `L` for a
+    /// live object, `F` for a slot on the freelist, `U` for one never handed out. Last comes the
+    /// freelist as `[chunk, slot]` pairs, in the order the slots will be reused. Zero-sized types
+    /// have no chunks and an empty freelist. With the `tags` feature, every chunk also lists the
+    /// live objects given a tag by `allocate_named`, as `[slot, tag]` pairs.
+    ///
+    /// Like `report`, this walks the whole arena and is not meant for hot paths. Dumping it
+    /// periodically shows how the layout evolves.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use reap::Reap;
+    ///
+    /// let reap = Reap::with_capacity(4);
+    /// let mut objects: Vec<_> = (0..3u8).map(|i| reap.allocate(i)).collect();
+    /// objects.remove(1);
+    ///
+    /// let layout = reap.dump_layout();
+    /// assert!(layout.contains(r#""slots":"LFLU""#));
+    /// assert!(layout.ends_with(r#""freelist":[[0,1]]}"#));
+    /// ```
+    pub fn dump_layout(&self) -> String {
+        let mut out = String::from("{\"type\":");
+        write_json_str(&mut out, ::std::any::type_name::<T>());
+        let slot_size = if mem::size_of::<T>() == 0 { 0 } else { mem::size_of::<Slot<T>>() };
+        let _ = write!(out,
+                       ",\"slot_size\":{},\"live\":{},\"chunks\":[",
+                       slot_size,
+                       self.0.live.get());
+        if mem::size_of::<T>() == 0 {
+            out.push_str("],\"freelist\":[]}");
+            return out;
+        }
+
+        let untouched = self.untouched();
+        let chunks = self.0.chunks.borrow();
+        let freelist = self.0.freelist.borrow();
+        // Every chunk's slots start out live, except the untouched end of the current one.
+        let mut slots: Vec<Vec<u8>> = chunks.iter()
+            .map(|chunk| vec![b'L'; chunk.capacity()])
+            .collect();
+        if let Some(last) = slots.last_mut() {
+            let len = last.len();
+            for state in &mut last[len - untouched..] {
+                *state = b'U';
+            }
+        }
+        // `(chunk, slot)` of every free pointer, from the top of the stack down.
+        let free: Vec<(usize, usize)> = freelist.iter()
+            .rev()
+            .map(|&ptr| {
+                self.position(ptr as usize).expect("reap: free pointer outside of every chunk")
+            })
+            .collect();
+        for &(chunk, slot) in &free {
+            slots[chunk][slot] = b'F';
+        }
+
+        for (i, (chunk, states)) in chunks.iter().zip(&slots).enumerate() {
+            if i != 0 {
+                out.push(',');
+            }
+            let _ = write!(out,
+                           "{{\"address\":\"{:p}\",\"capacity\":{},\"slots\":\"",
+                           chunk.start::<u8>(),
+                           chunk.capacity());
+            // Only ever ASCII letters.
+            out.extend(states.iter().map(|&state| state as char));
+            out.push('"');
+            #[cfg(feature = "tags")]
+            {
+                out.push_str(",\"tags\":[");
+                let mut first = true;
+                for (slot, _) in states.iter().enumerate().filter(|&(_, &state)| state == b'L') {
+                    if let Some(tag) = unsafe { Slot::tag(chunk.start::<Slot<T>>().add(slot)) } {
+                        if !first {
+                            out.push(',');
+                        }
+                        first = false;
+                        let _ = write!(out, "[{},", slot);
+                        write_json_str(&mut out, tag);
+                        out.push(']');
+                    }
+                }
+                out.push(']');
+            }
+            out.push('}');
+        }
+        out.push_str("],\"freelist\":[");
+        for (i, &(chunk, slot)) in free.iter().enumerate() {
+            if i != 0 {
+                out.push(',');
+            }
+            let _ = write!(out, "[{},{}]", chunk, slot);
+        }
+        out.push_str("]}");
+        out
+    }
+
+    // Returns the number of slots at the end of the current chunk that were never handed out.
+    fn untouched(&self) -> usize {
+        if self.0.chunks.borrow().is_empty() {
+            0
+        } else {
+            (self.0.end.get() as usize - self.0.ptr.get() as usize) / mem::size_of::<Slot<T>>()
+        }
+    }
+}
+
+// Appends `s` to `out` as a JSON string literal.
+fn write_json_str(out: &mut String, s: &str) {
+    out.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            c if (c as u32) < 0x20 => {
+                let _ = write!(out, "\\u{:04x}", c as u32);
+            }
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+}
@@ -0,0 +1,158 @@
+// Handles with a few bits of data in their pointer, for `Rp::with_tag`.
+
+use std::fmt;
+use std::mem::{self, ManuallyDrop};
+use std::ops::{Deref, DerefMut};
+use std::ptr::NonNull;
+
+use super::{Reap, Rp};
+
+/// An owning handle carrying a small tag in the unused low bits of its pointer.
+///
+/// Created with `Rp::with_tag`. Objects are aligned, so the low bits of their addresses are
+/// always zero, and the `TaggedRp::tag_bits()` of them below the alignment of `T` can hold a tag
+/// instead: two bits for a `u32`, three for a `u64`. Data structures get the classic tagged
+/// pointer trick this way, marking nodes or storing the color of a red-black tree node, with no
+/// extra field per node. The tag is masked off on every access to the object, a single `and`.
+///
+/// # Examples
+///
+/// ```
+/// use reap::{Reap, Rp, TaggedRp};
+///
+/// const RED: usize = 1;
+///
+/// let reap = Reap::new();
+/// let mut node = Rp::with_tag(reap.allocate(42u64), RED);
+/// assert_eq!((*node, TaggedRp::tag(&node)), (42, RED));
+///
+/// *node += 1;
+/// TaggedRp::set_tag(&mut node, 0);
+/// assert_eq!(*TaggedRp::into_rp(node), 43);
+/// ```
+pub struct TaggedRp<T> {
+    // The address of the object, with the tag in its low bits.
+    ptr: NonNull<T>,
+    // Only taken out when the handle is consumed.
+    reap: ManuallyDrop<Reap<T>>,
+}
+
+impl<T> Rp<T> {
+    /// Converts this handle into a `TaggedRp` carrying `tag`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `tag` doesn't fit in `TaggedRp::<T>::tag_bits()` bits.
+    #[inline]
+    pub fn with_tag(this: Rp<T>, tag: usize) -> TaggedRp<T> {
+        TaggedRp::<T>::check(tag);
+        let (ptr, reap) = Rp::into_parts(this);
+        TaggedRp {
+            // Still within the object, at an offset smaller than its alignment.
+            ptr: unsafe { NonNull::new_unchecked(ptr.map_addr(|addr| addr | tag)) },
+            reap: ManuallyDrop::new(reap),
+        }
+    }
+}
+
+impl<T> TaggedRp<T> {
+    /// Returns the number of low bits available for tags in a handle to a `T`, the base 2
+    /// logarithm of its alignment.
+    #[inline]
+    pub fn tag_bits() -> u32 {
+        mem::align_of::<T>().trailing_zeros()
+    }
+
+    // Returns the mask of the tag bits.
+    #[inline]
+    fn mask() -> usize {
+        mem::align_of::<T>() - 1
+    }
+
+    // Panics unless `tag` fits in the tag bits.
+    #[inline]
+    fn check(tag: usize) {
+        assert!(tag & !TaggedRp::<T>::mask() == 0,
+                "reap: tag {:#x} doesn't fit in the {} tag bits of a pointer to {}",
+                tag,
+                TaggedRp::<T>::tag_bits(),
+                ::std::any::type_name::<T>());
+    }
+
+    /// Returns the tag of this handle.
+    #[inline]
+    pub fn tag(this: &TaggedRp<T>) -> usize {
+        this.ptr.as_ptr() as usize & TaggedRp::<T>::mask()
+    }
+
+    /// Replaces the tag of this handle with `tag`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `tag` doesn't fit in `TaggedRp::<T>::tag_bits()` bits.
+    #[inline]
+    pub fn set_tag(this: &mut TaggedRp<T>, tag: usize) {
+        TaggedRp::<T>::check(tag);
+        let ptr = this.untagged().map_addr(|addr| addr | tag);
+        this.ptr = unsafe { NonNull::new_unchecked(ptr) };
+    }
+
+    /// Returns a reference to this handle's associated `Reap<T>`.
+    #[inline]
+    pub fn reap(this: &TaggedRp<T>) -> &Reap<T> {
+        &this.reap
+    }
+
+    /// Converts this handle back into a plain `Rp<T>`, dropping the tag.
+    #[inline]
+    pub fn into_rp(mut this: TaggedRp<T>) -> Rp<T> {
+        let ptr = this.untagged();
+        unsafe {
+            let reap = ManuallyDrop::take(&mut this.reap);
+            mem::forget(this);
+            Rp::from_parts(ptr, reap)
+        }
+    }
+
+    // Returns the address of the object, without the tag.
+    #[inline]
+    fn untagged(&self) -> *mut T {
+        self.ptr.as_ptr().map_addr(|addr| addr & !TaggedRp::<T>::mask())
+    }
+}
+
+impl<T> Drop for TaggedRp<T> {
+    fn drop(&mut self) {
+        let ptr = self.untagged();
+        unsafe {
+            drop(Rp::from_parts(ptr, ManuallyDrop::take(&mut self.reap)));
+        }
+    }
+}
+
+impl<T> Deref for TaggedRp<T> {
+    type Target = T;
+
+    #[inline]
+    fn deref(&self) -> &T {
+        unsafe { &*self.untagged() }
+    }
+}
+
+impl<T> DerefMut for TaggedRp<T> {
+    #[inline]
+    fn deref_mut(&mut self) -> &mut T {
+        unsafe { &mut *self.untagged() }
+    }
+}
+
+impl<T> fmt::Debug for TaggedRp<T>
+    where T: fmt::Debug
+{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("TaggedRp")
+            .field("value", &**self)
+            .field("tag", &TaggedRp::tag(self))
+            .finish()
+    }
+}
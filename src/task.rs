@@ -0,0 +1,284 @@
+//! Arena-allocated local tasks and a minimal single-threaded executor.
+//!
+//! Every task's future lives pinned in a `Reap` slot, so spawning thousands of small cooperative
+//! tasks costs no boxing of futures, and finished tasks' slots are recycled by the next spawn.
+//! Futures don't need to be `Send`.
+//!
+//! Since a `Reap` holds a single type, an executor runs futures of a single type `F`, typically
+//! the anonymous future of one `async fn` or `async` block.
+//!
+//! # Examples
+//!
+//! ```
+//! use std::cell::Cell;
+//! use std::future::Future;
+//! use std::pin::Pin;
+//! use std::rc::Rc;
+//! use std::task::{Context, Poll};
+//!
+//! use reap::task::LocalExecutor;
+//!
+//! // A future that yields once before completing.
+//! struct YieldOnce(bool, Rc<Cell<u32>>);
+//!
+//! impl Future for YieldOnce {
+//!     type Output = ();
+//!
+//!     fn poll(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<()> {
+//!         if self.0 {
+//!             self.1.set(self.1.get() + 1);
+//!             Poll::Ready(())
+//!         } else {
+//!             self.0 = true;
+//!             cx.waker().wake_by_ref();
+//!             Poll::Pending
+//!         }
+//!     }
+//! }
+//!
+//! let done = Rc::new(Cell::new(0));
+//! let executor = LocalExecutor::new();
+//! let handles: Vec<_> = (0..100).map(|_| executor.spawn(YieldOnce(false, done.clone()))).collect();
+//!
+//! executor.run_until_stalled();
+//! assert_eq!(done.get(), 100);
+//! assert!(handles.iter().all(|h| executor.is_finished(h)));
+//! ```
+
+use std::cell::RefCell;
+use std::future::Future;
+use std::mem;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Wake, Waker};
+use std::vec;
+
+use super::generation::Generation;
+use super::{Reap, Rp};
+
+// Queue of ids of woken tasks, shared with every task's waker.
+type WokenQueue = Arc<Mutex<Vec<usize>>>;
+
+// Waker for a single task: pushes the task's id onto the executor's queue.
+struct TaskWaker {
+    id: usize,
+    woken: WokenQueue,
+}
+
+impl Wake for TaskWaker {
+    fn wake(self: Arc<TaskWaker>) {
+        self.wake_by_ref();
+    }
+
+    fn wake_by_ref(self: &Arc<TaskWaker>) {
+        self.woken.lock().unwrap().push(self.id);
+    }
+}
+
+// A task slot in the executor.
+struct Slot<F> {
+    // Moves on whenever a task leaves the slot, so that the handle of a finished task doesn't
+    // report on the task spawned into the slot after it.
+    generation: Generation,
+    // `None` while vacant, or while the task is being polled.
+    future: Option<Pin<Rp<F>>>,
+    // `true` from spawn until completion or cancellation.
+    live: bool,
+    waker: Waker,
+}
+
+/// Identifies a task spawned on a `LocalExecutor`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct TaskHandle {
+    id: usize,
+    generation: Generation,
+}
+
+/// A single-threaded executor for arena-allocated futures of type `F`.
+pub struct LocalExecutor<F>
+    where F: Future<Output = ()>
+{
+    reap: Reap<F>,
+    slots: RefCell<Vec<Slot<F>>>,
+    // Ids of vacant slots.
+    vacant: RefCell<Vec<usize>>,
+    woken: WokenQueue,
+}
+
+impl<F> LocalExecutor<F>
+    where F: Future<Output = ()>
+{
+    /// Creates a new executor with no tasks.
+    #[inline]
+    pub fn new() -> LocalExecutor<F> {
+        LocalExecutor {
+            reap: Reap::new(),
+            slots: RefCell::new(Vec::new()),
+            vacant: RefCell::new(Vec::new()),
+            woken: Arc::new(Mutex::new(Vec::new())),
+        }
+    }
+
+    /// Spawns a future onto the executor, returning a handle to the new task.
+    ///
+    /// The task is first polled by the next call to `run_until_stalled`. Tasks may spawn further
+    /// tasks while being polled.
+    pub fn spawn(&self, future: F) -> TaskHandle {
+        let future = self.reap.allocate_pinned(future);
+        let mut slots = self.slots.borrow_mut();
+        let id = match self.vacant.borrow_mut().pop() {
+            Some(id) => id,
+            None => {
+                let id = slots.len();
+                slots.push(Slot {
+                    generation: Generation::new(),
+                    future: None,
+                    live: false,
+                    waker: Waker::from(Arc::new(TaskWaker {
+                        id,
+                        woken: self.woken.clone(),
+                    })),
+                });
+                id
+            }
+        };
+        let slot = &mut slots[id];
+        slot.future = Some(future);
+        slot.live = true;
+        slot.waker.wake_by_ref();
+        TaskHandle {
+            id,
+            generation: slot.generation,
+        }
+    }
+
+    /// Returns `true` if the given task has run to completion or has been cancelled.
+    ///
+    /// Handles from another executor, which may not have as many slots, count as finished.
+    pub fn is_finished(&self, task: &TaskHandle) -> bool {
+        match self.slots.borrow().get(task.id) {
+            Some(slot) => slot.generation != task.generation || !slot.live,
+            None => true,
+        }
+    }
+
+    /// Cancels the given task, dropping its future, and returns `true` if it was still running.
+    ///
+    /// Cancelling a task from inside its own `poll` takes effect once that poll returns.
+    pub fn cancel(&self, task: &TaskHandle) -> bool {
+        if self.is_finished(task) {
+            return false;
+        }
+        let future = {
+            let mut slots = self.slots.borrow_mut();
+            let slot = &mut slots[task.id];
+            slot.live = false;
+            slot.future.take()
+        };
+        // A task that is currently being polled has no future in its slot, the poll loop vacates
+        // the slot once it sees it is no longer live.
+        if future.is_some() {
+            self.vacate(task.id);
+        }
+        // Dropped outside of any borrow, the future's destructor may use the executor.
+        drop(future);
+        true
+    }
+
+    /// Returns the number of tasks that have not yet finished.
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.slots.borrow().iter().filter(|slot| slot.live).count()
+    }
+
+    /// Returns `true` if there are no unfinished tasks.
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Polls woken tasks until none are left to make progress, returning the number of polls
+    /// made.
+    ///
+    /// Tasks that complete are dropped and their slots recycled. If a task panics, it is dropped
+    /// as well, and the panic propagates once its slot is recycled. The other woken tasks are
+    /// polled by the next call.
+    pub fn run_until_stalled(&self) -> usize {
+        let mut polls = 0;
+        loop {
+            let mut woken = mem::take(&mut *self.woken.lock().unwrap()).into_iter();
+            if woken.len() == 0 {
+                return polls;
+            }
+            while let Some(id) = woken.next() {
+                let (future, waker) = {
+                    let mut slots = self.slots.borrow_mut();
+                    let slot = &mut slots[id];
+                    // Spurious or duplicate wakeups find the slot empty.
+                    match slot.future.take() {
+                        Some(future) => (future, slot.waker.clone()),
+                        None => continue,
+                    }
+                };
+                let mut future = future;
+                polls += 1;
+                let unwinding = Unwinding {
+                    executor: self,
+                    id,
+                    rest: &mut woken,
+                };
+                let poll = future.as_mut().poll(&mut Context::from_waker(&waker));
+                mem::forget(unwinding);
+
+                let live = self.slots.borrow()[id].live;
+                if poll.is_pending() && live {
+                    self.slots.borrow_mut()[id].future = Some(future);
+                } else {
+                    self.slots.borrow_mut()[id].live = false;
+                    self.vacate(id);
+                    drop(future);
+                }
+            }
+        }
+    }
+
+    // Marks the slot `id` as free for reuse by `spawn`.
+    fn vacate(&self, id: usize) {
+        self.slots.borrow_mut()[id].generation.bump();
+        self.vacant.borrow_mut().push(id);
+    }
+}
+
+// Cleans up after a task whose poll panics, before its future is dropped during unwinding.
+struct Unwinding<'a, F>
+    where F: Future<Output = ()> + 'a
+{
+    executor: &'a LocalExecutor<F>,
+    id: usize,
+    // The ids left to poll in this round.
+    rest: &'a mut vec::IntoIter<usize>,
+}
+
+impl<'a, F> Drop for Unwinding<'a, F>
+    where F: Future<Output = ()>
+{
+    fn drop(&mut self) {
+        // The task can never finish, so it doesn't count as live, and its slot is recycled.
+        self.executor.slots.borrow_mut()[self.id].live = false;
+        self.executor.vacate(self.id);
+        // Ahead of those woken during the poll, to keep the order they were woken in.
+        let mut queue = self.executor.woken.lock().unwrap();
+        let newer = mem::take(&mut *queue);
+        queue.extend(self.rest.by_ref());
+        queue.extend(newer);
+    }
+}
+
+impl<F> Default for LocalExecutor<F>
+    where F: Future<Output = ()>
+{
+    #[inline]
+    fn default() -> LocalExecutor<F> {
+        LocalExecutor::new()
+    }
+}
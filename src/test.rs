@@ -2,14 +2,41 @@ extern crate typed_arena;
 extern crate test;
 extern crate rand;
 
-use std::cell::Cell;
+use std::cell::{Cell, RefCell};
+use std::future::{self, Future};
+use std::marker::PhantomPinned;
+use std::iter;
 use std::mem;
+use std::panic::{self, AssertUnwindSafe};
+use std::pin::Pin;
+use std::rc::Rc;
+use std::task::{Context, Poll, Waker};
 
 use self::typed_arena::Arena;
 use self::test::Bencher;
-use self::rand::Rand;
 
-use super::{Reap, Rp};
+use super::{AllocError, AllocateIn, AllocatedInExt, ByAddr, ChunkStats, Frozen, Growth, Reap,
+            ReapCollect, ReapPeaks, ReapStats, Rp, RpLazy, StatsDiff, TaggedRp, WeakReap};
+use super::array::{ArrayRp, ReapArray};
+use super::brand::BrandCell;
+use super::budget::{Budget, Budgeted};
+use super::bintree::BinaryTree;
+use super::buffer::{Buffer, BufferReap};
+use super::cache::TtlCache;
+use super::chunk_cache;
+use super::dense::DensePool;
+use super::dlist::DList;
+use super::erased::ErasedReap;
+use super::fixed::{FixedReap, FixedRp};
+use super::graph::Graph;
+use super::frame::{FrameReap, FrameRp};
+use super::intrusive::{Link, LinkedList, SearchTree, TreeLink};
+use super::map::ReapMap;
+use super::owner::{Owner, OwnerCell};
+use super::session::{Session, SessionPool};
+use super::spsc::{SpscReap, SpscRp};
+use super::task::LocalExecutor;
+use super::tree::Tree;
 
 
 // Simple convenience function for the number of chunks in the given `Reap`.
@@ -26,6 +53,7 @@ fn reap_as_intended() {
         }
     }
 
+    #[allow(dead_code)]
     struct Node<'a>(Option<Rp<Node<'a>>>, usize, DropTracker<'a>);
 
     let drop_counter = Cell::new(0);
@@ -171,6 +199,2504 @@ fn test_zero_sized_type() {
     assert_eq!(n_chunks(&reap), 0);
 }
 
+#[cfg(feature = "poison")]
+#[test]
+fn test_poison_freed_slots() {
+    use super::POISON;
+
+    let bytes = Reap::new();
+    let strings = Reap::new();
+    let a = bytes.allocate([0x11u8; 16]);
+    let b = strings.allocate(String::from("stale"));
+    let a_ptr = &*a as *const [u8; 16] as *const u8;
+    let b_ptr = &*b as *const String as *const u8;
+
+    mem::drop(a);
+    mem::drop(b);
+    // The chunks are still alive, so reading the freed slots is fine, if ill-advised.
+    unsafe {
+        assert!((0..16).all(|i| *a_ptr.add(i) == POISON));
+        assert!((0..mem::size_of::<String>()).all(|i| *b_ptr.add(i) == POISON));
+    }
+}
+
+#[cfg(feature = "canary")]
+#[test]
+fn test_canaries_intact() {
+    let reap = Reap::with_capacity(4);
+    let objects: Vec<_> = (0..10u64).map(|i| reap.allocate([i; 3])).collect();
+    reap.check_canaries();
+    mem::drop(objects);
+    reap.check_canaries();
+}
+
+#[cfg(feature = "canary")]
+#[test]
+#[should_panic(expected = "were overwritten")]
+fn test_canary_overrun() {
+    let reap = Reap::new();
+    let a = reap.allocate([0u8; 8]);
+    let _b = reap.allocate([0u8; 8]);
+    unsafe {
+        // Write one byte past the end of `a`.
+        let ptr = &*a as *const [u8; 8] as *mut u8;
+        *ptr.add(8) = 0xFF;
+    }
+    reap.check_canaries();
+}
+
+#[cfg(feature = "canary")]
+#[test]
+#[should_panic(expected = "were overwritten")]
+fn test_canary_checked_on_drop() {
+    let reap = Reap::new();
+    let a = reap.allocate(0u64);
+    unsafe {
+        let ptr = &*a as *const u64 as *mut u64;
+        *ptr.offset(-1) = 0;
+    }
+    mem::drop(a);
+}
+
+#[cfg(feature = "backtrace")]
+#[test]
+fn test_dump_live() {
+    let reap = Reap::new();
+    let a = reap.allocate(1);
+    let b = reap.allocate(2);
+    let c = Rp::take(reap.allocate(3));
+    assert_eq!(c, 3);
+    assert!(reap.dump_live().starts_with("2 live object(s)"));
+
+    mem::drop(a);
+    let dump = reap.dump_live();
+    assert!(dump.starts_with("1 live object(s)"));
+    assert!(dump.contains(&format!("{:p} allocated at {}", &*b, file!())));
+
+    mem::drop(b);
+    assert_eq!(reap.dump_live(), "0 live object(s)\n");
+}
+
+#[test]
+fn test_raw_round_trip() {
+    struct Zst;
+
+    let reap = Reap::new();
+    let (ptr, r) = Rp::into_raw(reap.allocate(7));
+    let x = unsafe { Rp::from_raw(ptr, r) };
+    assert_eq!(*x, 7);
+
+    // ZSTs share an address, each of them can still be converted back once.
+    let zsts = Reap::new();
+    let raw: Vec<_> = (0..3).map(|_| Rp::into_raw(zsts.allocate(Zst))).collect();
+    for (ptr, r) in raw {
+        unsafe { Rp::from_raw(ptr, r) };
+    }
+}
+
+#[cfg(debug_assertions)]
+#[test]
+#[should_panic(expected = "was not given out by Rp::into_raw")]
+fn test_from_raw_twice() {
+    let reap = Reap::new();
+    let (ptr, r) = Rp::into_raw(reap.allocate(7));
+    unsafe {
+        let _x = Rp::from_raw(ptr, r.clone());
+        let _y = Rp::from_raw(ptr, r);
+    }
+}
+
+#[cfg(debug_assertions)]
+#[test]
+fn test_from_raw_reused() {
+    let reap = Reap::new();
+    let (stale, r) = Rp::into_raw(reap.allocate(7));
+    drop(unsafe { Rp::from_raw(stale, r) });
+    let (raw, r) = Rp::into_raw(reap.allocate(8));
+    assert_eq!(raw.as_ptr(), stale.as_ptr());
+
+    // Caught by its generation, though the slot was given out by `into_raw` again.
+    let result = panic::catch_unwind(AssertUnwindSafe(|| unsafe {
+        drop(Rp::from_raw(stale, r.clone()));
+    }));
+    let message = result.unwrap_err().downcast::<String>().unwrap();
+    assert!(message.contains("is stale"));
+    // The new pointer still goes back.
+    assert_eq!(*unsafe { Rp::from_raw(raw, r) }, 8);
+}
+
+#[cfg(debug_assertions)]
+#[test]
+#[should_panic(expected = "was not given out by Rp::into_raw")]
+fn test_from_raw_wrong_reap() {
+    let reap = Reap::new();
+    let (ptr, _) = Rp::into_raw(reap.allocate(7));
+    unsafe {
+        Rp::from_raw(ptr, Reap::new());
+    }
+}
+
+#[test]
+fn test_stats_diff() {
+    let reap = Reap::with_capacity(4);
+    let slot_size = reap.stats().slot_size;
+    let mut objects: Vec<_> = (0..3u64).map(|i| reap.allocate(i)).collect();
+    let before = reap.stats();
+    assert_eq!(ReapStats::diff(&before, &before), StatsDiff { slot_size, ..StatsDiff::default() });
+
+    // 2 slots reused from the freelist, 1 bumped, and a new chunk of 8 for the other 4.
+    objects.truncate(1);
+    objects.extend((0..7).map(|i| reap.allocate(i)));
+    let diff = ReapStats::diff(&before, &reap.stats());
+    assert_eq!(diff,
+               StatsDiff {
+                   live: 5,
+                   chunks: 1,
+                   capacity: 8,
+                   free: 0,
+                   untouched: 3,
+                   slot_size,
+                   freelist_hits: 2,
+                   bump_allocations: 5,
+               });
+    assert_eq!((diff.allocations(), diff.live_bytes()), (7, 5 * slot_size as isize));
+    assert_eq!(diff.reserved_bytes(), 8 * slot_size as isize);
+
+    let after = reap.stats();
+    objects.clear();
+    let diff = ReapStats::diff(&after, &reap.stats());
+    assert_eq!((diff.live, diff.free, diff.allocations()), (-8, 8, 0));
+    assert_eq!(diff.to_string(),
+               format!("-8 live ({} bytes), +0 chunk(s) (+0 bytes reserved), 0 allocation(s) \
+                        (0 from the freelist)",
+                       -8 * slot_size as isize));
+}
+
+#[test]
+fn test_stats() {
+    let reap = Reap::with_capacity(4);
+    // Slots are larger than the objects with the `canary` feature.
+    let slot_size = reap.stats().slot_size;
+    assert!(slot_size >= mem::size_of::<u64>());
+    assert_eq!(reap.stats(),
+               ReapStats {
+                   live: 0,
+                   chunks: 1,
+                   capacity: 4,
+                   free: 0,
+                   untouched: 4,
+                   slot_size,
+                   freelist_hits: 0,
+                   bump_allocations: 0,
+               });
+
+    let mut objects: Vec<_> = (0..6u64).map(|i| reap.allocate(i)).collect();
+    objects.truncate(3);
+    let stats = reap.stats();
+    assert_eq!((stats.live, stats.chunks, stats.capacity), (3, 2, 12));
+    assert_eq!((stats.free, stats.untouched), (3, 6));
+    assert_eq!(stats.live_bytes(), 3 * slot_size);
+    assert_eq!(stats.reserved_bytes(), 12 * slot_size);
+
+    let report = reap.report();
+    assert!(report.contains(&format!("live objects: 3 ({} bytes)", 3 * slot_size)));
+    assert!(report.contains("#0: 4 slots, 3 in use (75.0%), 1 free, 0 untouched"));
+    assert!(report.contains("#1: 8 slots, 0 in use (0.0%), 2 free, 6 untouched"));
+
+    // 6 slots handed out fresh, none reused yet, and half of those touched still in use.
+    assert_eq!((stats.freelist_hits, stats.bump_allocations), (0, 6));
+    assert_eq!((stats.hit_rate(), stats.occupancy()), (0.0, 0.5));
+    let chunks = reap.chunk_stats();
+    assert_eq!(chunks,
+               [ChunkStats {
+                    capacity: 4,
+                    live: 3,
+                    free: 1,
+                    untouched: 0,
+                },
+                ChunkStats {
+                    capacity: 8,
+                    live: 0,
+                    free: 2,
+                    untouched: 6,
+                }]);
+    assert_eq!((chunks[0].occupancy(), chunks[1].occupancy()), (0.75, 0.0));
+    objects.extend((0..3).map(|i| reap.allocate(i)));
+    objects.push(reap.allocate(3));
+    let stats = reap.stats();
+    assert_eq!((stats.freelist_hits, stats.bump_allocations), (3, 7));
+    assert_eq!(stats.hit_rate(), 0.3);
+    assert_eq!(stats.occupancy(), 1.0);
+
+    // ZSTs are only counted.
+    struct Zst;
+    let zsts = Reap::new();
+    let _zst = zsts.allocate(Zst);
+    assert_eq!(zsts.stats(),
+               ReapStats {
+                   live: 1,
+                   ..ReapStats::default()
+               });
+}
+
+#[test]
+fn test_allocate_with_panic() {
+    let reap = Reap::new();
+    let a = reap.allocate_with(|| 1);
+
+    let result = panic::catch_unwind(AssertUnwindSafe(|| {
+        reap.allocate_with(|| -> u32 { panic!("construction failed") })
+    }));
+    assert!(result.is_err());
+    // The reserved slot is back on the freelist, not leaked or counted as live.
+    assert_eq!((reap.stats().live, reap.stats().free), (1, 1));
+
+    // Nested allocation from within the closure.
+    let b = reap.allocate_with(|| *reap.allocate(2) + 1);
+    assert_eq!((*a, *b), (1, 3));
+    assert_eq!((reap.stats().live, reap.stats().free), (2, 1));
+}
+
+#[test]
+fn test_owns() {
+    let a = Reap::with_capacity(4);
+    let b = Reap::new();
+    let x = a.allocate(1u32);
+    let y = b.allocate(2u32);
+    assert!(a.owns(&x) && !a.owns(&y));
+    assert!(b.owns(&y) && !b.owns(&x));
+    assert!(a.owns(&x.reap().allocate(3)));
+
+    assert!(a.contains_ptr(&*x));
+    assert!(!a.contains_ptr(&*y));
+    assert!(b.contains_ptr(&*y));
+    let local = 4u32;
+    assert!(!a.contains_ptr(&local));
+
+    let zsts = Reap::new();
+    let z = zsts.allocate(());
+    assert!(zsts.owns(&z));
+    assert!(!zsts.contains_ptr(&*z));
+}
+
+#[test]
+fn test_panicking_destructor() {
+    struct Bomb(#[allow(dead_code)] u64);
+
+    impl Drop for Bomb {
+        fn drop(&mut self) {
+            panic!("boom");
+        }
+    }
+
+    fn assert_unwind_safe<T: panic::UnwindSafe + panic::RefUnwindSafe>(_: &T) {}
+
+    let reap = Reap::new();
+    assert_unwind_safe(&reap);
+    let bomb = reap.allocate(Bomb(0));
+    assert_unwind_safe(&bomb);
+    assert!(panic::catch_unwind(|| drop(bomb)).is_err());
+    // The slot was released despite the panic, and is the next to be reused.
+    assert_eq!((reap.stats().live, reap.stats().free), (0, 1));
+    let next = reap.allocate(Bomb(0));
+    assert_eq!(reap.stats().free, 0);
+    mem::forget(next);
+
+    // Not `RefUnwindSafe` itself, but a `Reap` of it is.
+    assert_unwind_safe(&Reap::<Cell<u32>>::new());
+}
+
+#[test]
+fn test_try_with_capacity() {
+    assert_eq!(n_chunks(&Reap::<u64>::try_with_capacity(16).unwrap()), 1);
+    assert_eq!(n_chunks(&Reap::<u64>::try_with_capacity(0).unwrap()), 0);
+    let too_many = isize::MAX as usize / mem::size_of::<u64>() + 1;
+    assert_eq!(Reap::<u64>::try_with_capacity(too_many).err(),
+               Some(AllocError::CapacityOverflow));
+    assert_eq!(Reap::<u64>::try_with_capacity(usize::MAX).err(),
+               Some(AllocError::CapacityOverflow));
+    // Zero-sized types need no memory, however many there are.
+    assert!(Reap::<()>::try_with_capacity(usize::MAX).is_ok());
+}
+
+#[test]
+fn test_with_capacity_bytes() {
+    let slot_size = Reap::<[u8; 24]>::new().stats().slot_size;
+    let reap = Reap::<[u8; 24]>::with_capacity_bytes(10 * slot_size + slot_size / 2);
+    assert_eq!((reap.stats().chunks, reap.stats().capacity), (1, 10));
+    assert_eq!(n_chunks(&Reap::<[u8; 24]>::with_capacity_bytes(slot_size - 1)), 0);
+    assert_eq!(n_chunks(&Reap::<()>::with_capacity_bytes(usize::MAX)), 0);
+}
+
+#[test]
+#[should_panic(expected = "capacity overflow")]
+fn test_with_capacity_overflow() {
+    Reap::<u64>::with_capacity(usize::MAX / 4);
+}
+
+#[cfg(feature = "verify")]
+#[test]
+fn test_verify() {
+    let reap = Reap::with_capacity(4);
+    reap.verify();
+    let mut objects: Vec<_> = (0..10u32).map(|i| reap.allocate(i)).collect();
+    objects.drain(2..7);
+    reap.verify();
+    objects.push(reap.allocate(10));
+    reap.verify();
+    drop(objects);
+    reap.verify();
+}
+
+#[cfg(feature = "verify")]
+#[test]
+#[should_panic(expected = "is on the freelist twice")]
+fn test_verify_double_free() {
+    let reap = Reap::new();
+    let ptr = Rp::into_raw(reap.allocate(1u32)).0.as_ptr();
+    reap.release(ptr);
+    reap.0.freelist.borrow_mut().push(ptr as *mut u8);
+    reap.verify();
+}
+
+#[test]
+fn test_try_finalize() {
+    let reap = Reap::with_capacity(8);
+    let a = reap.allocate(1u32);
+    let other = reap.clone();
+    drop(a);
+
+    // Another handle is still around.
+    let reap = reap.try_finalize().unwrap_err();
+    drop(other);
+
+    // A leaked object.
+    let (ptr, handle) = Rp::into_raw(reap.allocate(2));
+    let reap = reap.try_finalize().unwrap_err();
+    drop(unsafe { Rp::from_raw(ptr, handle) });
+
+    let stats = reap.try_finalize().unwrap();
+    assert_eq!((stats.live, stats.free, stats.capacity), (0, 1, 8));
+}
+
+#[cfg(feature = "trace")]
+#[test]
+fn test_trace_replay() {
+    use super::trace::{Op, Trace};
+
+    let reap = Reap::new();
+    let before = reap.allocate(0u32);
+    reap.start_trace();
+    let a = reap.allocate(1);
+    let b = reap.allocate(2);
+    drop(before);
+    drop(a);
+    let c = reap.allocate(3);
+    let trace = reap.finish_trace().unwrap();
+    assert!(reap.finish_trace().is_none());
+
+    let ops: Vec<_> = trace.events().iter().map(|e| (e.op, e.slot)).collect();
+    assert_eq!(ops,
+               [(Op::Alloc, 1), (Op::Alloc, 2), (Op::Free, 0), (Op::Free, 1), (Op::Alloc, 1)]);
+    assert!(trace.events().windows(2).all(|w| w[0].nanos <= w[1].nanos));
+
+    let decoded = Trace::decode(&trace.encode()).unwrap();
+    assert_eq!(decoded, trace);
+    assert!(Trace::decode(&trace.encode()[..3]).is_none());
+
+    // The release of `before`, allocated ahead of the trace, is skipped.
+    let replayed = Reap::new();
+    let live = decoded.replay(&replayed, || 0u32);
+    assert_eq!(live.len(), 2);
+    assert_eq!(replayed.stats().live, 2);
+    drop((b, c));
+}
+
+#[test]
+fn test_zeroize() {
+    let reap = Reap::new();
+    assert!(!reap.zeroizes());
+    reap.set_zeroize(true);
+    assert!(reap.zeroizes());
+
+    let secret = reap.allocate([0xAAu8; 32]);
+    let slot: *const [u8; 32] = &*secret;
+    drop(secret);
+    let expected = if cfg!(feature = "poison") { super::POISON } else { 0 };
+    // The slot stays allocated within the arena, so it can still be read.
+    assert_eq!(unsafe { *slot }, [expected; 32]);
+
+    let moved = Rp::take(reap.allocate([0xBBu8; 32]));
+    assert_eq!(moved, [0xBB; 32]);
+    assert_eq!(unsafe { *slot }, [expected; 32]);
+}
+
+#[cfg(feature = "mlock")]
+#[test]
+fn test_mlock() {
+    let reap = Reap::new();
+    reap.set_mlock(true);
+    let objects: Vec<_> = (0..1000u64).map(|i| reap.allocate(i)).collect();
+    assert!(objects.iter().enumerate().all(|(i, x)| **x == i as u64));
+
+    // Locking may well fail in a constrained environment, but the chunks are page-sized anyway.
+    let page = super::page_size();
+    for chunk in reap.0.chunks.borrow().iter() {
+        assert_eq!(chunk.ptr as usize % page, 0);
+        assert_eq!(chunk.layout.size() % page, 0);
+    }
+    assert_eq!(reap.mlocked_bytes() % page, 0);
+}
+
+#[test]
+fn test_zst_drops() {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    static DROPS: AtomicUsize = AtomicUsize::new(0);
+
+    struct Counted;
+
+    impl Drop for Counted {
+        fn drop(&mut self) {
+            DROPS.fetch_add(1, Ordering::SeqCst);
+        }
+    }
+
+    let reap = Reap::new();
+    let objects: Vec<_> = (0..100).map(|_| reap.allocate(Counted)).collect();
+    assert_eq!(reap.stats().live, 100);
+    drop(objects);
+    assert_eq!(DROPS.load(Ordering::SeqCst), 100);
+    assert_eq!(reap.stats().live, 0);
+    assert_eq!(reap.0.freelist.borrow().len(), 0);
+
+    // Moved out, not dropped by the arena.
+    let taken = Rp::take(reap.allocate_with(|| Counted));
+    assert_eq!(DROPS.load(Ordering::SeqCst), 100);
+    drop(taken);
+    assert_eq!(DROPS.load(Ordering::SeqCst), 101);
+    assert_eq!(reap.0.freelist.borrow().len(), 0);
+}
+
+#[test]
+fn test_zst_alignment() {
+    #[repr(align(64))]
+    struct Aligned;
+
+    let reap = Reap::new();
+    let a = reap.allocate(Aligned);
+    let b = reap.allocate(Aligned);
+    assert_eq!(&*a as *const Aligned as usize % 64, 0);
+    assert_eq!(&*b as *const Aligned as usize % 64, 0);
+}
+
+#[test]
+fn test_grow_uses_every_slot() {
+    let reap = Reap::with_capacity(3);
+    let objects: Vec<_> = (0..100u32).map(|i| reap.allocate(i)).collect();
+    let stats = reap.stats();
+    // 3 + 6 + 12 + 24 + 48 + 96 slots, of which only the tail of the last chunk is untouched.
+    assert_eq!(stats.chunks, 6);
+    assert_eq!(stats.capacity - stats.untouched, 100);
+    assert_eq!(stats.free, 0);
+    drop(objects);
+}
+
+#[test]
+fn test_frame_reap() {
+    let frames = FrameReap::new();
+    let mut a = frames.allocate(1);
+    *a += 1;
+    assert_eq!((*a, a.frame()), (2, 0));
+
+    assert_eq!(frames.next_frame(), 1);
+    assert!(!a.is_current());
+    let b = frames.allocate(3);
+    assert!(b.is_current());
+    // Stale handles can still be dropped.
+    drop(a);
+
+    let kept = FrameRp::into_rp(b);
+    frames.next_frame();
+    assert_eq!(*kept, 3);
+}
+
+#[cfg(debug_assertions)]
+#[test]
+#[should_panic(expected = "handle from frame 0 used in frame 1")]
+fn test_frame_reap_stale() {
+    let frames = FrameReap::new();
+    let a = frames.allocate(1);
+    frames.next_frame();
+    let _ = *a;
+}
+
+#[test]
+fn test_alloc_extend() {
+    let reap = Reap::new();
+    let empty = reap.alloc_extend(Vec::new());
+    assert!(empty.is_empty());
+
+    let a = reap.alloc_extend((0..10).map(|i| [i; 4]));
+    // Larger than a page worth of arrays, so in a chunk of its own.
+    let b = reap.alloc_extend((0..1000).map(|i| [i; 4]));
+    // Allocating from within the iterator.
+    let c = reap.alloc_extend((0..3).map(|i| {
+        let inner = reap.alloc_extend(Some([i; 4]));
+        [inner[0][0] * 10; 4]
+    }));
+    a[9][0] += 1;
+    assert_eq!(a[9], [10, 9, 9, 9]);
+    assert_eq!(b.len(), 1000);
+    assert!(b.iter().enumerate().all(|(i, x)| x[3] == i as i32));
+    assert_eq!(c.iter().map(|x| x[0]).collect::<Vec<_>>(), [0, 10, 20]);
+    assert_eq!(reap.stats().live, 0);
+
+    let zsts = Reap::new();
+    assert_eq!(zsts.alloc_extend(vec![(); 5]).len(), 5);
+}
+
+#[test]
+fn test_dense_pool() {
+    let mut pool = DensePool::new();
+    let handles: Vec<_> = (0..10).map(|i| pool.insert(i)).collect();
+    assert_eq!(pool.remove(handles[0]), Some(0));
+    assert_eq!(pool.remove(handles[0]), None);
+    assert_eq!(pool.remove(handles[5]), Some(5));
+    assert_eq!(pool.len(), 8);
+
+    // Every surviving handle still finds its value, whatever it was moved to.
+    for (i, &handle) in handles.iter().enumerate() {
+        assert_eq!(pool.get(handle), if i == 0 || i == 5 { None } else { Some(&i) });
+    }
+    for (handle, value) in pool.handles().zip(pool.iter()) {
+        assert_eq!(pool.get(handle), Some(value));
+    }
+
+    // A reused slot doesn't answer to the old handle.
+    let new = pool.insert(100);
+    assert!(!pool.contains(handles[5]) && !pool.contains(handles[0]));
+    *pool.get_mut(new).unwrap() += 1;
+    assert_eq!(pool.as_slice().last(), Some(&101));
+    assert_eq!(pool.iter().sum::<usize>(), 45 - 5 + 101);
+
+    pool.clear();
+    assert!(pool.is_empty() && !pool.contains(new));
+
+    // Growing moves the values to a larger chunk, and dropping the pool drops them.
+    let drops = Rc::new(());
+    let mut pool = DensePool::with_capacity(2);
+    let handles: Vec<_> = (0..1000).map(|i| pool.insert((i, drops.clone()))).collect();
+    assert!(pool.capacity() >= 1000);
+    assert_eq!(pool.remove(handles[500]).map(|(i, _)| i), Some(500));
+    assert_eq!(pool.get(handles[999]).map(|&(i, _)| i), Some(999));
+    assert_eq!(Rc::strong_count(&drops), 1000);
+    drop(pool);
+    assert_eq!(Rc::strong_count(&drops), 1);
+
+    let mut units = DensePool::new();
+    let unit = units.insert(());
+    assert_eq!((units.get(unit), units.capacity()), (Some(&()), usize::MAX));
+}
+
+#[test]
+fn test_session_pool() {
+    use std::time::Duration;
+
+    let pool = SessionPool::new();
+    let a = pool.insert(1);
+    let b = pool.insert(2);
+    assert!(pool.checkout().is_none());
+    drop(a);
+    drop(b);
+    assert_eq!((pool.len(), pool.idle()), (2, 2));
+
+    // Most recently checked in first.
+    let b = pool.checkout().unwrap();
+    assert_eq!(*b, 2);
+    assert_eq!(Session::detach(b), 2);
+    assert_eq!((pool.len(), pool.idle()), (1, 1));
+
+    assert_eq!(pool.evict_idle(Duration::from_secs(3600), |_| panic!("evicted")), 0);
+    let mut evicted = Vec::new();
+    assert_eq!(pool.evict_idle(Duration::from_secs(0), |s| evicted.push(s)), 1);
+    assert_eq!(evicted, [1]);
+    assert!(pool.is_empty());
+
+    // Recycling from within the eviction callback.
+    drop(pool.insert(3));
+    pool.evict_idle(Duration::from_secs(0), |s| drop(pool.insert(s + 1)));
+    assert_eq!(*pool.checkout().unwrap(), 4);
+}
+
+#[test]
+fn test_reap_array() {
+    struct Counted(Rc<Cell<usize>>);
+
+    impl Drop for Counted {
+        fn drop(&mut self) {
+            self.0.set(self.0.get() + 1);
+        }
+    }
+
+    let drops = Rc::new(Cell::new(0));
+    let array: ReapArray<Counted, 3> = ReapArray::new();
+    let a = array.allocate(Counted(drops.clone()));
+    let b = array.allocate(Counted(drops.clone()));
+    let c = array.allocate(Counted(drops.clone()));
+    assert!(array.is_full());
+    match array.try_allocate(Counted(drops.clone())) {
+        Ok(_) => panic!("allocated past capacity"),
+        Err(d) => drop(d),
+    }
+    assert_eq!(drops.get(), 1);
+
+    // Freed slots are reused, most recently freed first.
+    let (a_addr, c_addr) = (&*a as *const Counted, &*c as *const Counted);
+    drop(a);
+    drop(c);
+    assert_eq!((array.len(), drops.get()), (1, 3));
+    let e = array.allocate(Counted(drops.clone()));
+    let f = array.allocate(Counted(drops.clone()));
+    assert_eq!((&*e as *const Counted, &*f as *const Counted), (c_addr, a_addr));
+
+    let moved = ArrayRp::into_inner(b);
+    assert_eq!((array.len(), drops.get()), (2, 3));
+    drop((moved, e, f));
+    assert_eq!((array.len(), drops.get()), (0, 6));
+
+    let zsts: ReapArray<(), 2> = ReapArray::default();
+    let _x = zsts.allocate(());
+    let _y = zsts.allocate(());
+    assert!(zsts.try_allocate(()).is_err());
+}
+
+#[test]
+fn test_fixed_reap() {
+    let reap = FixedReap::with_capacity(3);
+    let a = reap.allocate(vec![1]);
+    let b = reap.allocate(vec![2]);
+    let c = reap.allocate(vec![3]);
+    assert!(reap.is_full());
+    assert_eq!(reap.try_allocate(vec![4]).unwrap_err(), [4]);
+
+    // Handles keep the arena alive.
+    let handle = a.reap().clone();
+    drop(reap);
+    let b_addr = &*b as *const Vec<i32>;
+    assert_eq!(FixedRp::into_inner(b), [2]);
+    let mut d = handle.allocate(vec![5]);
+    assert_eq!(&*d as *const Vec<i32>, b_addr);
+    d.push(6);
+    drop((a, c));
+    assert_eq!((handle.len(), &*d), (1, &vec![5, 6]));
+
+    let empty = FixedReap::with_capacity(0);
+    assert!(empty.is_full() && empty.try_allocate(()).is_err());
+    let zsts = FixedReap::with_capacity(1);
+    let _x = zsts.allocate(());
+}
+
+#[test]
+fn test_spsc_reap() {
+    use std::sync::mpsc;
+    use std::thread;
+
+    let mut reap = SpscReap::with_capacity(2);
+    let a = reap.allocate(String::from("a")).unwrap();
+    let b = reap.allocate(String::from("b")).unwrap();
+    assert_eq!(reap.allocate(String::from("c")).unwrap_err(), "c");
+    let a_addr = &*a as *const String;
+    assert_eq!(SpscRp::into_inner(a), "a");
+    let c = reap.allocate(String::from("c")).unwrap();
+    assert_eq!(&*c as *const String, a_addr);
+
+    // Freed on another thread, and reused here.
+    let (tx, rx) = mpsc::channel::<SpscRp<String>>();
+    let consumer = thread::spawn(move || rx.iter().map(|s| s.len()).sum::<usize>());
+    tx.send(b).unwrap();
+    tx.send(c).unwrap();
+    let mut sent = 0;
+    while sent < 100 {
+        if let Ok(s) = reap.allocate("x".repeat(sent)) {
+            tx.send(s).unwrap();
+            sent += 1;
+        }
+    }
+    drop(tx);
+    assert_eq!(consumer.join().unwrap(), 2 + (0..100).sum::<usize>());
+    assert!(reap.allocate(String::new()).is_ok());
+
+    let mut empty = SpscReap::with_capacity(0);
+    assert!(empty.allocate(()).is_err());
+}
+
+#[test]
+fn test_into_box_and_rc() {
+    let reap = Reap::new();
+    let a = reap.allocate(vec![1, 2]);
+    let addr = &*a as *const Vec<i32>;
+    let boxed = Rp::into_box(a);
+    assert_eq!(reap.stats().live, 0);
+    // The slot is free for reuse, while the value lives on elsewhere.
+    let b = reap.allocate(vec![3]);
+    assert_eq!(&*b as *const Vec<i32>, addr);
+
+    let rc = Rp::into_rc(b);
+    drop(reap);
+    assert_eq!((*boxed, (*rc).clone()), (vec![1, 2], vec![3]));
+}
+
+#[test]
+fn test_adopt_vec() {
+    let reap = Reap::with_capacity(4);
+    let first = reap.allocate(String::from("first"));
+    let mut vec = Vec::with_capacity(3);
+    vec.push(String::from("a"));
+    vec.push(String::from("b"));
+    let addr = vec.as_ptr();
+    let adopted = reap.adopt_vec(vec);
+    assert_eq!(adopted.iter().map(|s| &s[..]).collect::<Vec<_>>(), ["a", "b"]);
+    if !cfg!(debug_assertions) && !cfg!(feature = "canary") && !cfg!(feature = "tags") {
+        // Not copied, and the old chunk's untouched slots weren't thrown away.
+        assert_eq!(&*adopted[0] as *const String, addr);
+        assert_eq!(reap.stats().free, 3);
+        assert!(reap.contains_ptr(addr));
+    }
+    let more: Vec<_> = (0..10).map(|i| reap.allocate(i.to_string())).collect();
+    #[cfg(feature = "verify")]
+    reap.verify();
+    drop((first, adopted, more));
+    assert_eq!(reap.stats().live, 0);
+
+    let zsts = Reap::new();
+    assert_eq!(zsts.adopt_vec(vec![(); 3]).len(), 3);
+    assert!(Reap::<u8>::new().adopt_vec(Vec::new()).is_empty());
+}
+
+#[test]
+fn test_from_iter() {
+    let (reap, rps) = Reap::from_iter(vec![1, 2, 3]);
+    assert_eq!(rps.iter().map(|x| **x).collect::<Vec<_>>(), [1, 2, 3]);
+    let stats = reap.stats();
+    assert_eq!((stats.live, stats.chunks, stats.capacity), (3, 1, 3));
+
+    // No size hint to go by, so the arena grows as usual.
+    let (reap, rps) = Reap::from_iter((0..1000).filter(|i| i % 2 == 0));
+    assert_eq!((rps.len(), reap.stats().live), (500, 500));
+
+    let (reap, rps) = Reap::<u8>::from_iter(None);
+    assert!(rps.is_empty() && reap.stats().chunks == 0);
+}
+
+#[test]
+fn test_allocate_in() {
+    let reap = Reap::new();
+    let x = vec![1, 2].allocate_in(&reap);
+    assert!(reap.owns(&x));
+
+    // Lazily, so nothing is allocated until asked for.
+    let mut rps = (0..3).map(|i| vec![i]).allocated_in(&reap);
+    assert_eq!((rps.len(), reap.stats().live), (3, 1));
+    assert_eq!(*rps.next_back().unwrap(), [2]);
+    let rest: Vec<_> = rps.collect();
+    assert_eq!((rest.len(), reap.stats().live), (2, 3));
+}
+
+#[test]
+fn test_macros() {
+    struct Node {
+        value: u32,
+        children: Vec<Rp<Node>>,
+    }
+
+    let reap = Reap::new();
+    let by_ref = &reap;
+    let root = rp!(in reap; Node {
+        value: 1,
+        children: vec![rp!(in by_ref; Node { value: 2, children: Vec::new() }),
+                       rp!(in reap; Node { value: 3, children: Vec::new() })],
+    });
+    assert_eq!(root.value + root.children.iter().map(|c| c.value).sum::<u32>(), 6);
+    assert_eq!(reap.stats().live, 3);
+
+    let (reap, rps) = reap![1, 2, 3,];
+    assert_eq!((*rps[2], reap.stats().live), (3, 3));
+    let (_, rps) = reap![String::from("x"); 2];
+    assert_eq!(rps.iter().map(|s| &s[..]).collect::<String>(), "xx");
+    let (_, rps) = reap![];
+    let _: Vec<Rp<u8>> = rps;
+}
+
+#[test]
+fn test_allocate_clone_from() {
+    let reap = Reap::new();
+    let original = vec![String::from("a"), String::from("b")];
+    let first = reap.allocate_clone_from(&original);
+    let addr = &*first as *const Vec<String>;
+    drop(first);
+    // Clones go through the freelist like any other allocation.
+    let second = reap.allocate_clone_from(&original);
+    assert_eq!(&*second as *const Vec<String>, addr);
+    assert_eq!(*second, original);
+}
+
+#[test]
+fn test_migrate() {
+    let (source, target) = (Reap::new(), Reap::new());
+    let x = Rp::migrate(source.allocate(vec![1, 2, 3]), &target);
+    assert!(target.owns(&x));
+    assert_eq!((source.stats().live, target.stats().live), (0, 1));
+    // Already there, so not moved again.
+    let addr = &*x as *const Vec<i32>;
+    let x = Rp::migrate(x, &target);
+    assert_eq!(&*x as *const Vec<i32>, addr);
+    assert_eq!(target.stats().live, 1);
+    // The source arena can go while the migrated value lives on.
+    drop(source);
+    assert_eq!(*x, [1, 2, 3]);
+}
+
+// An `Rp`'s hash and equality only depend on its value, not on the arena's interior mutability.
+#[allow(clippy::mutable_key_type)]
+#[test]
+fn test_borrow_bridges() {
+    use std::collections::{HashMap, HashSet};
+
+    let strings = Reap::new();
+    let set: HashSet<_> = ["a", "b"].iter().map(|s| strings.allocate(s.to_string())).collect();
+    assert!(set.contains("a") && !set.contains("c"));
+
+    let bytes = Reap::new();
+    let mut map = HashMap::new();
+    map.insert(bytes.allocate(b"key".to_vec()), 1);
+    assert_eq!(map.get(&b"key"[..]), Some(&1));
+    assert_eq!(map.get(&b"nope"[..]), None);
+}
+
+#[allow(clippy::mutable_key_type)]
+#[test]
+fn test_by_addr() {
+    use std::collections::{BTreeSet, HashMap};
+
+    struct Opaque;
+
+    let reap = Reap::new();
+    let (a, b) = (reap.allocate(7), reap.allocate(7));
+    assert_eq!(*a, *b);
+    assert!(ByAddr(&*a) != ByAddr(&*b));
+    assert!(ByAddr(&*a) == ByAddr(&*a));
+
+    // Neither `Hash` nor `Ord` is needed of the objects themselves.
+    let opaque = Reap::new();
+    let mut memo = HashMap::new();
+    let nodes: Vec<_> = (0..3).map(|_| ByAddr(opaque.allocate(Opaque))).collect();
+    memo.insert(ByAddr(&*nodes[1]), "one");
+    assert_eq!(memo.get(&ByAddr(&*nodes[1])), Some(&"one"));
+    let sorted: BTreeSet<_> = vec![ByAddr(a), ByAddr(b)].into_iter().collect();
+    assert_eq!(sorted.len(), 2);
+}
+
+#[test]
+fn test_arena_ids() {
+    let a = Reap::new();
+    let b = Reap::<u8>::with_capacity(4);
+    let c = Reap::new();
+    assert!(a.id() != b.id() && b.id() != c.id() && a.id() != c.id());
+    let x = c.allocate(1);
+    assert_eq!((x.arena_id(), x.reap().id(), c.clone().id()), (c.id(), c.id(), c.id()));
+    assert_ne!(x.arena_id(), a.allocate(1).arena_id());
+}
+
+#[test]
+fn test_ttl_cache() {
+    let mut cache = TtlCache::new();
+    let keys: Vec<_> = (0..10).map(|i| cache.insert(i, i as u64)).collect();
+    assert_eq!(cache.sweep(2), 3);
+    assert_eq!((cache.len(), cache.reap().stats().live), (7, 7));
+    assert_eq!(cache.get(keys[2]), None);
+    assert_eq!(cache.get(keys[3]), Some(&3));
+
+    // Extended and removed values aren't swept on their old expiry.
+    assert!(cache.set_expires(keys[3], 100));
+    assert!(!cache.set_expires(keys[0], 100));
+    assert_eq!(cache.remove(keys[4]), Some(4));
+    assert_eq!(cache.remove(keys[4]), None);
+    *cache.get_mut(keys[5]).unwrap() += 50;
+    assert_eq!(cache.sweep(9), 5);
+    assert_eq!(cache.expires(keys[3]), Some(100));
+
+    // A reused entry doesn't answer to an old key.
+    let new = cache.insert(20, 20);
+    assert_eq!((cache.get(keys[5]), cache.get(new)), (None, Some(&20)));
+    assert_eq!(cache.sweep(9), 0);
+    cache.clear();
+    assert!(cache.is_empty() && cache.get(keys[3]).is_none());
+    assert_eq!(cache.reap().stats().live, 0);
+}
+
+#[test]
+fn test_frozen() {
+    struct Counted(Rc<Cell<usize>>, u32);
+
+    impl Drop for Counted {
+        fn drop(&mut self) {
+            self.0.set(self.0.get() + 1);
+        }
+    }
+
+    let drops = Rc::new(Cell::new(0));
+    let reap = Reap::new();
+    let frozen = Rp::freeze(reap.allocate(Counted(drops.clone(), 7)));
+    assert_eq!(frozen.1, 7);
+    assert!(Frozen::reap(&frozen).contains_ptr(&*frozen));
+    // Still owns the slot.
+    assert_eq!(reap.stats().live, 1);
+    drop(frozen);
+    assert_eq!((drops.get(), reap.stats().live), (1, 0));
+
+    let ints = Reap::new();
+    let (a, b) = (Rp::freeze(ints.allocate(1)), Rp::freeze(ints.allocate(2)));
+    assert_eq!(format!("{:p}", a), format!("{:p}", &*a));
+    assert!(a < b && a != b);
+}
+
+#[test]
+fn test_transaction() {
+    use std::cell::RefCell;
+
+    struct Logged(Rc<RefCell<Vec<u32>>>, u32);
+
+    impl Drop for Logged {
+        fn drop(&mut self) {
+            self.0.borrow_mut().push(self.1);
+        }
+    }
+
+    let order = Rc::new(RefCell::new(Vec::new()));
+
+    let reap = Reap::new();
+    let kept = reap.allocate(Logged(order.clone(), 0));
+    let mut tx = reap.begin();
+    for i in 1..4 {
+        tx.allocate(Logged(order.clone(), i));
+    }
+    tx[2].1 = 30;
+    assert_eq!(tx.iter().map(|l| l.1).collect::<Vec<_>>(), [1, 2, 30]);
+    tx.abort();
+    // Rolled back latest first, and the slots are reused.
+    assert_eq!(*order.borrow(), [30, 2, 1]);
+    assert_eq!(reap.stats().live, 1);
+    let free = reap.stats().free;
+
+    let mut tx = reap.begin();
+    tx.allocate(Logged(order.clone(), 4));
+    assert_eq!(tx.get(0).map(|l| l.1), Some(4));
+    assert!(tx.get(1).is_none());
+    drop(tx);
+    assert_eq!(order.borrow().last(), Some(&4));
+
+    let mut tx = reap.begin();
+    tx.allocate(Logged(order.clone(), 5));
+    tx.get_mut(0).unwrap().1 = 50;
+    let committed = tx.commit();
+    assert_eq!((committed[0].1, reap.stats().live, reap.stats().free), (50, 2, free - 1));
+    drop((kept, committed));
+}
+
+#[cfg(feature = "lifetimes")]
+#[test]
+fn test_lifetime_histogram() {
+    use super::lifetime::LifetimeHistogram;
+
+    let reap = Reap::new();
+    let a = reap.allocate(0u32);
+    let b = reap.allocate(1);
+    drop(reap.allocate(2));
+    drop(a);
+    // Lived through 2 other allocations, and so did `c` below.
+    assert_eq!(reap.lifetime_histogram().counts()[..3], [1, 0, 1]);
+    let c = Rp::take(reap.allocate(3));
+    drop(reap.allocate(c));
+    drop(b);
+    let histogram = reap.lifetime_histogram();
+    assert_eq!((histogram.counts()[2], histogram.total()), (2, 5));
+    assert_eq!(LifetimeHistogram::bucket_range(2), (2, 3));
+    assert_eq!(LifetimeHistogram::bucket_range(64), (1 << 63, u64::MAX));
+    assert!(format!("{}", histogram).starts_with("5 object(s) freed"));
+
+    reap.reset_lifetime_histogram();
+    assert_eq!(reap.lifetime_histogram().total(), 0);
+
+    let zsts = Reap::new();
+    let (x, y) = (zsts.allocate(()), zsts.allocate(()));
+    drop((y, x));
+    assert_eq!(zsts.lifetime_histogram().total(), 2);
+}
+
+#[test]
+fn test_peaks() {
+    let reap = Reap::new();
+    assert_eq!(reap.peaks().live, 0);
+    let mut objects: Vec<_> = (0..10u64).map(|i| reap.allocate(i)).collect();
+    objects.truncate(4);
+    objects.push(reap.allocate(10));
+    let peaks = reap.peaks();
+    assert_eq!((peaks.live, peaks.free), (10, 6));
+    assert_eq!(peaks.slot_size, reap.stats().slot_size);
+    assert_eq!(peaks.live_bytes(), 10 * peaks.slot_size);
+
+    // Back to the current usage, and up again from there.
+    reap.reset_peaks();
+    assert_eq!((reap.peaks().live, reap.peaks().free), (5, 5));
+    objects.extend(reap.adopt_vec(vec![11, 12]));
+    objects.push(reap.allocate(13));
+    assert_eq!(reap.peaks().live, 8);
+    drop(objects);
+    assert_eq!(reap.peaks().live, 8);
+    assert_eq!(reap.peaks().free, reap.stats().free);
+
+    let zsts = Reap::new();
+    let units: Vec<_> = (0..3).map(|_| zsts.allocate(())).collect();
+    drop(units);
+    assert_eq!(zsts.peaks(),
+               ReapPeaks {
+                   live: 3,
+                   ..ReapPeaks::default()
+               });
+}
+
+#[test]
+fn test_dump_layout() {
+    let reap = Reap::with_capacity(2);
+    let mut objects: Vec<_> = (0..4u32).map(|i| reap.allocate(i)).collect();
+    objects.swap_remove(0);
+    objects.swap_remove(1);
+    let layout = reap.dump_layout();
+    let slot_size = reap.stats().slot_size;
+    assert!(layout.starts_with(&format!(r#"{{"type":"u32","slot_size":{},"live":2,"chunks":[{{"#,
+                                        slot_size)));
+    assert!(layout.contains(r#""capacity":2,"slots":"FF""#));
+    assert!(layout.contains(r#""capacity":4,"slots":"LLUU""#));
+    // Reused latest freed first.
+    assert!(layout.ends_with(r#""freelist":[[0,1],[0,0]]}"#));
+
+    let zsts = Reap::new();
+    let _unit = zsts.allocate(());
+    assert_eq!(zsts.dump_layout(),
+               r#"{"type":"()","slot_size":0,"live":1,"chunks":[],"freelist":[]}"#);
+    assert!(Reap::<&str>::new().dump_layout().starts_with(r#"{"type":"&str","#));
+}
+
+#[cfg(feature = "tags")]
+#[test]
+fn test_allocate_named() {
+    let reap = Reap::with_capacity(4);
+    let a = reap.allocate_named(1u32, "parser::expr");
+    let b = reap.allocate(2);
+    assert_eq!((Rp::tag(&a), Rp::tag(&b)), (Some("parser::expr"), None));
+    assert!(reap.dump_layout().contains(r#""slots":"LLUU","tags":[[0,"parser::expr"]]"#));
+
+    // A reused slot doesn't keep the tag of its previous object.
+    drop(a);
+    let c = reap.allocate(3);
+    assert_eq!(Rp::tag(&c), None);
+    let d = reap.allocate_named(4, "lexer");
+    assert_eq!(Rp::tag(&d), Some("lexer"));
+    assert_eq!(*d, 4);
+    assert!(reap.dump_layout().contains(r#""tags":[[2,"lexer"]]"#));
+    #[cfg(feature = "backtrace")]
+    assert!(reap.dump_live().contains(" (lexer) allocated at "));
+
+    let zsts = Reap::new();
+    assert_eq!(Rp::tag(&zsts.allocate_named((), "unit")), None);
+}
+
+#[test]
+fn test_budget() {
+    let gameplay = Budget::new();
+    let ints = Reap::new();
+    let names = Reap::new();
+    let int_size = ints.stats().slot_size;
+    let name_size = names.stats().slot_size;
+    gameplay.set_max_bytes(Some(2 * int_size + name_size));
+
+    // Charged across arenas of different types.
+    let a = gameplay.try_allocate(&ints, 1u64).unwrap();
+    let name = gameplay.try_allocate(&names, String::from("player")).unwrap();
+    let b = gameplay.try_allocate(&ints, 2).unwrap();
+    assert_eq!((gameplay.objects(), gameplay.bytes()), (3, 2 * int_size + name_size));
+    assert_eq!(gameplay.try_allocate(&ints, 3).unwrap_err(), 3);
+    assert_eq!(ints.stats().live, 2);
+
+    // Converting lifts the charge, the object stays.
+    let b = Budgeted::into_rp(b);
+    assert_eq!(Budgeted::into_inner(name), "player");
+    assert_eq!((gameplay.objects(), gameplay.bytes()), (1, int_size));
+    assert_eq!((*b, ints.stats().live), (2, 2));
+    let c = gameplay.try_allocate(&ints, 3).unwrap();
+    assert_eq!(*a + *c, 4);
+    // The handle refers to the same budget.
+    Budgeted::budget(&a).set_max_objects(Some(5));
+    assert_eq!(gameplay.max_objects(), Some(5));
+    drop((a, c));
+    assert_eq!((gameplay.objects(), gameplay.bytes()), (0, 0));
+
+    // ZSTs only count as objects.
+    let units = Budget::new();
+    units.set_max_objects(Some(1));
+    units.set_max_bytes(Some(0));
+    let zsts = Reap::new();
+    let unit = units.try_allocate(&zsts, ()).unwrap();
+    assert!(units.try_allocate(&zsts, ()).is_err());
+    drop(unit);
+    assert_eq!(units.objects(), 0);
+
+    // An allocation that panics isn't charged.
+    #[cfg(feature = "failpoints")]
+    {
+        use super::failpoint;
+
+        let full = Reap::with_capacity(1);
+        let kept = gameplay.try_allocate(&full, 1u64).unwrap();
+        failpoint::fail_always();
+        let result = panic::catch_unwind(AssertUnwindSafe(|| gameplay.try_allocate(&full, 2)));
+        failpoint::clear();
+        assert!(result.is_err());
+        assert_eq!((gameplay.objects(), gameplay.bytes()), (1, int_size));
+        drop(kept);
+    }
+}
+
+#[test]
+fn test_branded_reap() {
+    struct Counted(Rc<Cell<usize>>);
+
+    impl Drop for Counted {
+        fn drop(&mut self) {
+            self.0.set(self.0.get() + 1);
+        }
+    }
+
+    let drops = Rc::new(Cell::new(0));
+    let sum = Reap::with(|reap, mut token| {
+        let objects: Vec<&Counted> = (0..100).map(|_| &*reap.allocate(Counted(drops.clone())))
+            .collect();
+        let cells: Vec<_> = (0..3).map(|_| reap.allocate_cell(Counted(drops.clone()))).collect();
+        reap.alloc_extend((0..2).map(|_| Counted(drops.clone())));
+        // Nothing is dropped before the closure returns.
+        assert_eq!(drops.get(), 0);
+
+        // Any cell can be written to with the token, one at a time.
+        let fresh = Counted(Rc::new(Cell::new(0)));
+        drop(mem::replace(cells[1].borrow_mut(&mut token), fresh));
+        assert_eq!(drops.get(), 1);
+        objects.len() + cells.iter().map(|cell| cell.borrow(&token).0.get()).sum::<usize>()
+    });
+    assert_eq!(sum, 102);
+    assert_eq!(drops.get(), 1 + 100 + 2 + 2);
+
+    let mut cell = BrandCell::new(1);
+    *cell.get_mut() += 1;
+    assert_eq!(cell.into_inner(), 2);
+    assert_eq!(Reap::with(|reap, _| reap.alloc_extend(vec![(); 3]).len()), 3);
+}
+
+#[test]
+fn test_owner_cell() {
+    let mut owner = Owner::new();
+    Reap::with(|reap, _| {
+        let cells = reap.alloc_extend((0..4).map(|i| owner.cell(vec![i])));
+        let shared: &[OwnerCell<Vec<i32>>] = cells;
+        for cell in shared {
+            let value = owner.rw(cell);
+            value.push(value[0] * 2);
+        }
+        let (a, b) = owner.rw2(&shared[1], &shared[3]);
+        a.append(b);
+        assert_eq!(*owner.ro(&shared[1]), [1, 2, 3, 6]);
+        assert!(owner.ro(&shared[3]).is_empty());
+
+        // Cells of other owners, and aliased pairs, are caught.
+        let other = Owner::new().cell(vec![0]);
+        let result = panic::catch_unwind(AssertUnwindSafe(|| owner.ro(&other).len()));
+        assert!(result.is_err());
+        let result = panic::catch_unwind(AssertUnwindSafe(|| {
+            owner.rw2(&shared[0], &shared[0]);
+        }));
+        assert!(result.is_err());
+    });
+
+    let mut cell = Owner::new().cell(1);
+    *cell.get_mut() += 1;
+    assert_eq!(cell.into_inner(), 2);
+}
+
+#[test]
+fn test_weak_reap() {
+    let reap = Reap::new();
+    let weak = reap.downgrade();
+    let registry = [weak.clone(), WeakReap::new()];
+    let rp = registry[0].allocate(String::from("kept")).unwrap();
+    assert!(registry[1].upgrade().is_none());
+    assert_eq!(registry[0].upgrade().unwrap().stats().live, 1);
+
+    // Live objects keep the arena around.
+    drop(reap);
+    assert_eq!(*registry[0].allocate(String::from("still")).unwrap(), "still");
+    drop(rp);
+    assert!(weak.upgrade().is_none());
+    assert_eq!(weak.allocate(String::from("gone")).unwrap_err(), "gone");
+    assert_eq!(format!("{:?}", weak), "WeakReap(dropped)");
+}
+
+#[test]
+fn test_alloc_extend_shorter_lifetime() {
+    struct Borrowing<'a>(&'a Cell<usize>);
+
+    fn shorten<'a>(reap: Reap<Borrowing<'static>>) -> Reap<Borrowing<'a>> {
+        reap
+    }
+
+    // A handle of shortened type can store objects the arena's own type outlives, which it then
+    // must never touch again.
+    let reap: Reap<Borrowing<'static>> = Reap::new();
+    {
+        let local = Cell::new(0);
+        let shorter = shorten(reap.clone());
+        shorter.alloc_extend(iter::once(Borrowing(&local)))[0].0.set(1);
+        assert_eq!(local.get(), 1);
+    }
+    drop(reap);
+}
+
+#[test]
+fn test_alloc_extend_oversized() {
+    let reap = Reap::new();
+    let small = reap.alloc_extend(0..4u64).as_ptr();
+    let large = reap.alloc_extend(0..100_000u64);
+    assert_eq!((large.len(), large[99_999]), (100_000, 99_999));
+    // The large extent got a chunk of its own, later ones still go after the small one.
+    let next = reap.alloc_extend(4..8u64);
+    assert_eq!(next.as_ptr(), unsafe { small.add(4) });
+    assert_eq!(next, [4, 5, 6, 7]);
+
+    // Even when it is the first.
+    let reap = Reap::new();
+    reap.alloc_extend(0..10_000u64);
+    let a = reap.alloc_extend(vec![1]).as_ptr();
+    let b = reap.alloc_extend(vec![2]).as_ptr();
+    assert_eq!(b, unsafe { a.add(1) });
+}
+
+#[test]
+fn test_prefault() {
+    let reap = Reap::<u64>::with_capacity(100_000);
+    let start = reap.0.chunks.borrow()[0].ptr as usize;
+    assert_eq!(start % super::PAGE, 0);
+    let pages = reap.prefault();
+    let bytes = reap.stats().reserved_bytes();
+    assert_eq!(pages, bytes.div_ceil(super::PAGE));
+
+    // Only what lies ahead, starting from the middle of a page.
+    let objects: Vec<_> = (0..1000).map(|i| reap.allocate(i)).collect();
+    let used = 1000 * reap.stats().slot_size;
+    assert_eq!(reap.prefault(), bytes.div_ceil(super::PAGE) - used / super::PAGE);
+    assert_eq!(*objects[999], 999);
+    assert_eq!(reap.stats().live, 1000);
+
+    // Small chunks aren't padded out to a page.
+    let small = Reap::<u8>::with_capacity(16);
+    assert!(small.0.chunks.borrow()[0].layout.align() < super::PAGE);
+    assert!(small.prefault() <= 2);
+    assert_eq!(Reap::<()>::new().prefault(), 0);
+}
+
+#[test]
+fn test_reap_default() {
+    #[derive(Default)]
+    struct Pools {
+        names: Reap<String>,
+        ids: Reap<u32>,
+    }
+
+    let pools = Pools::default();
+    let name = pools.names.allocate(String::from("default"));
+    assert_eq!((&name[..], pools.ids.stats().live), ("default", 0));
+    assert_ne!(pools.names.id(), Reap::<String>::default().id());
+}
+
+#[test]
+fn test_reap_collect() {
+    let collected: ReapCollect<String> = "a b c".split(' ').map(String::from).collect();
+    assert_eq!(collected.rps.iter().map(|s| &s[..]).collect::<Vec<_>>(), ["a", "b", "c"]);
+    assert_eq!(collected.reap.stats().chunks, 1);
+    assert!(collected.rps.iter().all(|rp| collected.reap.owns(rp)));
+
+    let (reap, rps): (Reap<String>, Vec<Rp<String>>) = collected.into();
+    drop(rps);
+    assert_eq!(reap.stats().live, 0);
+    let (_, empty) = iter::empty::<u8>().collect::<ReapCollect<_>>().into_parts();
+    assert!(empty.is_empty());
+}
+
+#[test]
+fn test_background_growth() {
+    let reap = Reap::with_capacity(8);
+    assert_eq!(reap.background_growth(), None);
+    reap.set_background_growth(Some(0.5));
+    let mut objects: Vec<_> = (0..4u64).map(|i| reap.allocate(i)).collect();
+    assert!(reap.0.background.borrow().pending.is_none());
+    // The 5th slot is where the next chunk is requested.
+    objects.push(reap.allocate(4));
+    assert_eq!(reap.0.background.borrow().pending.as_ref().map(|p| p.0), Some(16));
+    objects.extend((5..9).map(|i| reap.allocate(i)));
+    assert!(reap.0.background.borrow().pending.is_none());
+    let stats = reap.stats();
+    assert_eq!((stats.chunks, stats.capacity), (2, 24));
+    assert_eq!(objects.iter().map(|rp| **rp).sum::<u64>(), 36);
+
+    // And again for the next one, which is never needed but still freed.
+    objects.extend((0..8).map(|i| reap.allocate(i)));
+    assert!(reap.0.background.borrow().pending.is_some());
+    drop(objects);
+
+    // Enabled late, the request goes out with the next slot.
+    let late = Reap::with_capacity(4);
+    let a = late.allocate(0u8);
+    let b = late.allocate(1);
+    late.set_background_growth(Some(0.0));
+    assert!(late.0.background.borrow().pending.is_none());
+    let c = late.allocate(2);
+    assert!(late.0.background.borrow().pending.is_some());
+    late.set_background_growth(None);
+    let rest: Vec<_> = (0..10).map(|i| late.allocate(i)).collect();
+    assert_eq!(*a + *b + *c + *rest[9], 12);
+}
+
+#[test]
+fn test_background_growth_shared() {
+    use std::thread;
+
+    // Arenas on several threads request chunks from the one worker at once.
+    let threads: Vec<_> = (0..4u64)
+        .map(|t| {
+            thread::spawn(move || {
+                let reap = Reap::with_capacity(8);
+                reap.set_background_growth(Some(0.25));
+                let objects: Vec<_> = (0..1000).map(|i| reap.allocate(t * i)).collect();
+                assert_eq!(reap.stats().chunks, 7);
+                objects.iter().map(|rp| **rp).sum::<u64>()
+            })
+        })
+        .collect();
+    for (t, thread) in threads.into_iter().enumerate() {
+        assert_eq!(thread.join().unwrap(), t as u64 * 499_500);
+    }
+}
+
+#[test]
+#[should_panic(expected = "is not between 0 and 1")]
+fn test_background_growth_threshold() {
+    Reap::<u8>::new().set_background_growth(Some(1.5));
+}
+
+#[test]
+fn test_chunk_cache() {
+    // Other tests may retire and take chunks meanwhile, but not of this odd size.
+    chunk_cache::set_limit(1 << 30);
+    assert_eq!(chunk_cache::limit(), 1 << 30);
+    let first = Reap::<[u8; 3]>::with_capacity(12_345);
+    let size = first.stats().reserved_bytes();
+    let addr = first.0.chunks.borrow()[0].ptr;
+    drop(first);
+    assert!(chunk_cache::retained() >= size);
+
+    let second = Reap::<[u8; 3]>::with_capacity(12_345);
+    assert_eq!(second.0.chunks.borrow()[0].ptr, addr);
+    let objects: Vec<_> = (0..100).map(|i| second.allocate([i; 3])).collect();
+    assert_eq!(objects[99][2], 99);
+    drop((objects, second));
+
+    // A buffer taken over by `adopt_vec` is retired like any other chunk, and serves an arena of
+    // another type just as well.
+    if !cfg!(debug_assertions) && !cfg!(feature = "canary") && !cfg!(feature = "tags") {
+        let adopter = Reap::new();
+        let mut vec = Vec::<u64>::with_capacity(311);
+        vec.extend(0..3);
+        let addr = vec.as_ptr() as *mut u8;
+        drop(adopter.adopt_vec(vec));
+        drop(adopter);
+        let other = Reap::<[u16; 4]>::with_capacity(311);
+        assert_eq!(other.0.chunks.borrow()[0].ptr, addr);
+        let objects: Vec<_> = (0..311).map(|i| other.allocate([i; 4])).collect();
+        assert!(objects.iter().zip(0..).all(|(object, i)| object[3] == i));
+        drop((objects, other));
+    }
+
+    // Lowering the limit frees what doesn't fit.
+    chunk_cache::set_limit(size - 1);
+    assert!(chunk_cache::retained() < size);
+    chunk_cache::set_limit(0);
+    let third = Reap::<[u8; 3]>::with_capacity(12_345);
+    drop(third);
+    chunk_cache::clear();
+    assert_eq!(chunk_cache::limit(), 0);
+}
+
+#[test]
+fn test_inline_freelist() {
+    let reap = Reap::new();
+    let mut objects: Vec<_> = (0..40u64).map(|i| reap.allocate(i)).collect();
+    // A little churn stays within the inline entries.
+    let addrs: Vec<*const u64> = objects[..10].iter().map(|rp| &**rp as *const u64).collect();
+    objects.drain(..10);
+    assert_eq!(reap.stats().free, 10);
+    assert_eq!(reap.0.freelist.borrow().spill.capacity(), 0);
+    for &addr in addrs.iter().rev() {
+        let rp = reap.allocate(0);
+        assert_eq!(&*rp as *const u64, addr);
+        objects.push(rp);
+    }
+
+    // Beyond those the rest spills, and slots are still reused last freed first.
+    let addrs: Vec<*const u64> = objects.iter().map(|rp| &**rp as *const u64).collect();
+    objects.clear();
+    assert_eq!(reap.stats().free, 40);
+    assert!(reap.0.freelist.borrow().spill.capacity() > 0);
+    let again: Vec<_> = (0..40).map(|i| reap.allocate(i)).collect();
+    let mut expected = addrs.clone();
+    expected.reverse();
+    assert_eq!(again.iter().map(|rp| &**rp as *const u64).collect::<Vec<_>>(), expected);
+    assert_eq!(reap.stats().free, 0);
+}
+
+#[test]
+fn test_allocate_near() {
+    fn addr(rp: &Rp<u64>) -> *const u64 {
+        &**rp
+    }
+
+    let reap = Reap::with_capacity(4);
+    let mut first: Vec<_> = (0..4u64).map(|i| reap.allocate(i)).collect();
+    let in_first: Vec<_> = first.iter().map(addr).collect();
+    let mut second: Vec<_> = (4..6).map(|i| reap.allocate(i)).collect();
+
+    // Nothing free in the first chunk, which is full: same as `allocate`.
+    drop(second.pop());
+    let top = reap.0.freelist.borrow().iter().last().cloned().unwrap() as *const u64;
+    let near = reap.allocate_near(&first[0], 10);
+    assert_eq!(addr(&near), top);
+
+    // A hint in the current chunk takes a fresh slot there rather than reusing one elsewhere.
+    let stats = reap.stats();
+    drop(first.pop());
+    let fresh = reap.allocate_near(&second[0], 11);
+    assert!(addr(&fresh) != in_first[3]);
+    assert_eq!(reap.stats().bump_allocations, stats.bump_allocations + 1);
+
+    // A slot in the hint's chunk is preferred over those freed later elsewhere, the others
+    // staying in order.
+    drop(first.remove(2));
+    drop((0..20).map(|i| reap.allocate(i)).collect::<Vec<_>>());
+    let mut free: Vec<_> = reap.0.freelist.borrow().iter().map(|&ptr| ptr as *const u64).collect();
+    let near = reap.allocate_near(&first[0], 12);
+    let i = free.iter().rposition(|ptr| in_first.contains(ptr)).unwrap();
+    assert!(i + 1 < free.len());
+    assert_eq!(addr(&near), free.remove(i));
+    assert_eq!(reap.stats().freelist_hits, stats.freelist_hits + 3);
+    let again: Vec<_> = free.iter().map(|_| reap.allocate(0)).collect();
+    assert!(again.iter().map(addr).eq(free.into_iter().rev()));
+    #[cfg(feature = "verify")]
+    reap.verify();
+
+    // Hints from elsewhere change nothing.
+    let other = Reap::new();
+    let foreign = other.allocate(0u64);
+    assert_eq!(*reap.allocate_near(&foreign, 13), 13);
+    let zsts = Reap::new();
+    let z = zsts.allocate(());
+    zsts.allocate_near(&z, ());
+}
+
+#[test]
+fn test_leak() {
+    struct Config {
+        name: &'static str,
+        parent: Option<&'static Config>,
+    }
+
+    fn build() -> &'static Config {
+        let reap = Reap::new().leak();
+        let root = Rp::leak(reap.allocate(Config {
+            name: "root",
+            parent: None,
+        }));
+        let child = Rp::leak(reap.allocate(Config {
+            name: "child",
+            parent: Some(root),
+        }));
+        // Objects that aren't leaked are freed as usual.
+        drop(reap.allocate(Config {
+            name: "scratch",
+            parent: None,
+        }));
+        assert_eq!((reap.stats().live, reap.stats().free), (2, 1));
+        child
+    }
+
+    let child = build();
+    assert_eq!(child.name, "child");
+    assert_eq!(child.parent.map(|parent| parent.name), Some("root"));
+
+    // Leaking a handle keeps its arena alive, even without `Reap::leak`.
+    let reap = Reap::new();
+    let x = Rp::leak(reap.allocate(String::from("x")));
+    let (weak, id) = (reap.downgrade(), reap.id());
+    drop(reap);
+    x.push('!');
+    assert_eq!(x, "x!");
+    assert_eq!(weak.upgrade().map(|reap| reap.id()), Some(id));
+}
+
+#[test]
+fn test_reentrant_drop() {
+    // Dropping a node drops its children, freeing into the same arena from within `deallocate`.
+    struct Node {
+        #[allow(dead_code)]
+        children: Vec<Rp<Node>>,
+    }
+
+    fn tree(reap: &Reap<Node>, depth: usize) -> Rp<Node> {
+        let children = if depth == 0 {
+            Vec::new()
+        } else {
+            (0..3).map(|_| tree(reap, depth - 1)).collect()
+        };
+        reap.allocate(Node { children })
+    }
+
+    let reap = Reap::new();
+    let root = tree(&reap, 4);
+    assert_eq!(reap.stats().live, 121);
+    drop(root);
+    assert_eq!((reap.stats().live, reap.stats().free), (0, 121));
+
+    // A destructor allocating from, and freeing into, the arena it is being dropped from.
+    struct Phoenix {
+        reap: Reap<Phoenix>,
+        rebirths: Rc<Cell<u32>>,
+    }
+
+    impl Drop for Phoenix {
+        fn drop(&mut self) {
+            if self.rebirths.get() < 3 {
+                self.rebirths.set(self.rebirths.get() + 1);
+                let stats = self.reap.stats();
+                let child = self.reap.allocate(Phoenix {
+                    reap: self.reap.clone(),
+                    rebirths: self.rebirths.clone(),
+                });
+                // The dying object still holds its slot, the new one got another.
+                assert_eq!(self.reap.stats().live, stats.live + 1);
+                drop(child);
+            }
+        }
+    }
+
+    let reap = Reap::new();
+    let rebirths = Rc::new(Cell::new(0));
+    drop(reap.allocate(Phoenix {
+        reap: reap.clone(),
+        rebirths: rebirths.clone(),
+    }));
+    // Each generation took a slot of its own while its parent was still being dropped.
+    assert_eq!(rebirths.get(), 3);
+    assert_eq!((reap.stats().live, reap.stats().free), (0, 4));
+    #[cfg(feature = "verify")]
+    reap.verify();
+}
+
+#[test]
+fn test_panicking_nested_destructor() {
+    // One leaf of a tree panics while the whole tree is being dropped.
+    struct Node {
+        bomb: bool,
+        #[allow(dead_code)]
+        children: Vec<Rp<Node>>,
+    }
+
+    impl Drop for Node {
+        fn drop(&mut self) {
+            if self.bomb {
+                panic!("boom");
+            }
+        }
+    }
+
+    let reap = Reap::new();
+    let leaf = |bomb| {
+        reap.allocate(Node {
+            bomb,
+            children: Vec::new(),
+        })
+    };
+    let children = vec![leaf(false), leaf(true), leaf(false)];
+    let root = reap.allocate(Node {
+        bomb: false,
+        children,
+    });
+    assert!(panic::catch_unwind(AssertUnwindSafe(|| drop(root))).is_err());
+    // Every slot was reclaimed, the siblings after the bomb and the root included.
+    assert_eq!((reap.stats().live, reap.stats().free), (0, 4));
+    #[cfg(feature = "verify")]
+    reap.verify();
+
+    // And the arena carries on as usual.
+    let again: Vec<_> = (0..6).map(|_| leaf(false)).collect();
+    assert_eq!((reap.stats().live, reap.stats().free), (6, 0));
+    drop(again);
+    assert_eq!((reap.stats().live, reap.stats().free), (0, 6));
+}
+
+#[test]
+fn test_tagged_rp() {
+    struct DropCounter(Rc<Cell<u32>>);
+
+    impl Drop for DropCounter {
+        fn drop(&mut self) {
+            self.0.set(self.0.get() + 1);
+        }
+    }
+
+    assert_eq!(TaggedRp::<u8>::tag_bits(), 0);
+    assert_eq!(TaggedRp::<u64>::tag_bits(), 3);
+
+    let reap = Reap::new();
+    let dropped = Rc::new(Cell::new(0));
+    let node = reap.allocate((7u64, DropCounter(dropped.clone())));
+    let addr = &*node as *const (u64, DropCounter);
+    let mut tagged = Rp::with_tag(node, 0b101);
+    assert_eq!(TaggedRp::tag(&tagged), 0b101);
+    assert_eq!(&*tagged as *const (u64, DropCounter), addr);
+    tagged.0 += 1;
+    TaggedRp::set_tag(&mut tagged, 0b010);
+    assert_eq!((tagged.0, TaggedRp::tag(&tagged)), (8, 0b010));
+    assert!(TaggedRp::reap(&tagged).owns(&reap.allocate((0, DropCounter(dropped.clone())))));
+    assert_eq!(dropped.get(), 1);
+
+    // Back to a plain handle, then dropped as a tagged one.
+    let node = TaggedRp::into_rp(tagged);
+    assert_eq!(&*node as *const (u64, DropCounter), addr);
+    drop(Rp::with_tag(node, 1));
+    assert_eq!(dropped.get(), 2);
+    assert_eq!((reap.stats().live, reap.stats().free), (0, 2));
+
+    // Zero-sized types are aligned too.
+    let zsts = Reap::new();
+    let z = Rp::with_tag(zsts.allocate([0u32; 0]), 3);
+    assert_eq!(TaggedRp::tag(&z), 3);
+    assert_eq!(z.len(), 0);
+}
+
+#[test]
+#[should_panic(expected = "doesn't fit in the 2 tag bits")]
+fn test_tagged_rp_overflow() {
+    Rp::with_tag(Reap::new().allocate(0u32), 4);
+}
+
+#[test]
+fn test_growth_observer() {
+    let reap = Reap::<u64>::with_capacity(4);
+    let events = Rc::new(RefCell::new(Vec::new()));
+    let observed = events.clone();
+    let weak = reap.downgrade();
+    reap.set_growth_observer(Some(Box::new(move |growth: Growth| {
+        // The chunk is in place, and the arena usable, by the time the observer hears of it.
+        let reap = weak.upgrade().unwrap();
+        assert_eq!(reap.stats().chunks, growth.chunks);
+        drop(reap.allocate(0));
+        observed.borrow_mut().push(growth);
+    })));
+
+    let objects: Vec<_> = (0..20).map(|i| reap.allocate(i)).collect();
+    let slot_size = reap.stats().slot_size;
+    assert_eq!(*events.borrow(),
+               [Growth {
+                    capacity: 8,
+                    chunks: 2,
+                    total_capacity: 12,
+                    live: 4,
+                    slot_size,
+                },
+                Growth {
+                    capacity: 16,
+                    chunks: 3,
+                    total_capacity: 28,
+                    live: 12,
+                    slot_size,
+                }]);
+    assert_eq!(events.borrow()[1].bytes(), 16 * slot_size);
+    assert_eq!(events.borrow()[1].reserved_bytes(), reap.stats().reserved_bytes());
+
+    // An observer may remove itself.
+    let calls = Rc::new(Cell::new(0));
+    let counted = calls.clone();
+    let weak = reap.downgrade();
+    reap.set_growth_observer(Some(Box::new(move |_| {
+        counted.set(counted.get() + 1);
+        weak.upgrade().unwrap().set_growth_observer(None);
+    })));
+    let more: Vec<_> = (0..80).map(|i| reap.allocate(i)).collect();
+    assert_eq!(calls.get(), 1);
+    assert!(reap.stats().chunks > 4);
+    drop((objects, more));
+}
+
+#[cfg(feature = "failpoints")]
+#[test]
+fn test_failpoints() {
+    use super::failpoint;
+
+    failpoint::fail_nth(3);
+    let results: Vec<_> = (0..4).map(|_| Reap::<u64>::try_with_capacity(8).err()).collect();
+    assert_eq!(results, [None, None, Some(AllocError::OutOfMemory), None]);
+    assert_eq!(failpoint::injected(), 1);
+
+    // Infallible growth panics, leaving the arena as it was.
+    let reap = Reap::with_capacity(2);
+    let objects: Vec<_> = (0..2u64).map(|i| reap.allocate(i)).collect();
+    let stats = reap.stats();
+    failpoint::fail_always();
+    let result = panic::catch_unwind(AssertUnwindSafe(|| reap.allocate(2)));
+    assert!(result.is_err());
+    assert_eq!(reap.stats(), stats);
+    assert_eq!(failpoint::injected(), 2);
+    failpoint::clear();
+    assert_eq!(failpoint::injected(), 0);
+    assert_eq!(*reap.allocate(2), 2);
+    #[cfg(feature = "verify")]
+    reap.verify();
+    drop(objects);
+
+    // The same seed fails the same allocations.
+    let run = |seed| {
+        failpoint::fail_randomly(0.5, seed);
+        let failed: Vec<_> = (0..64).map(|_| Reap::<u64>::try_with_capacity(8).is_err()).collect();
+        failpoint::clear();
+        failed
+    };
+    let failed = run(7);
+    assert_eq!(run(7), failed);
+    assert!(failed.contains(&true) && failed.contains(&false));
+    failpoint::fail_randomly(0.0, 7);
+    assert!(Reap::<u64>::try_with_capacity(8).is_ok());
+    failpoint::fail_randomly(1.0, 7);
+    assert!(Reap::<u64>::try_with_capacity(8).is_err());
+    failpoint::clear();
+}
+
+#[test]
+fn test_allocate_pinned() {
+    // Pending once, and neither `Unpin` nor movable once polled.
+    struct YieldOnce(Cell<bool>, PhantomPinned);
+
+    impl Future for YieldOnce {
+        type Output = ();
+
+        fn poll(self: Pin<&mut Self>, cx: &mut Context) -> Poll<()> {
+            if self.0.replace(true) {
+                Poll::Ready(())
+            } else {
+                cx.waker().wake_by_ref();
+                Poll::Pending
+            }
+        }
+    }
+
+    fn assert_unpin<T: Unpin>(_: &T) {}
+
+    let reap = Reap::new();
+    let mut future = reap.allocate_pinned(YieldOnce(Cell::new(false), PhantomPinned));
+    // The handle can be moved around freely, just not the future.
+    assert_unpin(&future);
+    let addr = &*future as *const YieldOnce;
+    let mut cx = Context::from_waker(Waker::noop());
+    assert_eq!(future.as_mut().poll(&mut cx), Poll::Pending);
+    let mut moved = vec![future].pop().unwrap();
+    assert_eq!(&*moved as *const YieldOnce, addr);
+    assert_eq!(moved.as_mut().poll(&mut cx), Poll::Ready(()));
+    drop(moved);
+    assert_eq!(reap.stats().live, 0);
+
+    // Unpin futures can be polled through a plain handle too.
+    let ready = Reap::new();
+    let mut value = ready.allocate(future::ready(7));
+    assert_eq!(Pin::new(&mut value).poll(&mut cx), Poll::Ready(7));
+    let pinned: Pin<Rp<_>> = ready.allocate(future::ready(8)).into();
+    assert_eq!(Rp::into_box(Pin::into_inner(pinned)).into_inner(), 8);
+}
+
+#[cfg(feature = "callsites")]
+#[test]
+fn test_call_sites() {
+    let reap = Reap::new();
+    let mut objects = Vec::new();
+    for i in 0..3u64 {
+        objects.push(reap.allocate(i));
+        objects.extend(reap.adopt_vec(vec![i; 4]));
+    }
+    let with = line!() + 1;
+    objects.push(reap.allocate_with(|| 0));
+    drop(objects);
+
+    let report = reap.call_site_report();
+    let lines: Vec<_> = report.sites().iter().map(|site| (site.allocations, site.bytes)).collect();
+    let slot_size = reap.stats().slot_size;
+    assert_eq!(lines, [(12, 12 * slot_size), (3, 3 * slot_size), (1, slot_size)]);
+    assert_eq!(report.sites()[2].location.line(), with);
+    assert!(report.sites().iter().all(|site| site.location.file().ends_with("test.rs")));
+    assert_eq!((report.total_allocations(), report.total_bytes()), (16, 16 * slot_size));
+    assert!(report.to_string().starts_with("16 allocation(s)"));
+
+    reap.reset_call_sites();
+    assert!(reap.call_site_report().sites().is_empty());
+}
+
+#[test]
+fn test_rp_debug() {
+    let reap = Reap::with_capacity(2);
+    let objects: Vec<_> = (0..3u32).map(|i| reap.allocate(i)).collect();
+    assert_eq!(format!("{:?}", objects[2]), "2");
+    #[cfg(not(feature = "layout"))]
+    assert_eq!(format!("{:#?}", objects[2]), "2");
+    #[cfg(feature = "layout")]
+    {
+        let expected = format!(concat!("Rp {{\n    value: 2,\n",
+                                       "    arena: {},\n    chunk: 1,\n    slot: 0,\n}}"),
+                               reap.id());
+        assert_eq!(format!("{:#?}", objects[2]), expected);
+        let unit = Reap::new().allocate(());
+        assert!(!format!("{:#?}", unit).contains("chunk"));
+    }
+}
+
+#[test]
+fn test_allocate_lazy() {
+    let reap = Reap::new();
+    let built = Cell::new(0);
+    let build = |i: u64| {
+        let built = &built;
+        move || {
+            built.set(built.get() + 1);
+            vec![i; 4]
+        }
+    };
+    let mut lazy = reap.allocate_lazy(build(1));
+    let untouched = reap.allocate_lazy(build(2));
+    assert_eq!((reap.stats().live, built.get()), (2, 0));
+    assert_eq!(RpLazy::get(&lazy), None);
+    assert_eq!(format!("{:?}", lazy), "RpLazy(<uninit>)");
+
+    lazy.push(1);
+    assert_eq!((lazy.len(), built.get()), (5, 1));
+    assert_eq!(RpLazy::get_mut(&mut lazy).map(|v| v.len()), Some(5));
+    let rp = RpLazy::into_rp(lazy);
+    assert_eq!((&*rp, built.get()), (&vec![1; 5], 1));
+
+    // Never initialized, so just given back.
+    drop(untouched);
+    assert_eq!((reap.stats().live, reap.stats().free, built.get()), (1, 1, 1));
+    drop(rp);
+    assert_eq!(reap.stats().live, 0);
+    assert_eq!(RpLazy::force(&reap.allocate_lazy(build(3))), &[3; 4]);
+}
+
+#[test]
+fn test_allocate_lazy_poisoned() {
+    let reap = Reap::<String>::new();
+    let lazy = reap.allocate_lazy(|| panic!("no value"));
+    assert!(panic::catch_unwind(AssertUnwindSafe(|| lazy.len())).is_err());
+    let err = panic::catch_unwind(AssertUnwindSafe(|| lazy.len())).unwrap_err();
+    assert!(err.downcast_ref::<&str>().unwrap().contains("failed initialization"));
+    assert_eq!(reap.stats().live, 1);
+    drop(lazy);
+    assert_eq!((reap.stats().live, reap.stats().free), (0, 1));
+}
+
+// These only need to compile, and would stop doing so if `Reap` or `Rp` became invariant.
+fn reap_is_covariant<'a>(reap: Reap<&'static str>) -> Reap<&'a str> {
+    reap
+}
+
+fn rp_is_covariant<'a>(rp: Rp<&'static str>) -> Rp<&'a str> {
+    rp
+}
+
+#[test]
+fn test_variance() {
+    let local = String::from("local");
+    let reap = reap_is_covariant(Reap::new());
+    let statics = Reap::new();
+    let rps = [rp_is_covariant(statics.allocate("static")), reap.allocate(&local[..])];
+    assert_eq!((*rps[0], *rps[1]), ("static", "local"));
+}
+
+#[test]
+fn test_binary_tree() {
+    // Inserts `key` as a leaf, keeping the tree in search order.
+    fn insert(tree: &mut BinaryTree<u32>, key: u32) {
+        if tree.insert_root(key).is_ok() {
+            return;
+        }
+        let mut cursor = tree.cursor_mut();
+        loop {
+            let left = key < *cursor.current().unwrap();
+            if !(if left { cursor.move_to_left() } else { cursor.move_to_right() }) {
+                let inserted = if left {
+                    cursor.insert_left(key)
+                } else {
+                    cursor.insert_right(key)
+                };
+                return inserted.unwrap();
+            }
+        }
+    }
+
+    let mut tree = BinaryTree::new();
+    assert_eq!(tree.cursor_mut().remove_current(), None);
+    assert_eq!(tree.cursor_mut().insert_left(0), Err(0));
+    for &key in &[5, 3, 8, 1, 4, 7, 9] {
+        insert(&mut tree, key);
+    }
+    assert_eq!(tree.insert_root(0), Err(0));
+    let keys = |tree: &BinaryTree<u32>| tree.iter().cloned().collect::<Vec<_>>();
+    assert_eq!(keys(&tree), [1, 3, 4, 5, 7, 8, 9]);
+    {
+        let mut cursor = tree.cursor_mut();
+        // 5 ( 3 ( 1 4 ) 8 ) becomes 3 ( 1 5 ( 4 8 ) ).
+        assert!(cursor.rotate_right());
+        assert_eq!((cursor.peek_parent(), cursor.peek_left()), (Some(&3), Some(&4)));
+        assert!(!cursor.is_left_child());
+        assert!(cursor.move_to_parent());
+        assert!(cursor.rotate_left());
+        assert_eq!((cursor.peek_parent(), cursor.peek_right()), (Some(&5), Some(&4)));
+        assert!(cursor.is_left_child());
+        assert!(cursor.move_to_left() && !cursor.rotate_left() && !cursor.rotate_right());
+    }
+    assert_eq!(tree.root(), Some(&5));
+    assert_eq!(keys(&tree), [1, 3, 4, 5, 7, 8, 9]);
+    {
+        // The root has two children, so its successor's node goes in its place.
+        let mut cursor = tree.cursor_mut();
+        assert_eq!(cursor.remove_current(), Some(5));
+        assert_eq!(cursor.current(), Some(&mut 8));
+        assert_eq!(cursor.peek_left(), None);
+        cursor.move_to_root();
+        assert!(cursor.move_to_left() && cursor.move_to_left());
+        assert_eq!(cursor.remove_current(), Some(1));
+        assert_eq!(cursor.current(), Some(&mut 3));
+    }
+    assert_eq!(tree.root(), Some(&7));
+    assert_eq!(keys(&tree), [3, 4, 7, 8, 9]);
+    assert_eq!((tree.len(), tree.iter().len()), (5, 5));
+
+    // Neither degenerate shape makes dropping recurse.
+    let shared = Rc::new(());
+    for &left in &[true, false] {
+        let mut chain = BinaryTree::new();
+        chain.insert_root(shared.clone()).unwrap();
+        let mut cursor = chain.cursor_mut();
+        for _ in 0..100_000 {
+            if left {
+                cursor.insert_left(shared.clone()).unwrap();
+                cursor.move_to_left();
+            } else {
+                cursor.insert_right(shared.clone()).unwrap();
+                cursor.move_to_right();
+            }
+        }
+        assert_eq!(Rc::strong_count(&shared), 100_002);
+        drop(chain);
+        assert_eq!(Rc::strong_count(&shared), 1);
+    }
+}
+
+#[test]
+fn test_dlist_ends() {
+    let mut list = DList::new();
+    for i in 0..4 {
+        list.push_back(i);
+        list.push_front(-i);
+    }
+    assert_eq!(list.len(), 8);
+    assert_eq!(list.front(), Some(&-3));
+    assert_eq!(list.back(), Some(&3));
+    assert_eq!(list.iter().rev().cloned().collect::<Vec<_>>(),
+               vec![3, 2, 1, 0, 0, -1, -2, -3]);
+
+    // Mutable borrows of the nodes through their owners leave the back links usable.
+    *list.front_mut().unwrap() -= 10;
+    *list.back_mut().unwrap() += 10;
+    for x in list.iter_mut().rev().step_by(2) {
+        *x *= 2;
+    }
+    assert_eq!(list.pop_front(), Some(-13));
+    assert_eq!(list.iter().rev().cloned().collect::<Vec<_>>(),
+               vec![26, 2, 2, 0, 0, -1, -4]);
+
+    while list.pop_back().is_some() {}
+    assert!(list.is_empty());
+    assert_eq!(list.front(), None);
+    assert_eq!(list.back(), None);
+}
+
+#[test]
+fn test_dlist_append_and_cursor() {
+    let mut a: DList<_> = (0..5).collect();
+    let mut b: DList<_> = (5..10).collect();
+    a.append(&mut b);
+    assert!(b.is_empty());
+    assert_eq!(a.len(), 10);
+
+    {
+        let mut cursor = a.cursor_back_mut();
+        while let Some(&mut n) = cursor.current() {
+            if n % 3 == 0 {
+                assert_eq!(cursor.remove_current(), Some(n));
+            }
+            cursor.move_prev();
+        }
+        cursor.insert_before(100);
+        cursor.insert_after(-100);
+    }
+    assert_eq!(a.into_iter().collect::<Vec<_>>(),
+               vec![-100, 1, 2, 4, 5, 7, 8, 100]);
+}
+
+#[test]
+fn test_graph() {
+    // Counts its drops in `self.1`.
+    struct Counted(u32, Rc<Cell<u32>>);
+    impl Drop for Counted {
+        fn drop(&mut self) {
+            self.1.set(self.1.get() + 1);
+        }
+    }
+
+    let drops = Rc::new(Cell::new(0));
+    let mut graph = Graph::new();
+    {
+        // 0 ( 1 ( 3 4 ) 2 ( 5 ) ) 6
+        let mut cursor = graph.cursor_mut();
+        assert!(!cursor.move_to_first_child() && !cursor.move_to_parent());
+        cursor.push_child(Counted(0, drops.clone()));
+        cursor.push_child(Counted(6, drops.clone()));
+        assert!(cursor.move_to_first_child());
+        cursor.push_child(Counted(1, drops.clone()));
+        cursor.push_child(Counted(2, drops.clone()));
+        assert!(cursor.move_to_last_child());
+        cursor.push_child(Counted(5, drops.clone()));
+        assert!(cursor.move_to_prev_sibling() && !cursor.move_to_prev_sibling());
+        cursor.push_child(Counted(3, drops.clone()));
+        cursor.push_child(Counted(4, drops.clone()));
+        assert_eq!(cursor.current().map(|node| node.0), Some(1));
+    }
+    let values = |graph: &Graph<Counted>| graph.iter().map(|node| node.0).collect::<Vec<_>>();
+    assert_eq!(values(&graph), [0, 1, 3, 4, 2, 5, 6]);
+    assert_eq!((graph.len(), graph.iter().len()), (7, 7));
+
+    let root = graph.roots().next().unwrap();
+    let leaf = root.descendants().find(|node| node.value().0 == 4).unwrap();
+    assert_eq!(leaf.ancestors().map(|node| node.value().0).collect::<Vec<_>>(), [1, 0]);
+    assert_eq!(leaf.prev_sibling().map(|node| node.value().0), Some(3));
+    assert!(leaf.next_sibling().is_none() && leaf.first_child().is_none());
+    assert_eq!(root.children().map(|node| node.value().0).collect::<Vec<_>>(), [1, 2]);
+    assert_eq!(root.last_child().unwrap().descendants().count(), 2);
+    assert_eq!(graph.roots().map(|node| node.value().0).collect::<Vec<_>>(), [0, 6]);
+
+    // Removing a node takes its subtree along.
+    {
+        let mut cursor = graph.cursor_mut();
+        cursor.move_to_first_child();
+        cursor.move_to_first_child();
+        assert_eq!(cursor.remove_current().map(|node| node.0), Some(1));
+        assert_eq!(cursor.current().map(|node| node.0), Some(0));
+    }
+    assert_eq!(drops.get(), 3);
+    assert_eq!(values(&graph), [0, 2, 5, 6]);
+    assert_eq!(graph.len(), 4);
+    graph.push_root(Counted(7, drops.clone()));
+    assert_eq!(graph.roots().last().unwrap().prev_sibling().map(|node| node.value().0), Some(6));
+    drop(graph);
+    assert_eq!(drops.get(), 8);
+
+    // Dropping doesn't recurse, however deep the tree.
+    let mut deep = Graph::new();
+    {
+        let mut cursor = deep.cursor_mut();
+        for i in 0..100_000 {
+            cursor.push_child(i);
+            cursor.move_to_first_child();
+        }
+    }
+    assert_eq!(deep.iter().last(), Some(&99_999));
+    drop(deep);
+}
+
+#[test]
+fn test_tree() {
+    let values = |tree: &Tree<u32>| tree.iter().cloned().collect::<Vec<_>>();
+    // 0 ( 1 ( 3 4 ) 2 )
+    let mut tree = Tree::new(0);
+    {
+        let mut cursor = tree.cursor_mut();
+        // The cursor can't leave the root.
+        assert!(!cursor.move_to_parent() && !cursor.move_to_next_sibling());
+        assert_eq!(cursor.remove_current(), None);
+        assert!(cursor.detach_current().is_none());
+        cursor.push_child(1);
+        cursor.push_child(2);
+        cursor.move_to_first_child();
+        cursor.push_child(3);
+        cursor.push_child(4);
+    }
+    assert_eq!(values(&tree), [0, 1, 3, 4, 2]);
+
+    // Reparenting moves the whole subtree, and the back links with it.
+    {
+        let mut cursor = tree.cursor_mut();
+        cursor.move_to_first_child();
+        let subtree = cursor.detach_current().unwrap();
+        assert_eq!((subtree.len(), *subtree.root().value()), (3, 1));
+        assert_eq!(cursor.current(), Some(&mut 0));
+        cursor.move_to_first_child();
+        cursor.push_subtree(subtree);
+        cursor.move_to_last_child();
+        cursor.move_to_last_child();
+        assert_eq!(cursor.current(), Some(&mut 4));
+    }
+    assert_eq!(values(&tree), [0, 2, 1, 3, 4]);
+    assert_eq!(tree.len(), 5);
+    let leaf = tree.root().descendants().last().unwrap();
+    assert_eq!(leaf.ancestors().map(|node| *node.value()).collect::<Vec<_>>(), [1, 2, 0]);
+    assert_eq!(leaf.prev_sibling().map(|node| *node.value()), Some(3));
+
+    // A subtree carries over to another tree, or is torn down when dropped.
+    let mut other = {
+        let mut cursor = tree.cursor_mut();
+        cursor.move_to_first_child();
+        cursor.move_to_first_child();
+        let mut subtree = cursor.detach_current().unwrap();
+        *subtree.root_mut() += 10;
+        Tree::from(subtree)
+    };
+    assert_eq!((values(&tree), values(&other)), (vec![0, 2], vec![11, 3, 4]));
+    *other.root_mut() += 10;
+    {
+        let mut cursor = other.cursor_mut();
+        cursor.move_to_first_child();
+        drop(cursor.detach_current());
+        assert_eq!(cursor.remove_children(), 1);
+    }
+    assert_eq!((values(&other), other.len()), (vec![21], 1));
+    assert_eq!(tree.cursor_mut().remove_children(), 1);
+    assert_eq!(format!("{:?}", tree), "Node(0)");
+
+    // Every tree of a graph goes at the ghost node.
+    let mut graph = Graph::new();
+    graph.push_root(1);
+    graph.push_root(2);
+    assert_eq!(graph.cursor_mut().remove_children(), 2);
+    assert!(graph.is_empty() && graph.roots().next().is_none());
+
+    // Tearing down a deep subtree doesn't recurse.
+    let mut deep = Tree::new(0);
+    {
+        let mut cursor = deep.cursor_mut();
+        for i in 1..100_000 {
+            cursor.push_child(i);
+            cursor.move_to_first_child();
+        }
+    }
+    assert_eq!(deep.len(), 100_000);
+    let mut cursor = deep.cursor_mut();
+    cursor.move_to_first_child();
+    assert_eq!(cursor.detach_current().map(|subtree| subtree.len()), Some(99_999));
+    assert_eq!(deep.len(), 1);
+}
+
+#[test]
+fn test_intrusive_list() {
+    struct Item {
+        value: usize,
+        link: Link,
+    }
+    intrusive_adapter!(ItemAdapter = Item { link });
+
+    let reap = Reap::new();
+    let mut list = LinkedList::<ItemAdapter>::new(&reap);
+    let mut addrs = Vec::new();
+    for value in 0..5 {
+        let item = reap.allocate(Item {
+            value,
+            link: Link::new(),
+        });
+        addrs.push(&*item as *const Item);
+        list.push_back(item);
+    }
+    assert_eq!(list.len(), 5);
+
+    // Remove an object from the middle given only a reference to it.
+    let middle = unsafe { list.remove(&*addrs[2]) };
+    assert_eq!(middle.value, 2);
+    assert!(!middle.link.is_linked());
+    list.push_front(middle);
+
+    assert_eq!(list.iter().map(|i| i.value).collect::<Vec<_>>(),
+               vec![2, 0, 1, 3, 4]);
+    assert_eq!(list.pop_back().map(|i| i.value), Some(4));
+    assert_eq!(list.front().map(|i| i.value), Some(2));
+    assert_eq!(list.back().map(|i| i.value), Some(3));
+}
+
+#[test]
+fn test_intrusive_tree() {
+    struct Item {
+        key: u32,
+        value: usize,
+        link: TreeLink,
+    }
+    intrusive_tree_adapter!(ByKey = Item { link } by key: u32);
+
+    let reap = Reap::new();
+    let mut tree = SearchTree::<ByKey>::new(&reap);
+    // Every key twice, in a regular pattern of runs that would unbalance a plain search tree.
+    let keys: Vec<u32> = (0..500).chain((0..500).rev()).map(|i| i * 7 % 1000).collect();
+    for (value, &key) in keys.iter().enumerate() {
+        tree.insert(reap.allocate(Item {
+            key,
+            value,
+            link: TreeLink::new(),
+        }));
+    }
+    assert_eq!(tree.len(), 1000);
+    let mut sorted = keys.clone();
+    sorted.sort();
+    assert_eq!(tree.iter().map(|i| i.key).collect::<Vec<_>>(), sorted);
+    // Equal keys stay in insertion order.
+    let sevens: Vec<_> = tree.iter().filter(|i| i.key == 7).map(|i| i.value).collect();
+    assert_eq!(sevens, [1, 998]);
+
+    assert_eq!(tree.find(&14).map(|i| i.key), Some(14));
+    assert!(tree.find(&4).is_none());
+    let removed = tree.remove(&700).unwrap();
+    assert_eq!(removed.key, 700);
+    assert!(!removed.link.is_linked());
+    assert!(tree.remove(&4).is_none());
+    assert_eq!((tree.first().map(|i| i.key), tree.last().map(|i| i.key)), (Some(0), Some(996)));
+    assert_eq!(tree.pop_last().map(|i| i.key), Some(996));
+    assert_eq!(tree.pop_first().map(|i| i.key), Some(0));
+    tree.insert(removed);
+    assert_eq!(tree.len(), 998);
+    assert!(tree.iter().zip(tree.iter().skip(1)).all(|(a, b)| a.key <= b.key));
+    drop(tree);
+    assert_eq!(reap.stats().live, 0);
+}
+
+#[test]
+fn test_reap_map() {
+    let map = ReapMap::new();
+    let first = map.get_or_insert(String::from("first"), 1);
+    for i in 0..1000 {
+        map.get_or_insert(i.to_string(), i);
+    }
+    // Querying by `&str` goes through `String: Borrow<str>`.
+    assert_eq!(map.get("999"), Some(&999));
+    assert_eq!(*map.get_or_insert_with(String::from("999"), || unreachable!()), 999);
+    assert_eq!(*first, 1);
+    assert_eq!(map.len(), 1001);
+
+    let mut map = map;
+    assert_eq!(map.insert(String::from("first"), 2), Some(1));
+    *map.get_mut("first").unwrap() += 1;
+    assert_eq!(map.remove("first"), Some(3));
+    assert_eq!(map.remove("first"), None);
+    assert!(!map.contains_key("first"));
+    assert_eq!(map.iter().map(|(_, v)| v).sum::<i32>(), (0..1000).sum());
+    // Handing out `&mut V` leaves the keys in the index usable.
+    for (_, value) in map.iter_mut() {
+        *value *= 2;
+    }
+    assert_eq!(map.get("999"), Some(&1998));
+    assert_eq!(map.insert(String::from("999"), 0), Some(1998));
+}
+
+#[test]
+fn test_local_executor() {
+    // Yields `self.0` times before completing, counting completions in `self.1`.
+    struct Countdown(u32, Rc<Cell<u32>>);
+    impl Future for Countdown {
+        type Output = ();
+
+        fn poll(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<()> {
+            if self.0 == 0 {
+                self.1.set(self.1.get() + 1);
+                Poll::Ready(())
+            } else {
+                self.0 -= 1;
+                cx.waker().wake_by_ref();
+                Poll::Pending
+            }
+        }
+    }
+
+    let done = Rc::new(Cell::new(0));
+    let executor = LocalExecutor::new();
+    let short = executor.spawn(Countdown(1, done.clone()));
+    let long = executor.spawn(Countdown(10, done.clone()));
+    let cancelled = executor.spawn(Countdown(10, done.clone()));
+    assert_eq!(executor.len(), 3);
+
+    assert!(executor.cancel(&cancelled));
+    assert!(!executor.cancel(&cancelled));
+    assert_eq!(executor.run_until_stalled(), 2 + 11);
+    assert_eq!(done.get(), 2);
+    assert!(executor.is_finished(&short) && executor.is_finished(&long));
+    assert!(executor.is_empty());
+
+    // Finished slots are reused, and stale handles stay finished.
+    let again = executor.spawn(Countdown(0, done.clone()));
+    assert!(!executor.is_finished(&again));
+    assert!(executor.is_finished(&short));
+    executor.run_until_stalled();
+    assert_eq!(done.get(), 3);
+}
+
+#[test]
+fn test_local_executor_panic() {
+    // Panics on its first poll if `self.0`, and completes otherwise, counting polls in `self.1`.
+    struct Fallible(bool, Rc<Cell<u32>>);
+    impl Future for Fallible {
+        type Output = ();
+
+        fn poll(self: Pin<&mut Self>, _: &mut Context) -> Poll<()> {
+            self.1.set(self.1.get() + 1);
+            assert!(!self.0, "task failed");
+            Poll::Ready(())
+        }
+    }
+
+    let polled = Rc::new(Cell::new(0));
+    let executor = LocalExecutor::new();
+    let failing = executor.spawn(Fallible(true, polled.clone()));
+    let others: Vec<_> = (0..3).map(|_| executor.spawn(Fallible(false, polled.clone()))).collect();
+    let result = panic::catch_unwind(AssertUnwindSafe(|| executor.run_until_stalled()));
+    assert!(result.is_err());
+    assert!(executor.is_finished(&failing));
+    assert_eq!((executor.len(), polled.get()), (3, 1));
+
+    // The tasks woken alongside are still polled, and the failed task's slot is reused.
+    assert_eq!(executor.run_until_stalled(), 3);
+    assert!(others.iter().all(|task| executor.is_finished(task)));
+    assert!(executor.is_empty());
+    let reused = executor.spawn(Fallible(false, polled.clone()));
+    assert!(executor.is_finished(&failing) && !executor.is_finished(&reused));
+
+    // Handles from another executor are never running there.
+    let other = LocalExecutor::<Fallible>::new();
+    assert!(other.is_finished(&reused));
+    assert!(!other.cancel(&reused));
+}
+
+#[test]
+fn test_erased_reap_purge_reentrant() {
+    // Uses the erased arena when the observer holding it is dropped with its sub-arena.
+    struct Reenter(Rc<ErasedReap>);
+
+    impl Drop for Reenter {
+        fn drop(&mut self) {
+            assert!(self.0.contains::<u8>() || self.0.is_empty());
+        }
+    }
+
+    let erased = Rc::new(ErasedReap::new());
+    let reenter = Reenter(erased.clone());
+    erased.reap::<u8>().set_growth_observer(Some(Box::new(move |_| {
+        let _ = &reenter;
+    })));
+    assert_eq!(erased.purge(), 1);
+    assert!(erased.is_empty());
+}
+
+#[test]
+fn test_erased_reap() {
+    #[derive(Debug, PartialEq)]
+    struct Small(u8);
+    #[derive(Debug, PartialEq)]
+    struct Large([u64; 8]);
+
+    let reap = ErasedReap::new();
+    let small: Vec<_> = (0..10).map(|i| reap.allocate(Small(i))).collect();
+    let large = reap.allocate(Large([7; 8]));
+    assert_eq!(*small[9], Small(9));
+    assert_eq!(*large, Large([7; 8]));
+
+    // Values of one type share a single sub-arena.
+    assert_eq!(n_chunks(&reap.reap::<Small>()), 1);
+    assert_eq!(n_chunks(&reap.reap::<Large>()), 1);
+    assert_eq!(n_chunks(&reap.reap::<u32>()), 0);
+
+    assert!(reap.reserved_bytes() >= 10 + mem::size_of::<Large>());
+    let stats = reap.stats();
+    let small_stats = reap.reap::<Small>().stats();
+    let large_stats = reap.reap::<Large>().stats();
+    assert_eq!((stats.live, stats.bump_allocations), (11, 11));
+    assert_eq!(stats.chunks, 2);
+    assert_eq!(stats.capacity, small_stats.capacity + large_stats.capacity);
+    assert_eq!(stats.slot_size, 0);
+
+    // Only the sub-arena with no live handles is purged.
+    assert_eq!(reap.purge(), 1);
+    assert!(!reap.contains::<u32>());
+    assert_eq!(reap.len(), 2);
+
+    // Handles outlive the `ErasedReap` itself.
+    mem::drop(reap);
+    assert_eq!(*small[0], Small(0));
+}
+
+#[test]
+fn test_buffer_reap() {
+    let pool = BufferReap::with_max_retained(4096);
+
+    let mut a = pool.get(100);
+    assert!(a.capacity() >= 100);
+    a.extend_from_slice(&[1; 100]);
+    let b = pool.get(2000);
+    let c = pool.get(3000);
+    assert_eq!(pool.idle(), 0);
+
+    mem::drop(a);
+    mem::drop(b);
+    assert_eq!(pool.idle(), 2);
+    // Retaining `c` as well would exceed the cap, so it is freed.
+    mem::drop(c);
+    assert_eq!(pool.idle(), 2);
+    assert!(pool.retained_bytes() <= 4096);
+
+    // Small requests can be served by the smallest idle buffer that fits.
+    let a = pool.get(10);
+    assert!(a.is_empty() && a.capacity() >= 100);
+    let detached = Buffer::detach(pool.get(1500));
+    assert!(detached.capacity() >= 1500);
+    assert_eq!(pool.idle(), 0);
+
+    mem::drop(a);
+    pool.clear();
+    assert_eq!((pool.idle(), pool.retained_bytes()), (0, 0));
+}
+
 // Before you look at these benchmarks, please be advised that I have absolutely zero experience
 // writing benchmarks, and the following are just my best effort.
 //
@@ -0,0 +1,206 @@
+//! Recording and replaying the allocations of a `Reap`.
+//!
+//! Only available with the `trace` feature. While a trace is being recorded, every allocation and
+//! release in the `Reap` is logged with the slot it used and a timestamp. A `Trace` encodes to a
+//! compact byte string, so one recorded in production can be saved and later replayed against a
+//! fresh arena, e.g. in a benchmark reproducing a fragmentation report.
+//!
+//! # Examples
+//!
+//! ```
+//! use reap::Reap;
+//! use reap::trace::Trace;
+//!
+//! let reap = Reap::new();
+//! reap.start_trace();
+//! let mut objects: Vec<_> = (0..100u64).map(|i| reap.allocate(i)).collect();
+//! objects.retain(|x| **x % 3 == 0);
+//! let trace = reap.finish_trace().unwrap();
+//!
+//! let bytes = trace.encode();
+//! let trace = Trace::decode(&bytes).unwrap();
+//!
+//! let replayed = Reap::new();
+//! let live = trace.replay(&replayed, || 0u64);
+//! assert_eq!(live.len(), objects.len());
+//! assert_eq!(replayed.stats(), reap.stats());
+//! ```
+
+use std::collections::HashMap;
+use std::mem;
+use std::time::Instant;
+
+use super::{Reap, Rp, Slot};
+
+/// The kind of an operation in a `Trace`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Op {
+    /// An object was allocated.
+    Alloc,
+    /// An object was dropped or moved out, and its slot released.
+    Free,
+}
+
+/// A single operation in a `Trace`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct Event {
+    /// What happened.
+    pub op: Op,
+    /// Index of the slot involved, counting through the arena's chunks in allocation order.
+    /// Always 0 for zero-sized types.
+    pub slot: usize,
+    /// Time since the trace was started, in nanoseconds.
+    pub nanos: u64,
+}
+
+/// A recorded sequence of allocations and releases.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Trace {
+    slot_size: usize,
+    events: Vec<Event>,
+}
+
+impl Trace {
+    /// Returns the size in bytes of the slots of the traced arena.
+    #[inline]
+    pub fn slot_size(&self) -> usize {
+        self.slot_size
+    }
+
+    /// Returns the recorded operations, in order.
+    #[inline]
+    pub fn events(&self) -> &[Event] {
+        &self.events
+    }
+
+    /// Encodes the trace as a compact byte string, to be read back by `Trace::decode`.
+    pub fn encode(&self) -> Vec<u8> {
+        let mut out = Vec::with_capacity(4 * self.events.len() + 8);
+        write_varint(&mut out, self.slot_size as u64);
+        let mut last = 0;
+        for event in &self.events {
+            let op = match event.op {
+                Op::Alloc => 0,
+                Op::Free => 1,
+            };
+            write_varint(&mut out, (event.slot as u64) << 1 | op);
+            // Timestamps never decrease, so small deltas encode better than absolute times.
+            write_varint(&mut out, event.nanos - last);
+            last = event.nanos;
+        }
+        out
+    }
+
+    /// Decodes a trace encoded by `Trace::encode`, returning `None` if `bytes` is malformed.
+    pub fn decode(mut bytes: &[u8]) -> Option<Trace> {
+        let slot_size = read_varint(&mut bytes)? as usize;
+        let mut events = Vec::new();
+        let mut nanos = 0u64;
+        while !bytes.is_empty() {
+            let word = read_varint(&mut bytes)?;
+            nanos = nanos.checked_add(read_varint(&mut bytes)?)?;
+            events.push(Event {
+                op: if word & 1 == 0 { Op::Alloc } else { Op::Free },
+                slot: (word >> 1) as usize,
+                nanos,
+            });
+        }
+        Some(Trace {
+            slot_size,
+            events,
+        })
+    }
+
+    /// Re-executes the trace against `reap`, allocating objects made by `make`.
+    ///
+    /// Timestamps are ignored, the operations run back to back. Releases of slots that weren't
+    /// allocated within the trace are skipped. Returns the objects still live at the end of the
+    /// trace, for the caller to inspect the arena before dropping them.
+    pub fn replay<T, F>(&self, reap: &Reap<T>, mut make: F) -> Vec<Rp<T>>
+        where F: FnMut() -> T
+    {
+        // Several objects share slot 0 when `T` is zero-sized.
+        let mut live: HashMap<usize, Vec<Rp<T>>> = HashMap::new();
+        for event in &self.events {
+            match event.op {
+                Op::Alloc => live.entry(event.slot).or_default().push(reap.allocate(make())),
+                Op::Free => {
+                    if let Some(objects) = live.get_mut(&event.slot) {
+                        objects.pop();
+                    }
+                }
+            }
+        }
+        live.into_values().flatten().collect()
+    }
+}
+
+impl<T> Reap<T> {
+    /// Starts recording a trace of this arena's allocations, discarding any trace in progress.
+    pub fn start_trace(&self) {
+        let trace = Trace {
+            slot_size: mem::size_of::<Slot<T>>(),
+            events: Vec::new(),
+        };
+        *self.0.trace.borrow_mut() = Some((Instant::now(), trace));
+    }
+
+    /// Stops recording, returning the trace recorded since `start_trace`, if any.
+    pub fn finish_trace(&self) -> Option<Trace> {
+        self.0.trace.borrow_mut().take().map(|(_, trace)| trace)
+    }
+
+    // Logs `op` on the slot of the object at `ptr`, if a trace is being recorded.
+    #[inline]
+    pub(super) fn record(&self, op: Op, ptr: *mut T) {
+        let mut trace = self.0.trace.borrow_mut();
+        if let Some((start, ref mut trace)) = *trace {
+            let nanos = start.elapsed().as_nanos() as u64;
+            trace.events.push(Event {
+                op,
+                slot: self.slot_index(ptr),
+                nanos,
+            });
+        }
+    }
+
+    // Returns the index of the slot holding the object at `ptr`, counting through all chunks.
+    fn slot_index(&self, ptr: *mut T) -> usize {
+        if mem::size_of::<T>() == 0 {
+            return 0;
+        }
+        let addr = ptr as usize - mem::offset_of!(Slot<T>, value);
+        let mut base = 0;
+        for chunk in self.0.chunks.borrow().iter() {
+            let start = chunk.start::<Slot<T>>() as usize;
+            if start <= addr && addr < chunk.end::<Slot<T>>() as usize {
+                return base + (addr - start) / mem::size_of::<Slot<T>>();
+            }
+            base += chunk.capacity();
+        }
+        unreachable!("reap: traced object at {:p} is not in a chunk", ptr)
+    }
+}
+
+// Appends `value` as an LEB128 varint.
+fn write_varint(out: &mut Vec<u8>, mut value: u64) {
+    while value >= 0x80 {
+        out.push(value as u8 | 0x80);
+        value >>= 7;
+    }
+    out.push(value as u8);
+}
+
+// Reads an LEB128 varint off the front of `bytes`.
+fn read_varint(bytes: &mut &[u8]) -> Option<u64> {
+    let mut value = 0u64;
+    for shift in (0..64).step_by(7) {
+        let (&byte, rest) = bytes.split_first()?;
+        *bytes = rest;
+        value |= u64::from(byte & 0x7f) << shift;
+        if byte & 0x80 == 0 {
+            return Some(value);
+        }
+    }
+    None
+}
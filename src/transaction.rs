@@ -0,0 +1,170 @@
+//! All-or-nothing allocation.
+//!
+//! Speculative work, like an optimization pass that may turn out not to pay off, or a planner
+//! trying out a solution, allocates as it goes and then either keeps everything or nothing. A
+//! `Transaction` collects the objects allocated through it: `commit` hands them over as ordinary
+//! `Rp`s, while `abort`, or dropping the transaction, drops them all and returns their slots to
+//! the arena.
+//!
+//! # Examples
+//!
+//! ```
+//! use reap::Reap;
+//!
+//! let reap = Reap::new();
+//! let mut attempt = reap.begin();
+//! attempt.allocate(String::from("speculative"));
+//! attempt.abort();
+//! assert_eq!(reap.stats().live, 0);
+//!
+//! let mut attempt = reap.begin();
+//! attempt.allocate(String::from("kept")).push('!');
+//! let kept = attempt.commit();
+//! assert_eq!(*kept[0], "kept!");
+//! ```
+
+use std::fmt;
+use std::mem;
+use std::ops::{Index, IndexMut};
+use std::slice;
+
+use super::{Reap, Rp};
+
+/// A set of allocations to be kept or rolled back together, returned by `Reap::begin`.
+pub struct Transaction<T> {
+    reap: Reap<T>,
+    // Everything allocated so far, in order.
+    objects: Vec<Rp<T>>,
+}
+
+impl<T> Reap<T> {
+    /// Starts a transaction of allocations from this `Reap`.
+    #[inline]
+    pub fn begin(&self) -> Transaction<T> {
+        Transaction {
+            reap: self.clone(),
+            objects: Vec::new(),
+        }
+    }
+}
+
+impl<T> Transaction<T> {
+    /// Allocates `object` as part of the transaction, returning a reference to it.
+    ///
+    /// The object is owned by the transaction until it is committed, and accessible by index in
+    /// allocation order meanwhile.
+    #[inline]
+    #[cfg_attr(any(feature = "backtrace", feature = "callsites"), track_caller)]
+    pub fn allocate(&mut self, object: T) -> &mut T {
+        self.objects.push(self.reap.allocate(object));
+        self.objects.last_mut().unwrap()
+    }
+
+    /// Returns the number of objects allocated in the transaction.
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.objects.len()
+    }
+
+    /// Returns `true` if nothing was allocated in the transaction.
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.objects.is_empty()
+    }
+
+    /// Returns a reference to the `index`th object allocated, if any.
+    #[inline]
+    pub fn get(&self, index: usize) -> Option<&T> {
+        self.objects.get(index).map(|rp| &**rp)
+    }
+
+    /// Returns a mutable reference to the `index`th object allocated, if any.
+    #[inline]
+    pub fn get_mut(&mut self, index: usize) -> Option<&mut T> {
+        self.objects.get_mut(index).map(|rp| &mut **rp)
+    }
+
+    /// Returns an iterator over the objects, in allocation order.
+    #[inline]
+    pub fn iter(&self) -> Iter<'_, T> {
+        Iter(self.objects.iter())
+    }
+
+    /// Returns a reference to the `Reap` the transaction allocates from.
+    #[inline]
+    pub fn reap(&self) -> &Reap<T> {
+        &self.reap
+    }
+
+    /// Keeps everything allocated in the transaction, returning the handles in allocation order.
+    #[inline]
+    pub fn commit(mut self) -> Vec<Rp<T>> {
+        mem::take(&mut self.objects)
+    }
+
+    /// Rolls the transaction back, dropping its objects, latest first, and freeing their slots.
+    ///
+    /// This is also what dropping an uncommitted transaction does.
+    #[inline]
+    pub fn abort(self) {}
+}
+
+impl<T> Drop for Transaction<T> {
+    fn drop(&mut self) {
+        // Latest first, as later objects are the likelier to refer to earlier ones.
+        while let Some(rp) = self.objects.pop() {
+            drop(rp);
+        }
+    }
+}
+
+impl<T> Index<usize> for Transaction<T> {
+    type Output = T;
+
+    #[inline]
+    fn index(&self, index: usize) -> &T {
+        &self.objects[index]
+    }
+}
+
+impl<T> IndexMut<usize> for Transaction<T> {
+    #[inline]
+    fn index_mut(&mut self, index: usize) -> &mut T {
+        &mut self.objects[index]
+    }
+}
+
+impl<T> fmt::Debug for Transaction<T>
+    where T: fmt::Debug
+{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_list().entries(self.iter()).finish()
+    }
+}
+
+/// An iterator over the objects of a `Transaction`, returned by `Transaction::iter`.
+#[derive(Clone, Debug)]
+pub struct Iter<'a, T: 'a>(slice::Iter<'a, Rp<T>>);
+
+impl<'a, T> Iterator for Iter<'a, T> {
+    type Item = &'a T;
+
+    #[inline]
+    fn next(&mut self) -> Option<&'a T> {
+        self.0.next().map(|rp| &**rp)
+    }
+
+    #[inline]
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.0.size_hint()
+    }
+}
+
+impl<'a, T> DoubleEndedIterator for Iter<'a, T> {
+    #[inline]
+    fn next_back(&mut self) -> Option<&'a T> {
+        self.0.next_back().map(|rp| &**rp)
+    }
+}
+
+impl<'a, T> ExactSizeIterator for Iter<'a, T> {}
@@ -0,0 +1,126 @@
+//! Retained widget trees.
+//!
+//! A retained-mode UI keeps its widgets in a tree with a single root, the window, and rebuilds
+//! parts of it as the application state changes: containers gain and lose children, widgets move
+//! from one container to another, and closing a panel tears down everything in it. A `Tree` is a
+//! `Graph` kept to one root for this. Each widget owns its children, so removing one drops its
+//! subtree along with it, and the links back to parents and siblings are kept by the tree itself,
+//! never handed out, so a widget can always find its container without any handle to it.
+//!
+//! Reparenting goes through a cursor: `detach_current` cuts a widget out with its children, as a
+//! `Subtree` that owns them, and `push_subtree` adds it under the widget the cursor was moved to
+//! since. A detached subtree that is dropped instead is torn down, as is every child of a
+//! container cleared with `remove_children`.
+//!
+//! # Examples
+//!
+//! ```
+//! use reap::tree::Tree;
+//!
+//! let mut ui = Tree::new("window");
+//! {
+//!     let mut cursor = ui.cursor_mut();
+//!     cursor.push_child("sidebar");
+//!     cursor.push_child("content");
+//!     cursor.move_to_first_child();
+//!     cursor.push_child("button");
+//!
+//!     // Move the button from the sidebar to the content panel.
+//!     cursor.move_to_first_child();
+//!     let button = cursor.detach_current().unwrap();
+//!     cursor.move_to_next_sibling();
+//!     cursor.push_subtree(button);
+//! }
+//! let content = ui.root().last_child().unwrap();
+//! assert_eq!(content.children().map(|w| *w.value()).collect::<Vec<_>>(), ["button"]);
+//! assert_eq!(content.first_child().unwrap().parent().map(|w| *w.value()), Some("content"));
+//!
+//! // Tearing down the content panel takes the button with it.
+//! let mut cursor = ui.cursor_mut();
+//! assert_eq!(cursor.remove_children(), 3);
+//! assert_eq!(ui.len(), 1);
+//! ```
+
+use std::fmt;
+
+use super::graph::{CursorMut, Graph, Iter, NodeRef, Subtree};
+
+/// A tree with a single root, whose nodes live in a `Reap`.
+///
+/// Nodes are added, moved and removed through the `CursorMut` of `cursor_mut`, which starts out
+/// at the root and can't leave it, and read through `NodeRef`s.
+pub struct Tree<T> {
+    // Always holds exactly one root.
+    graph: Graph<T>,
+}
+
+#[allow(clippy::len_without_is_empty)]
+impl<T> Tree<T> {
+    /// Creates a tree of a single node holding `root`.
+    #[inline]
+    pub fn new(root: T) -> Tree<T> {
+        let mut graph = Graph::new();
+        graph.push_root(root);
+        Tree { graph }
+    }
+
+    /// Returns the number of nodes in the tree, the root included.
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.graph.len()
+    }
+
+    /// Returns a reference to the root of the tree.
+    #[inline]
+    pub fn root(&self) -> NodeRef<'_, T> {
+        self.graph.roots().next().expect("tree without a root")
+    }
+
+    /// Returns a mutable reference to the value of the root of the tree.
+    #[inline]
+    pub fn root_mut(&mut self) -> &mut T {
+        self.graph.first_root_mut().expect("tree without a root")
+    }
+
+    /// Returns an iterator over references to the values of all nodes, depth-first with every
+    /// node before its children.
+    #[inline]
+    pub fn iter(&self) -> Iter<'_, T> {
+        self.graph.iter()
+    }
+
+    /// Returns a cursor positioned at the root of the tree.
+    #[inline]
+    pub fn cursor_mut(&mut self) -> CursorMut<'_, T> {
+        self.graph.root_cursor_mut()
+    }
+}
+
+impl<T> From<Subtree<T>> for Tree<T> {
+    /// Makes a tree of its own out of a detached subtree, e.g. to show a panel in a window of its
+    /// own.
+    #[inline]
+    fn from(subtree: Subtree<T>) -> Tree<T> {
+        let mut graph = Graph::new();
+        graph.cursor_mut().push_subtree(subtree);
+        Tree { graph }
+    }
+}
+
+impl<T> fmt::Debug for Tree<T>
+    where T: fmt::Debug
+{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Debug::fmt(&self.root(), f)
+    }
+}
+
+impl<'a, T> IntoIterator for &'a Tree<T> {
+    type Item = &'a T;
+    type IntoIter = Iter<'a, T>;
+
+    #[inline]
+    fn into_iter(self) -> Iter<'a, T> {
+        self.iter()
+    }
+}
@@ -0,0 +1,83 @@
+// Handles to a `Reap` that don't keep it alive, for `Reap::downgrade`.
+
+use std::fmt;
+use std::rc::{self, Rc};
+
+use super::{InnerReap, Reap, Rp};
+
+/// A handle to a `Reap<T>` that doesn't keep the arena alive.
+///
+/// Created with `Reap::downgrade`. The arena, and its memory, go away with its last `Reap` (and
+/// so its last `Rp`, which each hold one), whether or not weak handles remain. Long-lived
+/// registries can remember arenas this way without pinning their memory forever, and allocate
+/// from them while they still exist.
+///
+/// # Examples
+///
+/// ```
+/// use reap::Reap;
+///
+/// let reap = Reap::new();
+/// let weak = reap.downgrade();
+/// assert_eq!(*weak.allocate(1).unwrap(), 1);
+///
+/// drop(reap);
+/// assert!(weak.upgrade().is_none());
+/// assert_eq!(weak.allocate(2).unwrap_err(), 2);
+/// ```
+pub struct WeakReap<T>(rc::Weak<InnerReap<T>>);
+
+impl<T> Reap<T> {
+    /// Creates a weak handle to this `Reap`.
+    #[inline]
+    pub fn downgrade(&self) -> WeakReap<T> {
+        WeakReap(Rc::downgrade(&self.0))
+    }
+}
+
+impl<T> WeakReap<T> {
+    /// Creates a weak handle that doesn't refer to any arena, and never upgrades.
+    #[inline]
+    pub fn new() -> WeakReap<T> {
+        WeakReap(rc::Weak::new())
+    }
+
+    /// Returns a `Reap` handle to the arena, if it still exists.
+    #[inline]
+    pub fn upgrade(&self) -> Option<Reap<T>> {
+        self.0.upgrade().map(Reap)
+    }
+
+    /// Allocates `object` in the arena if it still exists, or returns it back.
+    #[inline]
+    #[cfg_attr(any(feature = "backtrace", feature = "callsites"), track_caller)]
+    pub fn allocate(&self, object: T) -> Result<Rp<T>, T> {
+        match self.upgrade() {
+            Some(reap) => Ok(reap.allocate(object)),
+            None => Err(object),
+        }
+    }
+}
+
+impl<T> Clone for WeakReap<T> {
+    #[inline]
+    fn clone(&self) -> WeakReap<T> {
+        WeakReap(self.0.clone())
+    }
+}
+
+impl<T> Default for WeakReap<T> {
+    #[inline]
+    fn default() -> WeakReap<T> {
+        WeakReap::new()
+    }
+}
+
+impl<T> fmt::Debug for WeakReap<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.upgrade() {
+            Some(reap) => f.debug_tuple("WeakReap").field(&reap.stats()).finish(),
+            None => f.write_str("WeakReap(dropped)"),
+        }
+    }
+}